@@ -0,0 +1,306 @@
+//! Minimal reader for QuickTime / ISO-BMFF atoms.
+//!
+//! Only what movcat needs is implemented: listing the top-level atoms of a
+//! file without loading their payloads, and walking the children of an
+//! atom payload that has already been read into memory.
+
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+
+pub type FourCC = [u8; 4];
+
+/// Largest atom payload read into memory. Header atoms (ftyp, moov) of
+/// even multi-hour recordings are far smaller; a larger size is damage.
+const MAX_PAYLOAD_SIZE: u64 = 1 << 30;
+
+/// Position and size of an atom inside a file.
+#[derive(Debug, Clone, Copy)]
+pub struct AtomHeader {
+    pub kind: FourCC,
+    pub offset: u64,
+    pub header_size: u64,
+    pub size: u64,
+}
+
+impl AtomHeader {
+    pub fn data_offset(&self) -> u64 {
+        self.offset + self.header_size
+    }
+
+    pub fn data_size(&self) -> u64 {
+        self.size - self.header_size
+    }
+}
+
+/// Returns a printable form of a four character code.
+pub fn fourcc_to_string(kind: &FourCC) -> String {
+    kind.iter()
+        .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' })
+        .collect()
+}
+
+/// Lists the top-level atoms of a file, seeking over their payloads.
+pub fn top_level_atoms(file: &mut File) -> Result<Vec<AtomHeader>> {
+    let file_size = file.metadata()?.len();
+    let mut atoms = Vec::new();
+    let mut offset = 0;
+
+    while offset + 8 <= file_size {
+        file.seek(SeekFrom::Start(offset))?;
+        let mut header = [0u8; 8];
+        file.read_exact(&mut header)?;
+
+        let mut size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+        let kind = [header[4], header[5], header[6], header[7]];
+        let mut header_size = 8;
+
+        if size == 1 {
+            let mut large = [0u8; 8];
+            file.read_exact(&mut large)
+                .with_context(|| format!("Truncated atom header at offset {}", offset))?;
+            size = u64::from_be_bytes(large);
+            header_size = 16;
+        } else if size == 0 {
+            // Atom extends to the end of the file
+            size = file_size - offset;
+        }
+
+        if size < header_size || offset.checked_add(size).is_none_or(|end| end > file_size) {
+            anyhow::bail!(
+                "Invalid size for '{}' atom at offset {} (size {}, file size {})",
+                fourcc_to_string(&kind), offset, size, file_size
+            );
+        }
+
+        atoms.push(AtomHeader { kind, offset, header_size, size });
+        offset += size;
+    }
+
+    Ok(atoms)
+}
+
+/// Reads the payload of an atom (everything after its header).
+pub fn read_payload(file: &mut File, atom: &AtomHeader) -> Result<Vec<u8>> {
+    if atom.data_size() > MAX_PAYLOAD_SIZE {
+        anyhow::bail!("'{}' atom at offset {} is too large to read ({} bytes); the file is probably damaged",
+            fourcc_to_string(&atom.kind), atom.offset, atom.data_size());
+    }
+    let mut data = vec![0u8; atom.data_size() as usize];
+    file.seek(SeekFrom::Start(atom.data_offset()))?;
+    file.read_exact(&mut data)
+        .with_context(|| format!("Failed to read '{}' atom", fourcc_to_string(&atom.kind)))?;
+    Ok(data)
+}
+
+/// Splits an in-memory payload into its child atoms.
+pub fn children(data: &[u8]) -> Result<Vec<(FourCC, &[u8])>> {
+    let mut atoms = Vec::new();
+    let mut reader = ByteReader::new(data);
+
+    while reader.remaining() >= 8 {
+        let start = reader.position();
+        let mut size = reader.u32()? as u64;
+        let kind = reader.fourcc()?;
+
+        if size == 1 {
+            size = reader.u64()?;
+        } else if size == 0 {
+            size = (data.len() - start) as u64;
+        }
+
+        let header_size = (reader.position() - start) as u64;
+        if size < header_size || (start as u64).checked_add(size).is_none_or(|end| end > data.len() as u64) {
+            anyhow::bail!(
+                "Invalid size for '{}' atom (size {}, {} bytes available)",
+                fourcc_to_string(&kind), size, data.len() - start
+            );
+        }
+
+        let end = start + size as usize;
+        atoms.push((kind, &data[reader.position()..end]));
+        reader.seek(end);
+    }
+
+    Ok(atoms)
+}
+
+/// Returns the payload of the first child atom of the given kind.
+pub fn find_child<'a>(data: &'a [u8], kind: &FourCC) -> Result<Option<&'a [u8]>> {
+    Ok(children(data)?
+        .into_iter()
+        .find(|(k, _)| k == kind)
+        .map(|(_, payload)| payload))
+}
+
+/// Follows a path of nested atom kinds, e.g. `[b"mdia", b"minf", b"stbl"]`.
+pub fn find_path<'a>(data: &'a [u8], path: &[&FourCC]) -> Result<Option<&'a [u8]>> {
+    let mut current = data;
+    for kind in path {
+        match find_child(current, kind)? {
+            Some(payload) => current = payload,
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(current))
+}
+
+/// Big-endian cursor over an atom payload.
+pub struct ByteReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        ByteReader { data, pos: 0 }
+    }
+
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.data.len() - self.pos
+    }
+
+    pub fn seek(&mut self, pos: usize) {
+        self.pos = pos.min(self.data.len());
+    }
+
+    pub fn skip(&mut self, n: usize) -> Result<()> {
+        self.bytes(n).map(|_| ())
+    }
+
+    pub fn bytes(&mut self, n: usize) -> Result<&'a [u8]> {
+        if self.remaining() < n {
+            anyhow::bail!("Unexpected end of atom data");
+        }
+        let slice = &self.data[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(slice)
+    }
+
+    pub fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    pub fn u32(&mut self) -> Result<u32> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    pub fn u64(&mut self) -> Result<u64> {
+        let b = self.bytes(8)?;
+        Ok(u64::from_be_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]))
+    }
+
+    pub fn fourcc(&mut self) -> Result<FourCC> {
+        let b = self.bytes(4)?;
+        Ok([b[0], b[1], b[2], b[3]])
+    }
+
+    /// Reads the version byte and flags of a full atom.
+    pub fn version_and_flags(&mut self) -> Result<(u8, u32)> {
+        let value = self.u32()?;
+        Ok(((value >> 24) as u8, value & 0x00ff_ffff))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    /// Builds an atom with a 32-bit size header.
+    pub fn atom(kind: &FourCC, payload: &[u8]) -> Vec<u8> {
+        let mut data = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(kind);
+        data.extend_from_slice(payload);
+        data
+    }
+
+    #[test]
+    fn test_children_basic() {
+        let mut data = atom(b"free", &[1, 2, 3]);
+        data.extend(atom(b"moov", &atom(b"mvhd", &[0; 4])));
+        let atoms = children(&data).unwrap();
+        assert_eq!(atoms.len(), 2);
+        assert_eq!(&atoms[0].0, b"free");
+        assert_eq!(atoms[0].1, &[1, 2, 3]);
+        assert_eq!(&atoms[1].0, b"moov");
+    }
+
+    #[test]
+    fn test_children_large_size() {
+        let mut data = 1u32.to_be_bytes().to_vec();
+        data.extend_from_slice(b"mdat");
+        data.extend_from_slice(&20u64.to_be_bytes());
+        data.extend_from_slice(&[9, 9, 9, 9]);
+        let atoms = children(&data).unwrap();
+        assert_eq!(atoms.len(), 1);
+        assert_eq!(atoms[0].1, &[9, 9, 9, 9]);
+    }
+
+    #[test]
+    fn test_children_invalid_size() {
+        let mut data = atom(b"free", &[0; 8]);
+        data[3] = 200;
+        assert!(children(&data).is_err());
+    }
+
+    #[test]
+    fn test_children_overflowing_size() {
+        // The size wraps around when added to the atom's start
+        let mut data = atom(b"skip", &[]);
+        data.extend_from_slice(&1u32.to_be_bytes());
+        data.extend_from_slice(b"free");
+        data.extend_from_slice(&(u64::MAX - 4).to_be_bytes());
+        assert!(children(&data).is_err());
+    }
+
+    #[test]
+    fn test_find_path() {
+        let stbl = atom(b"stbl", &atom(b"stsz", &[7; 4]));
+        let data = atom(b"mdia", &atom(b"minf", &stbl));
+        let found = find_path(&data, &[b"mdia", b"minf", b"stbl", b"stsz"]).unwrap();
+        assert_eq!(found, Some(&[7u8; 4][..]));
+        assert!(find_path(&data, &[b"mdia", b"hdlr"]).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_top_level_atoms_truncated() {
+        let path = std::env::temp_dir().join("movcat_test_truncated.mov");
+        let mut data = atom(b"ftyp", b"qt  ");
+        data.extend(atom(b"mdat", &[0; 16]));
+        data.truncate(data.len() - 4);
+        std::fs::write(&path, &data).unwrap();
+
+        let mut file = File::open(&path).unwrap();
+        assert!(top_level_atoms(&mut file).is_err());
+
+        // A 64-bit size that wraps around when added to the offset
+        let mut data = atom(b"ftyp", b"qt  ");
+        data.extend_from_slice(&1u32.to_be_bytes());
+        data.extend_from_slice(b"mdat");
+        data.extend_from_slice(&(u64::MAX - 4).to_be_bytes());
+        std::fs::write(&path, &data).unwrap();
+        let mut file = File::open(&path).unwrap();
+        assert!(top_level_atoms(&mut file).is_err());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_read_payload_too_large() {
+        let path = std::env::temp_dir().join("movcat_test_payload_size.mov");
+        std::fs::write(&path, atom(b"moov", &[0; 8])).unwrap();
+        let mut file = File::open(&path).unwrap();
+        let header = AtomHeader { kind: *b"moov", offset: 0, header_size: 8, size: MAX_PAYLOAD_SIZE + 9 };
+        assert!(read_payload(&mut file, &header).unwrap_err().to_string().contains("too large"));
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,114 @@
+//! Audio sample counts for broadcast QC.
+//!
+//! QC tools compare the number of decoded audio samples with the video
+//! duration at the nominal sample rate and flag outputs where they drift
+//! apart. A stream-copied merge must contain exactly the samples of its
+//! inputs, so movcat reports the counts per input and checks the output
+//! against their sum and against its own video duration.
+
+use crate::movie::{Movie, Track};
+use crate::MovInfo;
+
+/// Sample rate and decoded sample count of the first audio track.
+pub fn first_track_samples(tracks: &[Track]) -> Option<(u32, u64)> {
+    let track = tracks.iter().find(|t| t.is_audio())?;
+    Some((track.sample_rate, track.audio_sample_count()?))
+}
+
+#[derive(Debug, PartialEq)]
+pub struct SampleCheck {
+    pub sample_rate: u32,
+    /// Samples in the output.
+    pub output: u64,
+    /// Sum over the inputs; `None` when inputs are trimmed or some input
+    /// has no audio at the output's rate, so no exact sum is expected.
+    pub inputs_total: Option<u64>,
+    /// Duration of the output's first video track in audio samples, and
+    /// one video frame in audio samples.
+    pub video: Option<(u64, u64)>,
+}
+
+/// Checks the output's audio against the inputs; `None` without audio.
+pub fn check(infos: &[MovInfo], output: &Movie) -> Option<SampleCheck> {
+    let (sample_rate, samples) = first_track_samples(&output.tracks)?;
+
+    let trimmed = infos.iter().any(|info| info.trim_start.is_some() || info.trim_end.is_some());
+    let inputs_total = if trimmed {
+        None
+    } else {
+        infos.iter()
+            .map(|info| first_track_samples(&info.tracks).filter(|&(rate, _)| rate == sample_rate).map(|(_, n)| n))
+            .sum::<Option<u64>>()
+    };
+
+    let video = output.tracks.iter()
+        .find(|t| t.is_video() && t.timescale > 0 && t.sample_count > 0)
+        .map(|t| {
+            let to_samples = |ticks: u64| (ticks as u128 * sample_rate as u128 / t.timescale as u128) as u64;
+            (to_samples(t.media_duration), to_samples(t.media_duration / t.sample_count as u64))
+        });
+
+    Some(SampleCheck { sample_rate, output: samples, inputs_total, video })
+}
+
+impl SampleCheck {
+    pub fn print(&self) {
+        println!("Audio samples: {} at {} Hz", self.output, self.sample_rate);
+        match self.inputs_total {
+            Some(total) if total == self.output => println!("  Sum of inputs: {} (matches)", total),
+            Some(total) => println!("Warning: Output has {} audio samples, but the inputs have {} ({:+})",
+                self.output, total, self.output as i64 - total as i64),
+            None => println!("  Sum of inputs: not checked (trimmed inputs or differing sample rates)"),
+        }
+        if let Some((video_samples, frame_samples)) = self.video {
+            let difference = self.output as i64 - video_samples as i64;
+            let milliseconds = difference as f64 * 1000.0 / self.sample_rate as f64;
+            if difference.unsigned_abs() > frame_samples {
+                println!("Warning: Audio and video durations differ by {:+} samples ({:+.1} ms), more than one video frame",
+                    difference, milliseconds);
+            } else {
+                println!("  Video duration: {} samples (audio {:+}, {:+.1} ms)", video_samples, difference, milliseconds);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::movie::tests::{movie_file, write_temp, TestTrack};
+
+    /// 25 fps video and 48 kHz audio with 512-sample packets.
+    fn file(name: &str, video_frames: u32, audio_samples: u32) -> std::path::PathBuf {
+        write_temp(name, &movie_file(None, &[
+            TestTrack { samples: video_frames, timescale: 12800, media_duration: video_frames * 512,
+                ..TestTrack::new(1, b"vide") }.build(),
+            TestTrack { samples: audio_samples / 512, timescale: 48000, media_duration: audio_samples,
+                sample_rate: 48000, ..TestTrack::new(2, b"soun") }.build(),
+        ]))
+    }
+
+    #[test]
+    fn test_check_sample_counts() {
+        let mut infos = Vec::new();
+        for (name, samples) in [("movcat_test_audio_1.mov", 48128), ("movcat_test_audio_2.mov", 47616)] {
+            let path = file(name, 25, samples);
+            infos.push(crate::analyze_mov_file(&path).unwrap());
+            let _ = std::fs::remove_file(&path);
+        }
+        assert_eq!(first_track_samples(&infos[0].tracks), Some((48000, 48128)));
+
+        let path = file("movcat_test_audio_out.mov", 50, 95744);
+        let output = crate::movie::read_movie(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        let result = check(&infos, &output).unwrap();
+        assert_eq!(result.output, 95744);
+        assert_eq!(result.inputs_total, Some(95744));
+        // 2 s of video: 96000 samples, one frame is 1920
+        assert_eq!(result.video, Some((96000, 1920)));
+
+        infos[1].trim_end = Some(0.5);
+        assert_eq!(check(&infos, &output).unwrap().inputs_total, None);
+    }
+}
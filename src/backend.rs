@@ -0,0 +1,259 @@
+//! Concatenation backends and their capability probes.
+//!
+//! Before anything is written, the chosen backend is probed against what
+//! the input set actually needs (codecs, trims, timecode tracks), so an
+//! unsupported input fails before the copy and limitations are reported.
+//! FFmpeg is the only real backend, so there is no automatic choice yet.
+
+use crate::atoms::{self, FourCC};
+use crate::{movie, process, profile, stereo, MovInfo};
+use clap::ValueEnum;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum BackendChoice {
+    /// Stream copy with FFmpeg's concat demuxer
+    Ffmpeg,
+    /// Writes a stub output and records the plan, for end-to-end tests
+    #[value(hide = true)]
+    Fake,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Ffmpeg,
+    Fake,
+}
+
+impl Backend {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Backend::Ffmpeg => "ffmpeg",
+            Backend::Fake => "fake",
+        }
+    }
+}
+
+/// Features of the input set a backend has to support.
+#[derive(Debug, Default)]
+pub struct Requirements {
+    pub codecs: Vec<FourCC>,
+    pub trims: bool,
+    pub timecode: bool,
+    pub multiview: bool,
+}
+
+impl Requirements {
+    pub fn from_inputs(infos: &[MovInfo]) -> Requirements {
+        let mut requirements = Requirements {
+            trims: infos.iter().any(|info| info.trim_start.is_some() || info.trim_end.is_some()),
+            multiview: infos.iter().any(|info| stereo::layout(info) == stereo::StereoLayout::Multiview),
+            ..Requirements::default()
+        };
+
+        for track in infos.iter().flat_map(|info| &info.tracks) {
+            if &track.handler == b"tmcd" {
+                requirements.timecode = true;
+            } else if (track.is_video() || track.is_audio()) && !requirements.codecs.contains(&track.codec) {
+                requirements.codecs.push(track.codec);
+            }
+        }
+
+        requirements
+    }
+}
+
+/// Result of probing one backend against the requirements.
+#[derive(Debug)]
+pub struct Probe {
+    pub backend: Backend,
+    /// Reasons the backend cannot handle the inputs at all.
+    pub blockers: Vec<String>,
+    /// Limitations that still allow the backend to be used.
+    pub caveats: Vec<String>,
+}
+
+impl Probe {
+    pub fn is_usable(&self) -> bool {
+        self.blockers.is_empty()
+    }
+}
+
+pub fn probe(backend: Backend, requirements: &Requirements) -> Probe {
+    match backend {
+        Backend::Ffmpeg => probe_ffmpeg(requirements),
+        // Handles anything, since it never reads the media data
+        Backend::Fake => Probe { backend, blockers: Vec::new(), caveats: Vec::new() },
+    }
+}
+
+fn probe_ffmpeg(requirements: &Requirements) -> Probe {
+    let mut probe = Probe { backend: Backend::Ffmpeg, blockers: Vec::new(), caveats: Vec::new() };
+
+    let mut cmd = match process::command("ffmpeg") {
+        Ok(cmd) => cmd,
+        Err(e) => {
+            probe.blockers.push(e.to_string());
+            return probe;
+        }
+    };
+    let codecs_output = {
+        let _span = profile::span("ffmpeg -codecs");
+        cmd.arg("-hide_banner").arg("-codecs").output()
+    };
+    let known_codecs = match codecs_output {
+        Ok(output) if output.status.success() => {
+            parse_ffmpeg_codecs(&String::from_utf8_lossy(&output.stdout))
+        }
+        _ => {
+            probe.blockers.push("ffmpeg was not found in PATH".to_string());
+            return probe;
+        }
+    };
+
+    check_ffmpeg_support(requirements, &known_codecs, &mut probe);
+    probe
+}
+
+fn check_ffmpeg_support(requirements: &Requirements, known_codecs: &[String], probe: &mut Probe) {
+    for codec in &requirements.codecs {
+        match movie::codec_name(codec) {
+            Some(name) if !known_codecs.iter().any(|known| known == name) => {
+                probe.blockers.push(format!("this FFmpeg build does not support codec {} ('{}')",
+                    name, atoms::fourcc_to_string(codec)));
+            }
+            Some(_) => {}
+            None => {
+                probe.caveats.push(format!("codec '{}' is not recognized and may not be copyable",
+                    atoms::fourcc_to_string(codec)));
+            }
+        }
+    }
+
+    if requirements.timecode {
+        probe.caveats.push("timecode (tmcd) tracks are not carried over to the output".to_string());
+    }
+    if requirements.multiview {
+        probe.caveats.push("the MV-HEVC multiview description (vexu) is not written; the output may play as 2D".to_string());
+    }
+    if requirements.trims {
+        probe.caveats.push("trims snap to keyframes when stream copying".to_string());
+    }
+}
+
+/// Extracts codec names from the output of `ffmpeg -codecs`.
+fn parse_ffmpeg_codecs(output: &str) -> Vec<String> {
+    output.lines()
+        .skip_while(|line| !line.trim_start().starts_with("---"))
+        .skip(1)
+        .filter_map(|line| line.split_whitespace().nth(1))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Backends considered for a `--backend` choice, in order of preference.
+pub fn candidates(choice: BackendChoice) -> Vec<Backend> {
+    match choice {
+        BackendChoice::Ffmpeg => vec![Backend::Ffmpeg],
+        BackendChoice::Fake => vec![Backend::Fake],
+    }
+}
+
+/// Probes the candidate backends and returns the best usable one.
+///
+/// A usable backend with fewer caveats wins; ties go to the earlier
+/// candidate.
+pub fn select(choice: BackendChoice, requirements: &Requirements, verbose: bool) -> anyhow::Result<Backend> {
+    let probes: Vec<Probe> = candidates(choice).iter().map(|&backend| probe(backend, requirements)).collect();
+
+    if verbose {
+        println!("Backend selection:");
+        for probe in &probes {
+            let status = if probe.is_usable() { "usable" } else { "not usable" };
+            println!("  {}: {}", probe.backend.name(), status);
+            for reason in probe.blockers.iter().chain(&probe.caveats) {
+                println!("    - {}", reason);
+            }
+        }
+    }
+
+    let Some(chosen) = probes.iter()
+        .filter(|probe| probe.is_usable())
+        .min_by_key(|probe| probe.caveats.len())
+    else {
+        let reasons: Vec<String> = probes.iter()
+            .flat_map(|probe| probe.blockers.iter().map(move |b| format!("{}: {}", probe.backend.name(), b)))
+            .collect();
+        anyhow::bail!(
+            "No backend can concatenate these inputs:\n  {}\n\
+            FFmpeg is required for mov concatenation. Please install FFmpeg:\n\
+            - macOS: brew install ffmpeg\n\
+            - Ubuntu/Debian: sudo apt install ffmpeg\n\
+            - Windows: Download from https://ffmpeg.org/download.html",
+            reasons.join("\n  ")
+        );
+    };
+
+    if verbose {
+        println!("  Using {} backend", chosen.backend.name());
+        println!();
+    }
+    for caveat in &chosen.caveats {
+        println!("Warning: {}", caveat);
+    }
+
+    Ok(chosen.backend)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CODECS_OUTPUT: &str = "Codecs:
+ D..... = Decoding supported
+ -------
+ DEV.LS h264                 H.264 / AVC / MPEG-4 AVC / MPEG-4 part 10
+ DEA.L. aac                  AAC (Advanced Audio Coding)
+ DEVIL. prores               Apple ProRes
+";
+
+    #[test]
+    fn test_parse_ffmpeg_codecs() {
+        assert_eq!(parse_ffmpeg_codecs(CODECS_OUTPUT), vec!["h264", "aac", "prores"]);
+        assert!(parse_ffmpeg_codecs("").is_empty());
+    }
+
+    #[test]
+    fn test_check_ffmpeg_support() {
+        let known = parse_ffmpeg_codecs(CODECS_OUTPUT);
+        let requirements = Requirements {
+            codecs: vec![*b"avc1", *b"mp4a", *b"hvc1", *b"xyz1"],
+            timecode: true,
+            ..Requirements::default()
+        };
+        let mut probe = Probe { backend: Backend::Ffmpeg, blockers: Vec::new(), caveats: Vec::new() };
+        check_ffmpeg_support(&requirements, &known, &mut probe);
+
+        assert_eq!(probe.blockers.len(), 1);
+        assert!(probe.blockers[0].contains("hevc"));
+        assert_eq!(probe.caveats.len(), 2);
+        assert!(!probe.is_usable());
+    }
+
+    #[test]
+    fn test_requirements_from_inputs() {
+        use crate::movie::tests::{movie_file, trak, write_temp};
+
+        let data = movie_file(Some(b"qt  "), &[
+            trak(1, b"vide", 1, 0),
+            trak(2, b"soun", 1, 0),
+            trak(3, b"tmcd", 1, 4),
+        ]);
+        let path = write_temp("movcat_test_requirements.mov", &data);
+        let info = crate::analyze_mov_file(&path).unwrap();
+        let requirements = Requirements::from_inputs(&[info]);
+        assert_eq!(requirements.codecs, vec![*b"avc1", *b"mp4a"]);
+        assert!(requirements.timecode);
+        assert!(!requirements.trims);
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,449 @@
+//! Decoding of codec configuration records (avcC, hvcC, esds).
+//!
+//! A stream copy keeps the sample description of the first input, so a
+//! later input whose configuration differs in a significant way (other
+//! parameter sets, another AAC object type, ...) decodes incorrectly after
+//! the join. The records are decoded into named fields so that differences
+//! can be shown field by field and judged harmless or breaking.
+
+use crate::atoms::{self, ByteReader, FourCC};
+use anyhow::Result;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    pub name: String,
+    pub value: String,
+    /// Whether a different value breaks stream-copy concatenation.
+    pub significant: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodecConfig {
+    /// Kind of the configuration record, e.g. `avcC`.
+    pub kind: FourCC,
+    pub fields: Vec<Field>,
+}
+
+impl CodecConfig {
+    fn new(kind: &FourCC) -> Self {
+        CodecConfig { kind: *kind, fields: Vec::new() }
+    }
+
+    fn push(&mut self, name: impl Into<String>, value: impl Into<String>, significant: bool) {
+        self.fields.push(Field { name: name.into(), value: value.into(), significant });
+    }
+}
+
+/// Returns the child atoms of a sample description entry (including its
+/// size and format header).
+pub fn sample_entry_children<'a>(handler: &FourCC, entry: &'a [u8]) -> Option<Vec<(FourCC, &'a [u8])>> {
+    // Sample entry fields before the child atoms
+    let children_offset = match handler {
+        b"vide" | b"pict" => 86,
+        b"soun" => {
+            // QuickTime sound descriptions grow with their version
+            let version = entry.get(16..18).map(|b| u16::from_be_bytes([b[0], b[1]]))?;
+            match version {
+                1 => 52,
+                2 => 72,
+                _ => 36,
+            }
+        }
+        _ => return None,
+    };
+    atoms::children(entry.get(children_offset..)?).ok()
+}
+
+/// Decodes the configuration record of a sample description entry
+/// (including its size and format header). Unknown or malformed records
+/// yield `None`; they only limit what can be reported.
+pub fn parse_sample_entry(handler: &FourCC, entry: &[u8]) -> Option<CodecConfig> {
+    let format = ByteReader::new(entry.get(4..8)?).fourcc().ok()?;
+    let children = sample_entry_children(handler, entry)?;
+
+    // Parameter sets carried in the stream itself (avc3, hev1) replace the
+    // ones in the sample description, so those may differ safely
+    let in_band = matches!(&format, b"avc3" | b"hev1");
+
+    for (kind, payload) in children {
+        let parsed = match &kind {
+            b"avcC" => parse_avcc(payload, !in_band),
+            b"hvcC" => parse_hvcc(payload, !in_band),
+            b"esds" => parse_esds(payload),
+            b"wave" => match atoms::find_child(payload, b"esds") {
+                Ok(Some(esds)) => parse_esds(esds),
+                _ => continue,
+            },
+            _ => continue,
+        };
+        return parsed.ok();
+    }
+    None
+}
+
+fn parse_avcc(data: &[u8], parameter_sets_significant: bool) -> Result<CodecConfig> {
+    let mut config = CodecConfig::new(b"avcC");
+    let mut reader = ByteReader::new(data);
+    reader.skip(1)?;
+    let profile = reader.u8()?;
+    let compatibility = reader.u8()?;
+    let level = reader.u8()?;
+    config.push("profile", format!("{} ({})", h264_profile_name(profile), profile), true);
+    config.push("constraint flags", format!("0x{:02x}", compatibility), false);
+    config.push("level", format!("{}.{}", level / 10, level % 10), true);
+    config.push("NAL length size", format!("{} bytes", (reader.u8()? & 0x03) + 1), true);
+
+    let sps_count = reader.u8()? & 0x1f;
+    push_parameter_sets(&mut config, &mut reader, "SPS", sps_count as usize, parameter_sets_significant)?;
+    let pps_count = reader.u8()?;
+    push_parameter_sets(&mut config, &mut reader, "PPS", pps_count as usize, parameter_sets_significant)?;
+
+    // High profiles append chroma format and bit depths
+    if matches!(profile, 100 | 110 | 122 | 144 | 244) && reader.remaining() >= 3 {
+        config.push("chroma format", chroma_format_name(reader.u8()? & 0x03), true);
+        config.push("luma bit depth", ((reader.u8()? & 0x07) + 8).to_string(), true);
+        config.push("chroma bit depth", ((reader.u8()? & 0x07) + 8).to_string(), true);
+    }
+    Ok(config)
+}
+
+fn parse_hvcc(data: &[u8], parameter_sets_significant: bool) -> Result<CodecConfig> {
+    let mut config = CodecConfig::new(b"hvcC");
+    let mut reader = ByteReader::new(data);
+    reader.skip(1)?;
+    let profile_byte = reader.u8()?;
+    let profile = profile_byte & 0x1f;
+    let tier = if profile_byte & 0x20 != 0 { "High" } else { "Main" };
+    reader.skip(4 + 6)?;
+    let level = reader.u8()?;
+    config.push("profile", format!("{} ({})", hevc_profile_name(profile), profile), true);
+    config.push("tier", tier, true);
+    config.push("level", format!("{}.{}", level / 30, level % 30 / 3), true);
+    reader.skip(2 + 1)?;
+    config.push("chroma format", chroma_format_name(reader.u8()? & 0x03), true);
+    config.push("luma bit depth", ((reader.u8()? & 0x07) + 8).to_string(), true);
+    config.push("chroma bit depth", ((reader.u8()? & 0x07) + 8).to_string(), true);
+    reader.skip(2)?;
+    config.push("NAL length size", format!("{} bytes", (reader.u8()? & 0x03) + 1), true);
+
+    for _ in 0..reader.u8()? {
+        let name = match reader.u8()? & 0x3f {
+            32 => "VPS",
+            33 => "SPS",
+            34 => "PPS",
+            _ => "SEI",
+        };
+        let count = reader.u16()?;
+        push_parameter_sets(&mut config, &mut reader, name, count as usize,
+            parameter_sets_significant && name != "SEI")?;
+    }
+    Ok(config)
+}
+
+/// Adds parameter sets as size and hash, which is enough to tell whether
+/// two are identical.
+fn push_parameter_sets(config: &mut CodecConfig, reader: &mut ByteReader, name: &str, count: usize,
+    significant: bool) -> Result<()> {
+    for i in 0..count {
+        let length = reader.u16()? as usize;
+        let data = reader.bytes(length)?;
+        config.push(format!("{}[{}]", name, i), format!("{} bytes, hash {:016x}", length, fnv1a(data)), significant);
+    }
+    Ok(())
+}
+
+fn parse_esds(data: &[u8]) -> Result<CodecConfig> {
+    let mut config = CodecConfig::new(b"esds");
+    let mut reader = ByteReader::new(data);
+    reader.version_and_flags()?;
+
+    while reader.remaining() > 0 {
+        let tag = reader.u8()?;
+        let size = descriptor_size(&mut reader)?;
+        match tag {
+            // ES_Descriptor: its children follow its own fields
+            0x03 => {
+                reader.skip(2)?;
+                let flags = reader.u8()?;
+                if flags & 0x80 != 0 {
+                    reader.skip(2)?;
+                }
+                if flags & 0x40 != 0 {
+                    let url_length = reader.u8()? as usize;
+                    reader.skip(url_length)?;
+                }
+                if flags & 0x20 != 0 {
+                    reader.skip(2)?;
+                }
+            }
+            // DecoderConfigDescriptor: also followed by its children
+            0x04 => {
+                let object_type = reader.u8()?;
+                config.push("object type", format!("0x{:02x}", object_type), true);
+                reader.skip(1)?;
+                let buffer = reader.bytes(3)?;
+                config.push("buffer size", (u32::from_be_bytes([0, buffer[0], buffer[1], buffer[2]])).to_string(), false);
+                config.push("max bitrate", reader.u32()?.to_string(), false);
+                config.push("average bitrate", reader.u32()?.to_string(), false);
+            }
+            // DecoderSpecificInfo: the AudioSpecificConfig
+            0x05 => {
+                parse_audio_specific_config(reader.bytes(size)?, &mut config);
+            }
+            _ => reader.skip(size.min(reader.remaining()))?,
+        }
+    }
+    Ok(config)
+}
+
+/// Reads the variable-length size of an MPEG-4 descriptor.
+fn descriptor_size(reader: &mut ByteReader) -> Result<usize> {
+    let mut size = 0;
+    for _ in 0..4 {
+        let byte = reader.u8()?;
+        size = (size << 7) | (byte & 0x7f) as usize;
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    Ok(size)
+}
+
+fn parse_audio_specific_config(data: &[u8], config: &mut CodecConfig) {
+    const SAMPLE_RATES: [u32; 13] = [96000, 88200, 64000, 48000, 44100, 32000, 24000, 22050, 16000, 12000, 11025, 8000, 7350];
+
+    // Object type, frequency index and channels take 13 bits; an explicit
+    // 24-bit frequency makes it 37
+    let needed = if data.len() >= 2 && (data[0] & 0x07) << 1 | data[1] >> 7 == 15 { 5 } else { 2 };
+    if data.len() < needed {
+        return;
+    }
+    let bits = data.iter().take(8).fold(0u64, |acc, &b| (acc << 8) | b as u64) << (8 * (8 - data.len().min(8)));
+    let take = |offset: u32, count: u32| ((bits >> (64 - offset - count)) & ((1 << count) - 1)) as u32;
+
+    let object_type = take(0, 5);
+    let frequency_index = take(5, 4);
+    let (sample_rate, channels) = if frequency_index == 15 {
+        (take(9, 24), take(33, 4))
+    } else {
+        (SAMPLE_RATES.get(frequency_index as usize).copied().unwrap_or(0), take(9, 4))
+    };
+    config.push("audio object type", format!("{} ({})", aac_object_type_name(object_type), object_type), true);
+    config.push("sample rate", sample_rate.to_string(), true);
+    config.push("channel configuration", channels.to_string(), true);
+}
+
+fn h264_profile_name(profile: u8) -> &'static str {
+    match profile {
+        66 => "Baseline",
+        77 => "Main",
+        88 => "Extended",
+        100 => "High",
+        110 => "High 10",
+        122 => "High 4:2:2",
+        244 => "High 4:4:4 Predictive",
+        _ => "unknown",
+    }
+}
+
+fn hevc_profile_name(profile: u8) -> &'static str {
+    match profile {
+        1 => "Main",
+        2 => "Main 10",
+        3 => "Main Still Picture",
+        4 => "Range Extensions",
+        _ => "unknown",
+    }
+}
+
+fn aac_object_type_name(object_type: u32) -> &'static str {
+    match object_type {
+        1 => "AAC Main",
+        2 => "AAC LC",
+        3 => "AAC SSR",
+        4 => "AAC LTP",
+        5 => "HE-AAC",
+        29 => "HE-AAC v2",
+        _ => "unknown",
+    }
+}
+
+fn chroma_format_name(chroma_format: u8) -> &'static str {
+    match chroma_format {
+        0 => "4:0:0",
+        1 => "4:2:0",
+        2 => "4:2:2",
+        _ => "4:4:4",
+    }
+}
+
+/// 64-bit FNV-1a, used to fingerprint parameter sets.
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+/// A field whose value differs between two configurations; `None` means
+/// the field is not present on that side.
+#[derive(Debug, PartialEq)]
+pub struct FieldDiff<'a> {
+    pub name: &'a str,
+    pub old: Option<&'a str>,
+    pub new: Option<&'a str>,
+    pub significant: bool,
+}
+
+/// Compares two configurations field by field.
+pub fn diff<'a>(old: &'a CodecConfig, new: &'a CodecConfig) -> Vec<FieldDiff<'a>> {
+    let find = |config: &'a CodecConfig, name: &str| config.fields.iter().find(|f| f.name == name);
+
+    let mut diffs = Vec::new();
+    for field in &old.fields {
+        let other = find(new, &field.name);
+        if other.map(|f| &f.value) != Some(&field.value) {
+            diffs.push(FieldDiff {
+                name: &field.name,
+                old: Some(&field.value),
+                new: other.map(|f| f.value.as_str()),
+                significant: field.significant || other.is_some_and(|f| f.significant),
+            });
+        }
+    }
+    for field in new.fields.iter().filter(|f| find(old, &f.name).is_none()) {
+        diffs.push(FieldDiff { name: &field.name, old: None, new: Some(&field.value), significant: field.significant });
+    }
+    diffs
+}
+
+/// Renders a diff as indented lines, marking whether each difference is
+/// harmless for stream copy.
+pub fn format_diff(diffs: &[FieldDiff]) -> String {
+    diffs.iter()
+        .map(|d| format!("    {}: {} -> {} ({})",
+            d.name,
+            d.old.unwrap_or("(not present)"),
+            d.new.unwrap_or("(not present)"),
+            if d.significant { "breaks stream copy" } else { "harmless" }))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::atoms::tests::atom;
+
+    /// An avcC record with one SPS and one PPS.
+    pub fn avcc(profile: u8, level: u8, sps: &[u8]) -> Vec<u8> {
+        let mut data = vec![1, profile, 0, level, 0xff, 0xe1];
+        data.extend_from_slice(&(sps.len() as u16).to_be_bytes());
+        data.extend_from_slice(sps);
+        data.extend_from_slice(&[1, 0, 2, 0x68, 0xce]);
+        if profile == 100 {
+            data.extend_from_slice(&[0xfd, 0xf8, 0xf8, 0]);
+        }
+        data
+    }
+
+    /// A visual sample entry with the given child atoms.
+    pub fn video_entry(format: &FourCC, children: &[u8]) -> Vec<u8> {
+        let mut payload = vec![0u8; 78];
+        payload.extend_from_slice(children);
+        atom(format, &payload)
+    }
+
+    fn esds(audio_specific_config: &[u8], bitrate: u32) -> Vec<u8> {
+        let mut decoder_config = vec![0x40, 0x15, 0, 0x18, 0];
+        decoder_config.extend_from_slice(&bitrate.to_be_bytes());
+        decoder_config.extend_from_slice(&bitrate.to_be_bytes());
+        decoder_config.extend_from_slice(&[0x05, audio_specific_config.len() as u8]);
+        decoder_config.extend_from_slice(audio_specific_config);
+
+        let mut es = vec![0, 1, 0, 0x04, decoder_config.len() as u8];
+        es.extend(decoder_config);
+        let mut data = vec![0, 0, 0, 0, 0x03, es.len() as u8];
+        data.extend(es);
+        data
+    }
+
+    #[test]
+    fn test_parse_avcc() {
+        let entry = video_entry(b"avc1", &atom(b"avcC", &avcc(100, 40, &[0x67, 0x64, 0x00, 0x28])));
+        let config = parse_sample_entry(b"vide", &entry).unwrap();
+        let value = |name: &str| config.fields.iter().find(|f| f.name == name).unwrap().value.clone();
+        assert_eq!(&config.kind, b"avcC");
+        assert_eq!(value("profile"), "High (100)");
+        assert_eq!(value("level"), "4.0");
+        assert_eq!(value("NAL length size"), "4 bytes");
+        assert!(value("SPS[0]").starts_with("4 bytes, hash "));
+        assert_eq!(value("chroma format"), "4:2:0");
+        assert_eq!(value("luma bit depth"), "8");
+    }
+
+    #[test]
+    fn test_parse_hvcc() {
+        let mut hvcc = vec![1, 0x02, 0x20, 0, 0, 0, 0x90, 0, 0, 0, 0, 0, 153, 0xf0, 0, 0xfc, 0xfd, 0xfa, 0xfa, 0, 0, 0x0f];
+        hvcc.extend_from_slice(&[1, 0x21, 0, 1, 0, 3, 0x42, 0x01, 0x01]);
+        let entry = video_entry(b"hvc1", &atom(b"hvcC", &hvcc));
+        let config = parse_sample_entry(b"vide", &entry).unwrap();
+        let value = |name: &str| config.fields.iter().find(|f| f.name == name).unwrap().value.clone();
+        assert_eq!(value("profile"), "Main 10 (2)");
+        assert_eq!(value("tier"), "Main");
+        assert_eq!(value("level"), "5.1");
+        assert_eq!(value("chroma format"), "4:2:0");
+        assert_eq!(value("luma bit depth"), "10");
+        assert!(value("SPS[0]").starts_with("3 bytes"));
+    }
+
+    #[test]
+    fn test_parse_esds_in_wave() {
+        // AAC LC, 48 kHz, stereo
+        let mut payload = vec![0u8; 8];
+        payload.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 0, 16, 0, 0, 0, 0, 0xbb, 0x80, 0, 0]);
+        payload.extend(atom(b"wave", &atom(b"esds", &esds(&[0x11, 0x90], 128000))));
+        let entry = atom(b"mp4a", &payload);
+        let config = parse_sample_entry(b"soun", &entry).unwrap();
+        let value = |name: &str| config.fields.iter().find(|f| f.name == name).unwrap().value.clone();
+        assert_eq!(value("object type"), "0x40");
+        assert_eq!(value("audio object type"), "AAC LC (2)");
+        assert_eq!(value("sample rate"), "48000");
+        assert_eq!(value("channel configuration"), "2");
+        assert_eq!(value("average bitrate"), "128000");
+    }
+
+    #[test]
+    fn test_parse_esds_short_config() {
+        // An empty or truncated DecoderSpecificInfo has no audio fields
+        for audio_specific_config in [&[][..], &[0x11], &[0x17, 0x80, 0x01]] {
+            let mut payload = vec![0u8; 28];
+            payload.extend(atom(b"esds", &esds(audio_specific_config, 128000)));
+            let config = parse_sample_entry(b"soun", &atom(b"mp4a", &payload)).unwrap();
+            assert!(config.fields.iter().any(|f| f.name == "object type"));
+            assert!(!config.fields.iter().any(|f| f.name == "sample rate"));
+        }
+    }
+
+    #[test]
+    fn test_diff() {
+        let parse = |profile, level, sps: &[u8], format| {
+            parse_sample_entry(b"vide", &video_entry(format, &atom(b"avcC", &avcc(profile, level, sps)))).unwrap()
+        };
+        let high = parse(100, 40, &[0x67, 1], b"avc1");
+        assert!(diff(&high, &high).is_empty());
+
+        let main = parse(77, 40, &[0x67, 2], b"avc1");
+        let diffs = diff(&high, &main);
+        assert_eq!(diffs[0], FieldDiff { name: "profile", old: Some("High (100)"), new: Some("Main (77)"), significant: true });
+        assert!(diffs.iter().any(|d| d.name == "SPS[0]" && d.significant));
+        // High profile extension fields are missing on the Main side
+        assert!(diffs.iter().any(|d| d.name == "chroma format" && d.new.is_none()));
+
+        // In-band parameter sets may differ
+        let a = parse(100, 40, &[0x67, 1], b"avc3");
+        let b = parse(100, 40, &[0x67, 2], b"avc3");
+        let diffs = diff(&a, &b);
+        assert_eq!(diffs.len(), 1);
+        assert!(!diffs[0].significant);
+        assert_eq!(format_diff(&diffs), format!("    SPS[0]: {} -> {} (harmless)",
+            a.fields[4].value, b.fields[4].value));
+    }
+}
@@ -0,0 +1,175 @@
+//! Deterministic fake backend (`--backend fake`).
+//!
+//! Goes through the whole pipeline (globbing, ordering, validation, join
+//! timing, metadata, partial files and finalization) but instead of running
+//! FFmpeg it writes a stub movie and records what FFmpeg would have been
+//! asked to do in `<output>.movcat-fake.json`. The stub has the duration
+//! the real output would have, gaps included, and a checkpoint is kept
+//! while the partial files exist, so `--resume` behaves as with FFmpeg.
+//! End-to-end tests can then check the resulting plan and exit codes
+//! without real media or FFmpeg.
+
+use crate::{checkpoint_path, finalize_output, partial_output_path, plan, Checkpoint, MovInfo, OutputOptions};
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// Everything the fake backend was asked to do.
+#[derive(Debug, Serialize)]
+struct Record {
+    output: PathBuf,
+    also_output: Option<PathBuf>,
+    inputs: Vec<RecordedInput>,
+    /// The file list that would have been passed to FFmpeg.
+    filelist: String,
+    metadata: Vec<(String, String)>,
+    chapters: Vec<(String, f64)>,
+    faststart: bool,
+    hvc1_tag: bool,
+    /// Duration of the output in seconds, including filled gaps.
+    duration: f64,
+    /// Whether the partial outputs of an interrupted run were finished.
+    resumed: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct RecordedInput {
+    path: PathBuf,
+    /// Duration in seconds.
+    duration: f64,
+    trim_start: Option<f64>,
+    trim_end: Option<f64>,
+}
+
+/// Path of the plan record for an output file.
+pub fn record_path(output_path: &Path) -> PathBuf {
+    let mut name = output_path.file_name().unwrap_or_default().to_os_string();
+    name.push(".movcat-fake.json");
+    output_path.with_file_name(name)
+}
+
+pub fn concatenate(infos: &[MovInfo], output_path: &Path, options: &OutputOptions) -> Result<()> {
+    println!("Using fake backend; no media data is copied...");
+
+    let timing = crate::join_timing(infos, options);
+    let last = infos.last().map_or(0.0, |info| info.used_duration_secs());
+    let duration = match &timing {
+        Some(timing) => timing.durations_us.iter().sum::<u64>() as f64 / 1_000_000.0 + last,
+        None => infos.iter().map(|info| info.used_duration_secs()).sum(),
+    };
+    let mut record = Record {
+        output: output_path.to_path_buf(),
+        also_output: options.also_output.clone(),
+        inputs: infos.iter().map(|info| RecordedInput {
+            path: info.path.clone(),
+            duration: info.duration_secs(),
+            trim_start: info.trim_start,
+            trim_end: info.trim_end,
+        }).collect(),
+        filelist: crate::concat_filelist(infos, timing.as_ref())?,
+        metadata: crate::output_metadata(infos, options)?,
+        chapters: options.chapters.iter().map(|c| (c.title.clone(), c.start)).collect(),
+        faststart: options.faststart,
+        hvc1_tag: options.hvc1_tag,
+        duration,
+        resumed: false,
+    };
+
+    // The record stands in for the FFmpeg arguments
+    let checkpoint = checkpoint_path(output_path);
+    let current = Checkpoint {
+        plan: plan::Plan::from_infos(infos, Some(output_path))?,
+        filelist: record.filelist.clone(),
+        chapters: String::new(),
+        ffmpeg_args: vec![serde_json::to_string(&record)?],
+    };
+
+    // Same partial-then-rename sequence as a real run
+    let mut outputs = vec![(output_path.to_path_buf(), options.faststart)];
+    if let Some(also_output) = &options.also_output {
+        outputs.push((also_output.clone(), true));
+    }
+    let partial_paths: Vec<PathBuf> = outputs.iter().map(|(path, _)| partial_output_path(path)).collect();
+    if options.resume {
+        crate::resume_partials(&outputs, &partial_paths, &checkpoint, &current)?;
+        record.resumed = true;
+    } else {
+        current.save(&checkpoint)?;
+        for ((path, _), partial_path) in outputs.iter().zip(&partial_paths) {
+            std::fs::write(partial_path, stub_movie(duration))
+                .with_context(|| format!("Failed to write output: {:?}", partial_path))?;
+            if let Err(e) = finalize_output(partial_path, path) {
+                let _ = std::fs::remove_file(partial_path);
+                let _ = std::fs::remove_file(&checkpoint);
+                return Err(e);
+            }
+        }
+        let _ = std::fs::remove_file(&checkpoint);
+    }
+
+    let path = record_path(output_path);
+    let content = serde_json::to_string_pretty(&record)?;
+    std::fs::write(&path, content + "\n")
+        .with_context(|| format!("Failed to write fake backend record: {:?}", path))?;
+    println!("Fake backend record written to {:?}", path);
+    Ok(())
+}
+
+/// A movie without tracks that only carries the total duration, at a
+/// 1/1000 s timescale.
+fn stub_movie(duration_secs: f64) -> Vec<u8> {
+    let atom = |kind: &[u8; 4], payload: &[u8]| {
+        let mut data = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(kind);
+        data.extend_from_slice(payload);
+        data
+    };
+
+    let mut mvhd = vec![0u8; 100];
+    mvhd[12..16].copy_from_slice(&1000u32.to_be_bytes());
+    mvhd[16..20].copy_from_slice(&((duration_secs * 1000.0).round() as u32).to_be_bytes());
+    mvhd[20..24].copy_from_slice(&0x0001_0000u32.to_be_bytes()); // rate 1.0
+    mvhd[24..26].copy_from_slice(&0x0100u16.to_be_bytes()); // volume 1.0
+    for (i, value) in [0x0001_0000u32, 0, 0, 0, 0x0001_0000, 0, 0, 0, 0x4000_0000].iter().enumerate() {
+        mvhd[36 + i * 4..40 + i * 4].copy_from_slice(&value.to_be_bytes());
+    }
+    mvhd[96..100].copy_from_slice(&1u32.to_be_bytes()); // next track ID
+
+    let mut data = atom(b"ftyp", b"qt  \0\0\0\0qt  ");
+    data.extend(atom(b"moov", &atom(b"mvhd", &mvhd)));
+    data.extend(atom(b"mdat", &[]));
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::BackendChoice;
+    use crate::movie::tests::{movie_file, trak, write_temp};
+
+    #[test]
+    fn test_fake_backend_end_to_end() {
+        let first = write_temp("movcat_test_fake_1.mov", &movie_file(Some(b"qt  "), &[trak(1, b"vide", 2, 0)]));
+        let second = write_temp("movcat_test_fake_2.mov", &movie_file(Some(b"qt  "), &[trak(1, b"vide", 2, 0)]));
+        let output = std::env::temp_dir().join("movcat_test_fake_out.mov");
+
+        assert!(crate::check_backend_available(BackendChoice::Fake).is_empty());
+        let infos = crate::validate_input_files(&[first.clone(), second.clone()], &[]).unwrap();
+        let options = OutputOptions { metadata: vec![("title".to_string(), "Test".to_string())], ..OutputOptions::default() };
+        crate::concatenate_mov_files(&infos, &output, &options, BackendChoice::Fake, false).unwrap();
+
+        let movie = crate::movie::read_movie(&output).unwrap();
+        assert_eq!(movie.timescale, 1000);
+        assert_eq!(movie.duration, (infos.iter().map(|info| info.duration_secs()).sum::<f64>() * 1000.0).round() as u64);
+
+        let record: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(record_path(&output)).unwrap()).unwrap();
+        assert_eq!(record["inputs"].as_array().unwrap().len(), 2);
+        assert_eq!(record["inputs"][0]["path"], first.to_string_lossy().as_ref());
+        assert_eq!(record["metadata"][0], serde_json::json!(["title", "Test"]));
+        assert_eq!(record["filelist"].as_str().unwrap().matches("file '").count(), 2);
+
+        for path in [first, second, record_path(&output), output] {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
@@ -0,0 +1,230 @@
+//! Input filters such as `codec=hevc,height>=2160`.
+//!
+//! Filters are evaluated against the analysis results of each input, so a
+//! folder with mixed footage can be narrowed down to one deliverable class
+//! without sorting the files into separate directories first.
+
+use crate::{atoms, movie, MovInfo};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    /// Codec of the first video track (or the first audio track of
+    /// audio-only files), by FFmpeg name or FourCC.
+    Codec,
+    Width,
+    Height,
+    /// Duration in seconds.
+    Duration,
+    Brand,
+    VideoTracks,
+    AudioTracks,
+}
+
+impl Field {
+    fn parse(name: &str) -> Option<Field> {
+        Some(match name {
+            "codec" => Field::Codec,
+            "width" => Field::Width,
+            "height" => Field::Height,
+            "duration" => Field::Duration,
+            "brand" => Field::Brand,
+            "video" => Field::VideoTracks,
+            "audio" => Field::AudioTracks,
+            _ => return None,
+        })
+    }
+
+    fn is_numeric(&self) -> bool {
+        !matches!(self, Field::Codec | Field::Brand)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Ge,
+    Le,
+    Gt,
+    Lt,
+}
+
+impl Op {
+    /// Operators in the order they have to be tried when splitting a
+    /// condition, so that `>=` is not read as `>` followed by `=value`.
+    const ALL: [(&'static str, Op); 6] = [
+        ("!=", Op::Ne),
+        (">=", Op::Ge),
+        ("<=", Op::Le),
+        ("=", Op::Eq),
+        (">", Op::Gt),
+        ("<", Op::Lt),
+    ];
+
+    fn compare<T: PartialOrd>(&self, left: T, right: T) -> bool {
+        match self {
+            Op::Eq => left == right,
+            Op::Ne => left != right,
+            Op::Ge => left >= right,
+            Op::Le => left <= right,
+            Op::Gt => left > right,
+            Op::Lt => left < right,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Number(f64),
+    Text(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Condition {
+    field: Field,
+    op: Op,
+    value: Value,
+}
+
+/// A comma-separated list of conditions that must all hold.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Filter {
+    expression: String,
+    conditions: Vec<Condition>,
+}
+
+impl Filter {
+    pub fn parse(expression: &str) -> Result<Filter, String> {
+        let mut conditions = Vec::new();
+        for part in expression.split(',').map(str::trim) {
+            if part.is_empty() {
+                return Err(format!("empty condition in filter '{}'", expression));
+            }
+            conditions.push(parse_condition(part)?);
+        }
+        Ok(Filter { expression: expression.to_string(), conditions })
+    }
+
+    pub fn expression(&self) -> &str {
+        &self.expression
+    }
+
+    pub fn matches(&self, info: &MovInfo) -> bool {
+        self.conditions.iter().all(|condition| condition.matches(info))
+    }
+}
+
+fn parse_condition(condition: &str) -> Result<Condition, String> {
+    let (position, symbol, op) = Op::ALL.iter()
+        .filter_map(|&(symbol, op)| condition.find(symbol).map(|position| (position, symbol, op)))
+        .min_by_key(|&(position, _, _)| position)
+        .ok_or_else(|| format!("'{}' has no comparison operator (=, !=, >=, <=, >, <)", condition))?;
+
+    let name = condition[..position].trim();
+    let value = condition[position + symbol.len()..].trim();
+    let field = Field::parse(name).ok_or_else(|| {
+        format!("unknown filter field '{}' (expected codec, width, height, duration, brand, video or audio)", name)
+    })?;
+    if value.is_empty() {
+        return Err(format!("'{}' has no value", condition));
+    }
+
+    let value = if field.is_numeric() {
+        Value::Number(value.parse().map_err(|_| format!("'{}' is not a number in '{}'", value, condition))?)
+    } else if matches!(op, Op::Eq | Op::Ne) {
+        Value::Text(value.to_string())
+    } else {
+        return Err(format!("'{}' can only be compared with = or !=", name));
+    };
+
+    Ok(Condition { field, op, value })
+}
+
+impl Condition {
+    fn matches(&self, info: &MovInfo) -> bool {
+        let track = info.tracks.iter().find(|t| t.is_video())
+            .or_else(|| info.tracks.iter().find(|t| t.is_audio()));
+
+        match &self.value {
+            Value::Number(expected) => {
+                let actual = match self.field {
+                    Field::Width => track.filter(|t| t.is_video()).map(|t| t.width as f64),
+                    Field::Height => track.filter(|t| t.is_video()).map(|t| t.height as f64),
+                    Field::Duration => Some(info.duration_secs()),
+                    Field::VideoTracks => Some(info.video_tracks as f64),
+                    Field::AudioTracks => Some(info.audio_tracks as f64),
+                    Field::Codec | Field::Brand => None,
+                };
+                actual.is_some_and(|actual| self.op.compare(actual, *expected))
+            }
+            Value::Text(expected) => {
+                let equal = match self.field {
+                    Field::Codec => track.is_some_and(|t| codec_matches(&t.codec, expected)),
+                    Field::Brand => info.major_brand.eq_ignore_ascii_case(expected),
+                    _ => false,
+                };
+                equal == (self.op == Op::Eq)
+            }
+        }
+    }
+}
+
+/// Matches a codec against an FFmpeg codec name (`hevc`) or a FourCC
+/// (`hvc1`), ignoring case.
+fn codec_matches(codec: &atoms::FourCC, expected: &str) -> bool {
+    movie::codec_name(codec).is_some_and(|name| name.eq_ignore_ascii_case(expected))
+        || atoms::fourcc_to_string(codec).trim_end().eq_ignore_ascii_case(expected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::movie::tests::{movie_file, TestTrack, write_temp};
+
+    fn analyzed(name: &str, codec: &[u8; 4], width: u32, height: u32) -> MovInfo {
+        let video = TestTrack { codec: *codec, width, height, ..TestTrack::new(1, b"vide") };
+        let data = movie_file(Some(b"qt  "), &[video.build(), TestTrack::new(2, b"soun").build()]);
+        let path = write_temp(name, &data);
+        let info = crate::analyze_mov_file(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        info
+    }
+
+    #[test]
+    fn test_parse_filter() {
+        let filter = Filter::parse("codec=hevc, height>=2160").unwrap();
+        assert_eq!(filter.conditions, vec![
+            Condition { field: Field::Codec, op: Op::Eq, value: Value::Text("hevc".to_string()) },
+            Condition { field: Field::Height, op: Op::Ge, value: Value::Number(2160.0) },
+        ]);
+        assert_eq!(Filter::parse("duration<10").unwrap().conditions[0].op, Op::Lt);
+        assert_eq!(Filter::parse("codec!=prores").unwrap().conditions[0].op, Op::Ne);
+    }
+
+    #[test]
+    fn test_parse_filter_errors() {
+        assert!(Filter::parse("").is_err());
+        assert!(Filter::parse("codec=hevc,").is_err());
+        assert!(Filter::parse("height").is_err());
+        assert!(Filter::parse("fps>=30").is_err());
+        assert!(Filter::parse("height>=tall").is_err());
+        assert!(Filter::parse("codec>=hevc").is_err());
+        assert!(Filter::parse("width=").is_err());
+    }
+
+    #[test]
+    fn test_filter_matches() {
+        let uhd_hevc = analyzed("movcat_test_filter_uhd.mov", b"hvc1", 3840, 2160);
+        let hd_avc = analyzed("movcat_test_filter_hd.mov", b"avc1", 1920, 1080);
+
+        let filter = Filter::parse("codec=hevc,height>=2160").unwrap();
+        assert!(filter.matches(&uhd_hevc));
+        assert!(!filter.matches(&hd_avc));
+
+        // FourCCs work as well as FFmpeg codec names
+        assert!(Filter::parse("codec=AVC1").unwrap().matches(&hd_avc));
+        assert!(Filter::parse("codec!=hevc").unwrap().matches(&hd_avc));
+        assert!(Filter::parse("width<3840,audio=1").unwrap().matches(&hd_avc));
+        assert!(Filter::parse("brand=qt").unwrap().matches(&hd_avc));
+    }
+}
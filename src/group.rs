@@ -0,0 +1,100 @@
+//! Splitting a mixed set of inputs into several merges (`--group`).
+//!
+//! A folder with clips from different cameras or settings cannot be joined
+//! by stream copy as a whole. Grouping the inputs by their track signature
+//! (codecs, resolution, frame rate, audio parameters) yields one valid
+//! merge per group instead of one failed validation.
+
+use crate::{atoms, movie, MovInfo};
+use clap::ValueEnum;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Grouping {
+    /// One output per distinct track signature
+    Signature,
+}
+
+/// The parameters of the video and audio tracks that a stream copy needs
+/// to match, e.g. `hevc 3840x2160 29.97fps + aac 48000Hz`.
+pub fn signature(info: &MovInfo) -> String {
+    let codec = |track: &movie::Track| movie::codec_name(&track.codec)
+        .map_or_else(|| atoms::fourcc_to_string(&track.codec).trim_end().to_string(), str::to_string);
+    info.tracks.iter()
+        .filter_map(|track| {
+            if track.is_video() {
+                let (width, height) = track.display_size();
+                let size = if width > 0 { format!(" {}x{}", width, height) } else { String::new() };
+                let fps = track.frame_rate().map_or_else(String::new, |fps| format!(" {:.2}fps", fps));
+                Some(format!("{}{}{}", codec(track), size, fps))
+            } else if track.is_audio() {
+                let rate = if track.sample_rate > 0 { format!(" {}Hz", track.sample_rate) } else { String::new() };
+                Some(format!("{}{}", codec(track), rate))
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" + ")
+}
+
+/// Groups the inputs by signature, keeping their order within a group.
+/// Groups are ordered by their first input.
+pub fn by_signature(infos: &[MovInfo]) -> Vec<(String, Vec<PathBuf>)> {
+    let mut groups: Vec<(String, Vec<PathBuf>)> = Vec::new();
+    for info in infos {
+        let key = signature(info);
+        match groups.iter_mut().find(|(signature, _)| *signature == key) {
+            Some((_, paths)) => paths.push(info.path.clone()),
+            None => groups.push((key, vec![info.path.clone()])),
+        }
+    }
+    groups
+}
+
+/// Output path of a group: `out.mov` becomes `out-1.mov`, `out-2.mov`, ...
+pub fn output_path(output: &Path, number: usize) -> PathBuf {
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match output.extension() {
+        Some(ext) => format!("{}-{}.{}", stem, number, ext.to_string_lossy()),
+        None => format!("{}-{}", stem, number),
+    };
+    output.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::movie::tests::{movie_file, write_temp, TestTrack};
+
+    fn analyzed(name: &str, width: u32, codec: &[u8; 4]) -> MovInfo {
+        let video = TestTrack { codec: *codec, width, height: width * 9 / 16, samples: 30, timescale: 30, media_duration: 30,
+            ..TestTrack::new(1, b"vide") };
+        let audio = TestTrack { sample_rate: 48000, ..TestTrack::new(2, b"soun") };
+        let path = write_temp(name, &movie_file(None, &[video.build(), audio.build()]));
+        let info = crate::analyze_mov_file(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        info
+    }
+
+    #[test]
+    fn test_group_by_signature() {
+        let infos = vec![
+            analyzed("movcat_test_group_1.mov", 3840, b"hvc1"),
+            analyzed("movcat_test_group_2.mov", 1920, b"avc1"),
+            analyzed("movcat_test_group_3.mov", 3840, b"hvc1"),
+        ];
+        assert_eq!(signature(&infos[0]), "hevc 3840x2160 30.00fps + aac 48000Hz");
+
+        let groups = by_signature(&infos);
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].1, vec![infos[0].path.clone(), infos[2].path.clone()]);
+        assert_eq!(groups[1].0, "h264 1920x1080 30.00fps + aac 48000Hz");
+    }
+
+    #[test]
+    fn test_group_output_path() {
+        assert_eq!(output_path(Path::new("out/day.mov"), 2), PathBuf::from("out/day-2.mov"));
+        assert_eq!(output_path(Path::new("day"), 1), PathBuf::from("day-1"));
+    }
+}
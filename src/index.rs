@@ -0,0 +1,300 @@
+//! Random-access index sidecar for merged outputs.
+//!
+//! With `--index`, the keyframes of the output's first video track are
+//! written next to it as `<output>.movcat-index.json`: their presentation
+//! times and byte offsets. Later tools can seek or cut the output from the
+//! sidecar instead of re-parsing a moov that can be hundreds of megabytes.
+//!
+//! Keyframe times follow the track's edit list: media skipped by the first
+//! media segment is subtracted and leading empty edits are added, so times
+//! match the player's timeline. Later segments of an edit list with several
+//! media segments are not applied; FFmpeg's concat output has at most one.
+
+use crate::atoms::{self, ByteReader};
+use crate::movie::{self, Edit};
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Serialize)]
+pub struct Index {
+    pub version: u32,
+    /// Size of the indexed file, to detect a stale sidecar.
+    pub file_size: u64,
+    pub track_id: u32,
+    /// Ticks per second of the keyframe times.
+    pub timescale: u32,
+    /// `[presentation time, byte offset]` of every keyframe, in sample
+    /// order.
+    pub keyframes: Vec<(i64, u64)>,
+}
+
+const VERSION: u32 = 1;
+
+/// Path of the sidecar for an output file.
+pub fn sidecar_path(output_path: &Path) -> PathBuf {
+    let mut name = output_path.file_name().unwrap_or_default().to_os_string();
+    name.push(".movcat-index.json");
+    output_path.with_file_name(name)
+}
+
+/// Builds the index of a file's first video track.
+pub fn build(path: &Path) -> Result<Index> {
+    let mut file = File::open(path)
+        .with_context(|| format!("Failed to open file: {:?}", path))?;
+    let file_size = file.metadata()?.len();
+    let top_level = atoms::top_level_atoms(&mut file)
+        .with_context(|| format!("Failed to read atoms of {:?}", path))?;
+    let moov = top_level.iter().find(|a| &a.kind == b"moov")
+        .with_context(|| format!("No moov atom found in {:?}", path))?;
+    let data = atoms::read_payload(&mut file, moov)?;
+
+    let mvhd = atoms::find_child(&data, b"mvhd")?
+        .with_context(|| format!("No mvhd atom found in {:?}", path))?;
+    let mut reader = ByteReader::new(mvhd);
+    let (version, _) = reader.version_and_flags()?;
+    reader.skip(if version == 1 { 16 } else { 8 })?;
+    let movie_timescale = reader.u32()?;
+
+    for (kind, trak) in atoms::children(&data)? {
+        if &kind != b"trak" {
+            continue;
+        }
+        let Some(hdlr) = atoms::find_path(trak, &[b"mdia", b"hdlr"])? else {
+            continue;
+        };
+        let mut reader = ByteReader::new(hdlr);
+        reader.skip(8)?;
+        if matches!(&reader.fourcc()?, b"vide" | b"pict") {
+            return index_track(trak, movie_timescale, file_size)
+                .with_context(|| format!("Failed to index video track of {:?}", path));
+        }
+    }
+
+    anyhow::bail!("No video track to index in {:?}", path)
+}
+
+/// Shift from media time to presentation time: leading empty edits (in
+/// the movie timescale) minus the media time of the first media segment.
+fn edit_shift(edits: &[Edit], movie_timescale: u32, timescale: u32) -> i64 {
+    let empty = edits.iter()
+        .take_while(|edit| edit.media_time < 0)
+        .map(|edit| edit.segment_duration as u128)
+        .sum::<u128>();
+    let delay = match movie_timescale {
+        0 => 0,
+        movie_timescale => (empty * timescale as u128 / movie_timescale as u128) as i64,
+    };
+    let skipped = edits.iter().find(|edit| edit.media_time >= 0).map_or(0, |edit| edit.media_time);
+    delay - skipped
+}
+
+fn index_track(trak: &[u8], movie_timescale: u32, file_size: u64) -> Result<Index> {
+    let tkhd = atoms::find_child(trak, b"tkhd")?.context("No tkhd atom found")?;
+    let mut reader = ByteReader::new(tkhd);
+    let (version, _) = reader.version_and_flags()?;
+    reader.skip(if version == 1 { 16 } else { 8 })?;
+    let track_id = reader.u32()?;
+
+    let mdhd = atoms::find_path(trak, &[b"mdia", b"mdhd"])?.context("No mdhd atom found")?;
+    let mut reader = ByteReader::new(mdhd);
+    let (version, _) = reader.version_and_flags()?;
+    reader.skip(if version == 1 { 16 } else { 8 })?;
+    let timescale = reader.u32()?;
+    let shift = edit_shift(&movie::edit_list(trak)?, movie_timescale, timescale);
+
+    let stbl = atoms::find_path(trak, &[b"mdia", b"minf", b"stbl"])?.context("No stbl atom found")?;
+    // Sample table atom with the reader positioned after version and flags
+    let table = |kind| -> Result<Option<ByteReader>> {
+        let Some(data) = atoms::find_child(stbl, kind)? else {
+            return Ok(None);
+        };
+        let mut reader = ByteReader::new(data);
+        reader.version_and_flags()?;
+        Ok(Some(reader))
+    };
+
+    let mut stts = table(b"stts")?.context("No stts atom found")?;
+    let mut durations = Vec::new();
+    for _ in 0..stts.u32()? {
+        durations.push((stts.u32()?, stts.u32()?));
+    }
+
+    let mut offsets = Vec::new();
+    if let Some(mut ctts) = table(b"ctts")? {
+        for _ in 0..ctts.u32()? {
+            // Signed in version 1, and in practice in version 0 as well
+            offsets.push((ctts.u32()?, ctts.u32()? as i32 as i64));
+        }
+    }
+
+    // No stss means every sample is a sync sample
+    let sync_samples = match table(b"stss")? {
+        Some(mut stss) => {
+            // No pre-allocation: a corrupt count fails on the first
+            // missing entry instead of allocating for it
+            let mut samples = Vec::new();
+            for _ in 0..stss.u32()? {
+                samples.push(stss.u32()?);
+            }
+            Some(samples)
+        }
+        None => None,
+    };
+
+    let mut stsc = table(b"stsc")?.context("No stsc atom found")?;
+    let mut chunk_runs = Vec::new();
+    for _ in 0..stsc.u32()? {
+        let first_chunk = stsc.u32()?;
+        let samples_per_chunk = stsc.u32()?;
+        stsc.skip(4)?;
+        chunk_runs.push((first_chunk, samples_per_chunk));
+    }
+
+    let mut stsz = table(b"stsz")?.context("No stsz atom found")?;
+    let uniform_size = stsz.u32()?;
+    let sample_count = stsz.u32()?;
+
+    let (mut chunk_offsets, large) = match (table(b"stco")?, table(b"co64")?) {
+        (Some(stco), _) => (stco, false),
+        (None, Some(co64)) => (co64, true),
+        (None, None) => anyhow::bail!("No chunk offset atom found"),
+    };
+    let chunk_count = chunk_offsets.u32()?;
+
+    // Walk all samples chunk by chunk, tracking byte offset and decode time
+    let mut keyframes = Vec::new();
+    let mut durations = durations.into_iter().flat_map(|(count, delta)| std::iter::repeat_n(delta, count as usize));
+    let mut offsets = offsets.into_iter().flat_map(|(count, offset)| std::iter::repeat_n(offset, count as usize));
+    let mut next_sync = sync_samples.as_ref().map(|samples| samples.iter().peekable());
+
+    let mut sample = 1u32;
+    let mut decode_time = shift;
+    for chunk in 1..=chunk_count {
+        let mut offset = if large { chunk_offsets.u64()? } else { chunk_offsets.u32()? as u64 };
+        let samples_in_chunk = chunk_runs.iter()
+            .take_while(|(first_chunk, _)| *first_chunk <= chunk)
+            .last()
+            .map_or(0, |&(_, samples)| samples);
+
+        for _ in 0..samples_in_chunk {
+            if sample > sample_count {
+                break;
+            }
+            let size = if uniform_size != 0 { uniform_size } else { stsz.u32()? };
+            let is_sync = match next_sync.as_mut() {
+                Some(sync) => sync.next_if(|&&s| s == sample).is_some(),
+                None => true,
+            };
+            if is_sync {
+                keyframes.push((decode_time + offsets.next().unwrap_or(0), offset));
+            } else {
+                offsets.next();
+            }
+
+            decode_time += durations.next().unwrap_or(0) as i64;
+            offset += size as u64;
+            sample += 1;
+        }
+    }
+
+    Ok(Index { version: VERSION, file_size, track_id, timescale, keyframes })
+}
+
+impl Index {
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let content = serde_json::to_string(self)?;
+        std::fs::write(path, content + "\n")
+            .with_context(|| format!("Failed to write index: {:?}", path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atoms::tests::atom;
+    use crate::movie::tests::{full_atom, write_temp};
+
+    /// A video track with 5 samples in 2 chunks, keyframes at 1 and 4, and
+    /// B-frame style composition offsets.
+    fn indexed_trak(edts: Option<Vec<u8>>) -> Vec<u8> {
+        let mut hdlr = vec![0u8; 8];
+        hdlr.extend_from_slice(b"vide");
+        hdlr.extend_from_slice(&[0; 12]);
+
+        let mut stbl = full_atom(b"stts", 0, &[1, 5, 100]);
+        stbl.extend(full_atom(b"ctts", 0, &[2, 1, 200, 4, 100]));
+        stbl.extend(full_atom(b"stss", 0, &[2, 1, 4]));
+        stbl.extend(full_atom(b"stsc", 0, &[2, 1, 3, 1, 2, 2, 1]));
+        stbl.extend(full_atom(b"stsz", 0, &[0, 5, 10, 20, 30, 40, 50]));
+        stbl.extend(full_atom(b"stco", 0, &[2, 1000, 5000]));
+
+        let mut mdia = full_atom(b"mdhd", 0, &[0, 0, 2400, 500]);
+        mdia.extend(atom(b"hdlr", &hdlr));
+        mdia.extend(atom(b"minf", &atom(b"stbl", &stbl)));
+
+        let mut trak = full_atom(b"tkhd", 0, &[0, 0, 7, 0, 0]);
+        trak.extend(edts.unwrap_or_default());
+        trak.extend(atom(b"mdia", &mdia));
+        atom(b"trak", &trak)
+    }
+
+    /// A file with the indexed track in a movie with timescale 600.
+    fn indexed_file(edts: Option<Vec<u8>>) -> Vec<u8> {
+        let mut moov = full_atom(b"mvhd", 0, &[0, 0, 600, 0]);
+        moov.extend(indexed_trak(edts));
+        let mut data = atom(b"mdat", &[0; 16]);
+        data.extend(atom(b"moov", &moov));
+        data
+    }
+
+    #[test]
+    fn test_build_index() {
+        let data = indexed_file(None);
+        let path = write_temp("movcat_test_index.mov", &data);
+
+        let index = build(&path).unwrap();
+        assert_eq!(index.track_id, 7);
+        assert_eq!(index.timescale, 2400);
+        assert_eq!(index.file_size, data.len() as u64);
+        // Sample 1 starts chunk 1, sample 4 starts chunk 2; times include ctts
+        assert_eq!(index.keyframes, vec![(200, 1000), (400, 5000)]);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_build_index_edit_list() {
+        // A 1 s empty edit, then the media from 200 ticks on
+        let elst = full_atom(b"elst", 0, &[2, 600, u32::MAX, 0x0001_0000, 300, 200, 0x0001_0000]);
+        let path = write_temp("movcat_test_index_elst.mov", &indexed_file(Some(atom(b"edts", &elst))));
+
+        let index = build(&path).unwrap();
+        assert_eq!(index.keyframes, vec![(2400, 1000), (2600, 5000)]);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_build_index_corrupt_sync_count() {
+        let mut data = indexed_file(None);
+        // Claim 2^32 - 1 sync samples
+        let stss = data.windows(4).position(|window| window == b"stss").unwrap();
+        data[stss + 8..stss + 12].copy_from_slice(&u32::MAX.to_be_bytes());
+        let path = write_temp("movcat_test_index_stss.mov", &data);
+
+        assert!(build(&path).is_err());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_build_index_without_video() {
+        let path = write_temp("movcat_test_index_audio.mov", &atom(b"moov", &[]));
+        assert!(build(&path).is_err());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_sidecar_path() {
+        assert_eq!(sidecar_path(Path::new("out/day1.mov")), PathBuf::from("out/day1.mov.movcat-index.json"));
+    }
+}
@@ -0,0 +1,270 @@
+//! Job files: a TOML description of a complete assembly.
+//!
+//! A job lists named inputs with optional trims and timeline offsets, the
+//! order to join them in,
+//! chapters, metadata and output options, so a repeatable edit can be kept
+//! in one file and run with `movcat run job.toml`.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Job {
+    pub output: PathBuf,
+    #[serde(default)]
+    pub faststart: bool,
+    #[serde(default)]
+    pub order: Vec<String>,
+    #[serde(default)]
+    pub metadata: BTreeMap<String, String>,
+    #[serde(default, rename = "input")]
+    pub inputs: Vec<JobInput>,
+    #[serde(default, rename = "chapter")]
+    pub chapters: Vec<Chapter>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JobInput {
+    pub name: String,
+    pub path: PathBuf,
+    /// Seconds to skip at the start of the clip.
+    pub trim_start: Option<f64>,
+    /// Position in seconds (from the start of the clip) where the clip ends.
+    pub trim_end: Option<f64>,
+    /// Start of the recording on a shared timeline in seconds, as with
+    /// `--offsets`. Either every input in the order has one or none has.
+    pub offset: Option<f64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Chapter {
+    pub title: String,
+    /// Start of the chapter in seconds on the output timeline.
+    pub start: f64,
+}
+
+/// Reads a job file, resolving relative paths against its directory.
+pub fn load(path: &Path) -> Result<Job> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read job file: {:?}", path))?;
+    let mut job = parse(&content)
+        .with_context(|| format!("Invalid job file: {:?}", path))?;
+
+    let base = path.parent().unwrap_or(Path::new(""));
+    job.output = base.join(&job.output);
+    for input in &mut job.inputs {
+        input.path = base.join(&input.path);
+    }
+    Ok(job)
+}
+
+fn parse(content: &str) -> Result<Job> {
+    let job: Job = toml::from_str(content)?;
+
+    if job.inputs.is_empty() {
+        anyhow::bail!("Job has no inputs");
+    }
+
+    for (i, input) in job.inputs.iter().enumerate() {
+        if job.inputs[..i].iter().any(|other| other.name == input.name) {
+            anyhow::bail!("Duplicate input name: {}", input.name);
+        }
+        if input.trim_start.is_some_and(|t| t < 0.0) || input.trim_end.is_some_and(|t| t <= 0.0) {
+            anyhow::bail!("Trim points of input '{}' must be positive", input.name);
+        }
+        if input.offset.is_some_and(|offset| !offset.is_finite()) {
+            anyhow::bail!("Offset of input '{}' is not a number of seconds", input.name);
+        }
+        if let (Some(start), Some(end)) = (input.trim_start, input.trim_end) {
+            if start >= end {
+                anyhow::bail!("trim_start must be before trim_end for input '{}'", input.name);
+            }
+        }
+    }
+
+    for pair in job.chapters.windows(2) {
+        if pair[1].start <= pair[0].start {
+            anyhow::bail!("Chapters must be in increasing start order ('{}' does not start after '{}')",
+                pair[1].title, pair[0].title);
+        }
+    }
+    if job.chapters.first().is_some_and(|c| c.start < 0.0) {
+        anyhow::bail!("Chapter start times must not be negative");
+    }
+
+    Ok(job)
+}
+
+impl Job {
+    /// Returns the inputs in assembly order.
+    ///
+    /// Without an explicit `order`, inputs are joined in the order they are
+    /// declared. Names may be repeated in `order` to reuse a clip.
+    pub fn ordered_inputs(&self) -> Result<Vec<&JobInput>> {
+        if self.order.is_empty() {
+            return Ok(self.inputs.iter().collect());
+        }
+
+        let mut ordered = Vec::new();
+        for name in &self.order {
+            let input = self.inputs.iter().find(|input| &input.name == name)
+                .with_context(|| format!("Unknown input in order: {}", name))?;
+            ordered.push(input);
+        }
+
+        for input in &self.inputs {
+            if !self.order.contains(&input.name) {
+                println!("Warning: Input '{}' is not listed in order and will be skipped", input.name);
+            }
+        }
+
+        Ok(ordered)
+    }
+
+    /// The timeline offsets of the ordered inputs, if the job gives them.
+    pub fn offsets(inputs: &[&JobInput]) -> Result<Option<Vec<f64>>> {
+        let missing: Vec<&str> = inputs.iter().filter(|input| input.offset.is_none()).map(|input| input.name.as_str()).collect();
+        if missing.len() == inputs.len() {
+            return Ok(None);
+        }
+        if !missing.is_empty() {
+            anyhow::bail!("No offset given for input(s) {}; either every input has an offset or none has", missing.join(", "));
+        }
+        Ok(Some(inputs.iter().filter_map(|input| input.offset).collect()))
+    }
+}
+
+/// Renders chapters as an FFmpeg metadata file.
+///
+/// Each chapter ends where the next begins; the last one ends at `total`
+/// seconds.
+pub fn ffmetadata_chapters(chapters: &[Chapter], total: f64) -> Result<String> {
+    let mut content = String::from(";FFMETADATA1\n");
+    for (i, chapter) in chapters.iter().enumerate() {
+        let end = chapters.get(i + 1).map_or(total, |next| next.start);
+        if chapter.start >= end {
+            anyhow::bail!("Chapter '{}' starts after the end of the output ({:.3}s)", chapter.title, total);
+        }
+        content.push_str("[CHAPTER]\nTIMEBASE=1/1000\n");
+        content.push_str(&format!("START={}\n", (chapter.start * 1000.0).round() as u64));
+        content.push_str(&format!("END={}\n", (end * 1000.0).round() as u64));
+        content.push_str(&format!("title={}\n", escape_ffmetadata(&chapter.title)));
+    }
+    Ok(content)
+}
+
+fn escape_ffmetadata(value: &str) -> String {
+    let mut escaped = String::new();
+    for c in value.chars() {
+        if matches!(c, '=' | ';' | '#' | '\\' | '\n') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const JOB: &str = r#"
+        output = "final.mov"
+        faststart = true
+        order = ["intro", "body", "intro"]
+
+        [metadata]
+        title = "Episode 12"
+
+        [[input]]
+        name = "body"
+        path = "raw/body.mov"
+        trim_start = 2.5
+        trim_end = 60
+
+        [[input]]
+        name = "intro"
+        path = "intro.mov"
+
+        [[chapter]]
+        title = "Opening"
+        start = 0
+
+        [[chapter]]
+        title = "Main"
+        start = 10.5
+    "#;
+
+    #[test]
+    fn test_parse_job() {
+        let job = parse(JOB).unwrap();
+        assert_eq!(job.output, PathBuf::from("final.mov"));
+        assert!(job.faststart);
+        assert_eq!(job.metadata.get("title").unwrap(), "Episode 12");
+        assert_eq!(job.inputs[0].trim_start, Some(2.5));
+        assert_eq!(job.inputs[0].trim_end, Some(60.0));
+        assert_eq!(job.chapters.len(), 2);
+
+        let names: Vec<_> = job.ordered_inputs().unwrap().iter().map(|i| i.name.as_str()).collect();
+        assert_eq!(names, vec!["intro", "body", "intro"]);
+    }
+
+    #[test]
+    fn test_parse_job_declaration_order() {
+        let job = parse("output = \"o.mov\"\n[[input]]\nname = \"b\"\npath = \"b.mov\"\n[[input]]\nname = \"a\"\npath = \"a.mov\"\n").unwrap();
+        let names: Vec<_> = job.ordered_inputs().unwrap().iter().map(|i| i.name.as_str()).collect();
+        assert_eq!(names, vec!["b", "a"]);
+    }
+
+    #[test]
+    fn test_parse_job_errors() {
+        // No inputs
+        assert!(parse("output = \"o.mov\"").is_err());
+        // Unknown field
+        assert!(parse("output = \"o.mov\"\nspeed = 2\n[[input]]\nname = \"a\"\npath = \"a.mov\"").is_err());
+        // Duplicate names
+        assert!(parse("output = \"o.mov\"\n[[input]]\nname = \"a\"\npath = \"a.mov\"\n[[input]]\nname = \"a\"\npath = \"b.mov\"").is_err());
+        // Inverted trims
+        assert!(parse("output = \"o.mov\"\n[[input]]\nname = \"a\"\npath = \"a.mov\"\ntrim_start = 5\ntrim_end = 4").is_err());
+    }
+
+    #[test]
+    fn test_job_offsets() {
+        let job = parse("output = \"o.mov\"\n[[input]]\nname = \"a\"\npath = \"a.mov\"\noffset = 12.5\n\
+            [[input]]\nname = \"b\"\npath = \"b.mov\"\noffset = -1\n").unwrap();
+        assert_eq!(Job::offsets(&job.ordered_inputs().unwrap()).unwrap(), Some(vec![12.5, -1.0]));
+
+        let job = parse(JOB).unwrap();
+        assert_eq!(Job::offsets(&job.ordered_inputs().unwrap()).unwrap(), None);
+
+        // Offsets for only some inputs
+        let job = parse("output = \"o.mov\"\n[[input]]\nname = \"a\"\npath = \"a.mov\"\noffset = 1\n\
+            [[input]]\nname = \"b\"\npath = \"b.mov\"\n").unwrap();
+        assert!(Job::offsets(&job.ordered_inputs().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_ordered_inputs_unknown_name() {
+        let job = parse("output = \"o.mov\"\norder = [\"x\"]\n[[input]]\nname = \"a\"\npath = \"a.mov\"").unwrap();
+        assert!(job.ordered_inputs().is_err());
+    }
+
+    #[test]
+    fn test_ffmetadata_chapters() {
+        let chapters = vec![
+            Chapter { title: "Intro".to_string(), start: 0.0 },
+            Chapter { title: "Q&A; part=2".to_string(), start: 12.25 },
+        ];
+        let content = ffmetadata_chapters(&chapters, 30.0).unwrap();
+        assert_eq!(content, ";FFMETADATA1\n\
+            [CHAPTER]\nTIMEBASE=1/1000\nSTART=0\nEND=12250\ntitle=Intro\n\
+            [CHAPTER]\nTIMEBASE=1/1000\nSTART=12250\nEND=30000\ntitle=Q&A\\; part\\=2\n");
+
+        assert!(ffmetadata_chapters(&chapters, 10.0).is_err());
+    }
+}
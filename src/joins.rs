@@ -0,0 +1,235 @@
+//! Timing of the joins between clips.
+//!
+//! FFmpeg's concat demuxer starts each clip where the previous one ends
+//! according to its container duration, which the mvhd stores in a coarse
+//! timescale (typically 1/600 s). For 29.97 fps footage that duration is
+//! already rounded, and the rounding adds up over many joins. Instead,
+//! movcat derives every clip's duration from the exact track duration on
+//! the output track's timescale and rounds the *cumulative* position (error
+//! diffusion), so no join drifts by more than half a tick.
+
+use crate::movie::Track;
+use crate::MovInfo;
+use clap::ValueEnum;
+
+/// What to put into recording gaps between clips. Only part of the
+/// planned modes exist: black and slate fillers are not implemented, as
+/// they would have to be encoded to match the inputs. Audio is not padded
+/// and stays silent during a gap.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum FillGaps {
+    /// Join clips back to back
+    #[default]
+    None,
+    /// Hold the last frame before a gap for the gap's duration
+    Freeze,
+}
+
+#[derive(Debug)]
+pub struct JoinTiming {
+    /// Ticks per second of the track the joins are aligned to.
+    pub timescale: u32,
+    /// Duration in microseconds to pass for every input but the last.
+    pub durations_us: Vec<u64>,
+    /// Largest drift of a join from its exact position, in seconds, when
+    /// FFmpeg derives the durations itself.
+    pub uncorrected_error: f64,
+    /// Largest drift with the diffused durations, in seconds.
+    pub corrected_error: f64,
+}
+
+/// The track a clip's timing is judged by: the first video track, or the
+/// first audio track of audio-only clips.
+fn reference_track(info: &MovInfo) -> Option<&Track> {
+    info.tracks.iter().find(|t| t.is_video())
+        .or_else(|| info.tracks.iter().find(|t| t.is_audio()))
+}
+
+/// Exact duration, in ticks of `timescale`, of the part of a clip that
+/// ends up in the output. An edit list that changes the timing decides
+/// what FFmpeg plays, so it takes precedence over the media duration.
+fn exact_duration_ticks(info: &MovInfo, timescale: u32) -> f64 {
+    let timescale = timescale as f64;
+    let track = reference_track(info);
+    let full = match (track, track.and_then(|t| t.edited_duration_secs(info.timescale))) {
+        (_, Some(edited)) => edited * timescale,
+        (Some(track), None) if track.timescale > 0 => track.media_duration as f64 * timescale / track.timescale as f64,
+        _ => info.duration as f64 * timescale / info.timescale as f64,
+    };
+    if info.trim_start.is_none() && info.trim_end.is_none() {
+        return full;
+    }
+    let end = info.trim_end.map_or(full, |end| (end * timescale).min(full));
+    (end - info.trim_start.unwrap_or(0.0) * timescale).max(0.0)
+}
+
+/// Plans the join durations; `None` for a single input.
+pub fn plan(infos: &[MovInfo]) -> Option<JoinTiming> {
+    if infos.len() < 2 {
+        return None;
+    }
+
+    let timescale = reference_track(&infos[0])
+        .map(|t| t.timescale)
+        .filter(|&t| t > 0)
+        .unwrap_or(infos[0].timescale);
+    let to_us = |ticks: f64| (ticks * 1_000_000.0 / timescale as f64).round() as u64;
+
+    let mut durations_us = Vec::new();
+    let (mut uncorrected_error, mut corrected_error) = (0.0f64, 0.0f64);
+    let (mut exact_end, mut uncorrected_end_us, mut previous_us) = (0.0, 0u64, 0u64);
+
+    for info in &infos[..infos.len() - 1] {
+        exact_end += exact_duration_ticks(info, timescale);
+
+        // FFmpeg: container durations in microseconds, summed, then
+        // rescaled to the track timescale
+        uncorrected_end_us += (info.used_duration_secs() * 1_000_000.0).round() as u64;
+        let uncorrected = (uncorrected_end_us as f64 * timescale as f64 / 1_000_000.0).round();
+        uncorrected_error = uncorrected_error.max((uncorrected - exact_end).abs());
+
+        // Error diffusion: round the cumulative position, not each clip
+        let corrected = exact_end.round();
+        corrected_error = corrected_error.max((corrected - exact_end).abs());
+        let end_us = to_us(corrected);
+        durations_us.push(end_us - previous_us);
+        previous_us = end_us;
+    }
+
+    Some(JoinTiming {
+        timescale,
+        durations_us,
+        uncorrected_error: uncorrected_error / timescale as f64,
+        corrected_error: corrected_error / timescale as f64,
+    })
+}
+
+/// Recording gaps after every input but the last, in microseconds: the
+/// time from the end of a clip to the creation time of the next one.
+///
+/// Creation times have a resolution of one second, so shorter gaps, gaps
+/// of clips without a creation time and overlaps count as zero.
+pub fn recording_gaps_us(infos: &[MovInfo]) -> Vec<u64> {
+    infos.windows(2)
+        .map(|pair| {
+            let (Some(start), Some(next_start)) = (pair[0].creation_time, pair[1].creation_time) else {
+                return 0;
+            };
+            let end_us = start as f64 * 1_000_000.0 + (pair[0].used_duration_secs() * 1_000_000.0).round();
+            let gap_us = next_start as f64 * 1_000_000.0 - end_us;
+            if gap_us >= 1_000_000.0 { gap_us as u64 } else { 0 }
+        })
+        .collect()
+}
+
+impl JoinTiming {
+    /// Lengthens the clips before recording gaps. The concat demuxer then
+    /// starts the next clip later, and the muxer gives the last frame
+    /// before the gap the gap's duration: a freeze frame, without
+    /// re-encoding.
+    pub fn fill_gaps(&mut self, gaps_us: &[u64]) {
+        for (duration, gap) in self.durations_us.iter_mut().zip(gaps_us) {
+            *duration += gap;
+        }
+    }
+}
+
+/// Formats microseconds as seconds for the concat demuxer, without
+/// trailing zeros.
+pub fn format_us(us: u64) -> String {
+    let fraction = us % 1_000_000;
+    if fraction == 0 {
+        return (us / 1_000_000).to_string();
+    }
+    let fraction = format!("{:06}", fraction);
+    format!("{}.{}", us / 1_000_000, fraction.trim_end_matches('0'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::movie::tests::{movie_file, write_temp, TestTrack};
+
+    /// A 29.97 fps clip of 10.01 s: 300 frames of 1001 ticks at 30000,
+    /// which the 1/600 s mvhd can only store as 10.0166... or 10.0 s.
+    fn ntsc_clip(name: &str) -> MovInfo {
+        let video = TestTrack { timescale: 30000, media_duration: 300300, ..TestTrack::new(1, b"vide") };
+        let path = write_temp(name, &movie_file(None, &[video.build()]));
+        let info = crate::analyze_mov_file(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        info
+    }
+
+    #[test]
+    fn test_plan_single_input() {
+        assert!(plan(&[ntsc_clip("movcat_test_joins_single.mov")]).is_none());
+    }
+
+    #[test]
+    fn test_plan_diffuses_rounding() {
+        // mvhd says 6000/600 = 10 s, the video track 10.01 s
+        let infos: Vec<MovInfo> = (0..100).map(|i| ntsc_clip(&format!("movcat_test_joins_{}.mov", i))).collect();
+        let timing = plan(&infos).unwrap();
+        assert_eq!(timing.timescale, 30000);
+        assert_eq!(timing.durations_us.len(), 99);
+        assert!(timing.durations_us.iter().all(|&d| d == 10_010_000));
+
+        // 99 joins of 10 ms each without correction
+        assert!((timing.uncorrected_error - 0.99).abs() < 1e-9);
+        assert_eq!(timing.corrected_error, 0.0);
+    }
+
+    #[test]
+    fn test_plan_bounds_cumulative_error() {
+        // 1/3 s per clip does not fit a 1/1000 s grid; the cumulative
+        // positions still never drift by more than half a tick
+        let mut infos: Vec<MovInfo> = (0..10).map(|i| ntsc_clip(&format!("movcat_test_joins_third_{}.mov", i))).collect();
+        for info in &mut infos {
+            info.tracks[0].timescale = 3000;
+            info.tracks[0].media_duration = 1000;
+        }
+        infos[0].tracks[0].timescale = 1000;
+        infos[0].tracks[0].media_duration = 333;
+        let timing = plan(&infos).unwrap();
+        assert_eq!(timing.timescale, 1000);
+        assert!(timing.corrected_error <= 0.0005 + 1e-12);
+        let total: u64 = timing.durations_us.iter().sum();
+        assert_eq!(total, 3_000_000);
+    }
+
+    #[test]
+    fn test_plan_follows_edit_list() {
+        // The first clip's edit list starts 0.5 s into the media and shows
+        // 9 s of it (5400 ticks at 600); FFmpeg plays those 9 s
+        let mut infos = vec![ntsc_clip("movcat_test_joins_edit_1.mov"), ntsc_clip("movcat_test_joins_edit_2.mov")];
+        infos[0].tracks[0].edits = vec![crate::movie::Edit { segment_duration: 5400, media_time: 15000 }];
+        let timing = plan(&infos).unwrap();
+        assert_eq!(timing.durations_us, vec![9_000_000]);
+
+        // An edit list that presents the media unchanged keeps its exact duration
+        infos[0].tracks[0].edits = vec![crate::movie::Edit { segment_duration: 6006, media_time: 0 }];
+        assert_eq!(plan(&infos).unwrap().durations_us, vec![10_010_000]);
+    }
+
+    #[test]
+    fn test_recording_gaps() {
+        let mut infos: Vec<MovInfo> = (0..4).map(|i| ntsc_clip(&format!("movcat_test_joins_gap_{}.mov", i))).collect();
+        // 10 s clips: back to back, a 50 s gap, then no creation time
+        for (info, created) in infos.iter_mut().zip([Some(1000), Some(1010), Some(1070), None]) {
+            info.creation_time = created;
+        }
+        let gaps = recording_gaps_us(&infos);
+        assert_eq!(gaps, vec![0, 50_000_000, 0]);
+
+        let mut timing = plan(&infos).unwrap();
+        timing.fill_gaps(&gaps);
+        assert_eq!(timing.durations_us, vec![10_010_000, 60_010_000, 10_010_000]);
+    }
+
+    #[test]
+    fn test_format_us() {
+        assert_eq!(format_us(10_000_000), "10");
+        assert_eq!(format_us(10_010_000), "10.01");
+        assert_eq!(format_us(333_333), "0.333333");
+    }
+}
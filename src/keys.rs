@@ -0,0 +1,244 @@
+//! QuickTime metadata keys (`moov/meta` with `keys` and `ilst`).
+//!
+//! Cameras and phones store free-form metadata under reverse-DNS keys such
+//! as `com.apple.quicktime.location.ISO6709` or
+//! `com.apple.quicktime.content.identifier` (live photo pairing). FFmpeg
+//! only writes such keys when asked to, so by default a merge loses them.
+//! `--keys` rules decide, per key namespace, which keys of the first input
+//! are carried over and which values are overridden.
+
+use crate::atoms::{self, ByteReader};
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeyRule {
+    /// Keep keys starting with the prefix; once any keep rule is given,
+    /// keys matching none are dropped.
+    Keep(String),
+    /// Drop keys starting with the prefix.
+    Drop(String),
+    /// Set a key, adding it if the input does not have it.
+    Set(String, String),
+}
+
+impl KeyRule {
+    /// Parses `keep:PREFIX`, `drop:PREFIX` or `set:KEY=VALUE`. A prefix of
+    /// `*` matches every key.
+    pub fn parse(rule: &str) -> Result<KeyRule, String> {
+        let (action, argument) = rule.split_once(':')
+            .ok_or_else(|| format!("'{}' is not a key rule (expected keep:PREFIX, drop:PREFIX or set:KEY=VALUE)", rule))?;
+        let prefix = || match argument {
+            "" => Err(format!("'{}' has no key prefix", rule)),
+            "*" => Ok(String::new()),
+            prefix => Ok(prefix.to_string()),
+        };
+        match action {
+            "keep" => Ok(KeyRule::Keep(prefix()?)),
+            "drop" => Ok(KeyRule::Drop(prefix()?)),
+            "set" => match argument.split_once('=') {
+                Some((key, value)) if !key.is_empty() => Ok(KeyRule::Set(key.to_string(), value.to_string())),
+                _ => Err(format!("'{}' must have the form set:KEY=VALUE", rule)),
+            },
+            _ => Err(format!("unknown key rule action '{}' (expected keep, drop or set)", action)),
+        }
+    }
+}
+
+/// Reads the text-valued metadata keys of a file, in stored order.
+pub fn read(path: &Path) -> Result<Vec<(String, String)>> {
+    let mut file = File::open(path)
+        .with_context(|| format!("Failed to open file: {:?}", path))?;
+    let top_level = atoms::top_level_atoms(&mut file)
+        .with_context(|| format!("Failed to read atoms of {:?}", path))?;
+    let moov = top_level.iter().find(|a| &a.kind == b"moov")
+        .with_context(|| format!("No moov atom found in {:?}", path))?;
+    let data = atoms::read_payload(&mut file, moov)?;
+
+    match atoms::find_child(&data, b"meta")? {
+        Some(meta) => parse_meta(meta).with_context(|| format!("Failed to read metadata keys of {:?}", path)),
+        None => Ok(Vec::new()),
+    }
+}
+
+fn parse_meta(meta: &[u8]) -> Result<Vec<(String, String)>> {
+    // QuickTime writes meta as a plain atom, ISO-BMFF as a full atom
+    let meta = if meta.get(4..8) == Some(b"hdlr") { meta } else { meta.get(4..).unwrap_or_default() };
+
+    let Some(keys) = atoms::find_child(meta, b"keys")? else {
+        return Ok(Vec::new());
+    };
+    let mut reader = ByteReader::new(keys);
+    reader.version_and_flags()?;
+    let mut names = Vec::new();
+    for _ in 0..reader.u32()? {
+        let size = reader.u32()? as usize;
+        let _namespace = reader.fourcc()?;
+        let name = reader.bytes(size.saturating_sub(8))?;
+        names.push(String::from_utf8_lossy(name).into_owned());
+    }
+
+    let mut values = Vec::new();
+    let Some(ilst) = atoms::find_child(meta, b"ilst")? else {
+        return Ok(values);
+    };
+    // Items are named by the 1-based index of their key
+    for (kind, item) in atoms::children(ilst)? {
+        let index = u32::from_be_bytes(kind) as usize;
+        let Some(name) = index.checked_sub(1).and_then(|i| names.get(i)) else {
+            continue;
+        };
+        let Some(data) = atoms::find_child(item, b"data")? else {
+            continue;
+        };
+        if let Some(value) = data_value(data) {
+            values.push((name.clone(), value));
+        }
+    }
+    Ok(values)
+}
+
+/// Renders a `data` atom as text; binary values (cover art, ...) are
+/// skipped.
+fn data_value(data: &[u8]) -> Option<String> {
+    let mut reader = ByteReader::new(data);
+    let data_type = reader.u32().ok()? & 0x00ff_ffff;
+    reader.skip(4).ok()?;
+    let value = reader.bytes(reader.remaining()).ok()?;
+    Some(match (data_type, value.len()) {
+        (1, _) => String::from_utf8_lossy(value).into_owned(),
+        (21, 1) => (value[0] as i8).to_string(),
+        (21, 2) => i16::from_be_bytes([value[0], value[1]]).to_string(),
+        (21, 4) => i32::from_be_bytes([value[0], value[1], value[2], value[3]]).to_string(),
+        (22, 1) => value[0].to_string(),
+        (22, 2) => u16::from_be_bytes([value[0], value[1]]).to_string(),
+        (22, 4) => u32::from_be_bytes([value[0], value[1], value[2], value[3]]).to_string(),
+        (23, 4) => f32::from_be_bytes([value[0], value[1], value[2], value[3]]).to_string(),
+        _ => return None,
+    })
+}
+
+/// Applies the rules to the keys of the first input.
+///
+/// The keep or drop rule with the longest matching prefix decides about a
+/// key (the later one on a tie), so `keep:com.apple.quicktime` combined
+/// with `drop:com.apple.quicktime.location` keeps everything but the
+/// location. Set rules are applied last.
+pub fn apply(keys: &[(String, String)], rules: &[KeyRule]) -> Vec<(String, String)> {
+    let any_keep = rules.iter().any(|rule| matches!(rule, KeyRule::Keep(_)));
+
+    let mut result: Vec<(String, String)> = keys.iter()
+        .filter(|(key, _)| {
+            let decision = rules.iter()
+                .filter_map(|rule| match rule {
+                    KeyRule::Keep(prefix) if key.starts_with(prefix.as_str()) => Some((prefix.len(), true)),
+                    KeyRule::Drop(prefix) if key.starts_with(prefix.as_str()) => Some((prefix.len(), false)),
+                    _ => None,
+                })
+                .max_by_key(|&(length, _)| length);
+            decision.map_or(!any_keep, |(_, keep)| keep)
+        })
+        .cloned()
+        .collect();
+
+    for rule in rules {
+        if let KeyRule::Set(key, value) = rule {
+            match result.iter_mut().find(|(k, _)| k == key) {
+                Some(entry) => entry.1 = value.clone(),
+                None => result.push((key.clone(), value.clone())),
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atoms::tests::atom;
+    use crate::movie::tests::{full_atom, write_temp};
+
+    fn strings(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    /// A QuickTime-style meta atom with UTF-8 and integer values.
+    fn meta(entries: &[(&str, u32, &[u8])]) -> Vec<u8> {
+        let mut keys = vec![0, 0, 0, 0];
+        keys.extend_from_slice(&(entries.len() as u32).to_be_bytes());
+        let mut ilst = Vec::new();
+        for (i, (name, data_type, value)) in entries.iter().enumerate() {
+            keys.extend_from_slice(&((name.len() + 8) as u32).to_be_bytes());
+            keys.extend_from_slice(b"mdta");
+            keys.extend_from_slice(name.as_bytes());
+
+            let mut data = data_type.to_be_bytes().to_vec();
+            data.extend_from_slice(&[0; 4]);
+            data.extend_from_slice(value);
+            ilst.extend(atom(&(i as u32 + 1).to_be_bytes(), &atom(b"data", &data)));
+        }
+
+        let mut hdlr = vec![0u8; 8];
+        hdlr.extend_from_slice(b"mdta");
+        hdlr.extend_from_slice(&[0; 12]);
+        let mut payload = atom(b"hdlr", &hdlr);
+        payload.extend(atom(b"keys", &keys));
+        payload.extend(atom(b"ilst", &ilst));
+        atom(b"meta", &payload)
+    }
+
+    #[test]
+    fn test_parse_key_rule() {
+        assert_eq!(KeyRule::parse("keep:com.apple.quicktime").unwrap(), KeyRule::Keep("com.apple.quicktime".to_string()));
+        assert_eq!(KeyRule::parse("drop:*").unwrap(), KeyRule::Drop(String::new()));
+        assert_eq!(KeyRule::parse("set:com.example.shot=A=1").unwrap(),
+            KeyRule::Set("com.example.shot".to_string(), "A=1".to_string()));
+        assert!(KeyRule::parse("keep").is_err());
+        assert!(KeyRule::parse("keep:").is_err());
+        assert!(KeyRule::parse("set:novalue").is_err());
+        assert!(KeyRule::parse("rename:a").is_err());
+    }
+
+    #[test]
+    fn test_read_keys() {
+        let mut moov = full_atom(b"mvhd", 0, &[0, 0, 600, 600]);
+        moov.extend(meta(&[
+            ("com.apple.quicktime.make", 1, b"Apple"),
+            ("com.apple.quicktime.location.accuracy.horizontal", 22, &[0, 35]),
+            ("com.apple.quicktime.artwork", 13, &[0xff, 0xd8]),
+        ]));
+        let path = write_temp("movcat_test_keys.mov", &atom(b"moov", &moov));
+
+        let keys = read(&path).unwrap();
+        assert_eq!(keys, strings(&[
+            ("com.apple.quicktime.make", "Apple"),
+            ("com.apple.quicktime.location.accuracy.horizontal", "35"),
+        ]));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_apply_rules() {
+        let keys = strings(&[
+            ("com.apple.quicktime.make", "Apple"),
+            ("com.apple.quicktime.location.ISO6709", "+35.6+139.7/"),
+            ("com.apple.quicktime.content.identifier", "ABC"),
+            ("com.example.camera", "B"),
+        ]);
+        let parse = |rules: &[&str]| rules.iter().map(|r| KeyRule::parse(r).unwrap()).collect::<Vec<_>>();
+
+        // Only drop rules: everything else is kept
+        assert_eq!(apply(&keys, &parse(&["drop:com.apple.quicktime.location"])).len(), 3);
+
+        // Keep rules turn the list into an allowlist; longer prefixes win
+        let result = apply(&keys, &parse(&["keep:com.apple.quicktime", "drop:com.apple.quicktime.location"]));
+        assert_eq!(result, strings(&[
+            ("com.apple.quicktime.make", "Apple"),
+            ("com.apple.quicktime.content.identifier", "ABC"),
+        ]));
+
+        let result = apply(&keys, &parse(&["drop:*", "keep:com.example", "set:com.example.camera=A", "set:com.example.reel=7"]));
+        assert_eq!(result, strings(&[("com.example.camera", "A"), ("com.example.reel", "7")]));
+    }
+}
@@ -0,0 +1,220 @@
+//! One-line-per-file listing of a directory of media (`movcat ls`).
+//!
+//! The quick triage before a merge: what is in a card dump, which clips
+//! are the odd ones out, what was recorded when. Everything comes from the
+//! movie header, so even a large directory lists in moments.
+
+use crate::movie;
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use std::cmp::Ordering;
+use std::path::{Path, PathBuf};
+
+/// Extensions of the files that are listed.
+const MEDIA_EXTENSIONS: &[&str] = &["mov", "mp4", "m4v", "m4a", "3gp"];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Column {
+    #[default]
+    Name,
+    Duration,
+    Codec,
+    Resolution,
+    Fps,
+    Size,
+    Created,
+}
+
+#[derive(Debug)]
+pub struct Entry {
+    pub path: PathBuf,
+    /// File size in bytes.
+    pub size: u64,
+    /// Duration in seconds; `None` if the file could not be analyzed.
+    pub duration: Option<f64>,
+    /// Codec of the first video track, or of the first audio track.
+    pub codec: Option<String>,
+    /// Display size of the first video track.
+    pub resolution: Option<(u32, u32)>,
+    pub fps: Option<f64>,
+    /// Seconds since 1904-01-01 UTC.
+    pub created: Option<u64>,
+    /// Why the file could not be analyzed.
+    pub error: Option<String>,
+}
+
+/// Analyzes the media files of a directory, in name order.
+pub fn list(dir: &Path) -> Result<Vec<Entry>> {
+    let mut paths = Vec::new();
+    for entry in std::fs::read_dir(dir).with_context(|| format!("Failed to read directory: {:?}", dir))? {
+        let path = entry?.path();
+        let is_media = path.extension()
+            .is_some_and(|ext| MEDIA_EXTENSIONS.contains(&ext.to_string_lossy().to_lowercase().as_str()));
+        if is_media && path.is_file() {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths.into_iter().map(|path| analyze(&path)).collect())
+}
+
+fn analyze(path: &Path) -> Entry {
+    let mut entry = Entry {
+        path: path.to_path_buf(),
+        size: std::fs::metadata(path).map_or(0, |m| m.len()),
+        duration: None,
+        codec: None,
+        resolution: None,
+        fps: None,
+        created: None,
+        error: None,
+    };
+    let info = match crate::analyze_mov_file(path) {
+        Ok(info) => info,
+        Err(e) => {
+            entry.error = Some(format!("{:#}", e));
+            return entry;
+        }
+    };
+
+    let video = info.tracks.iter().find(|t| t.is_video());
+    let track = video.or_else(|| info.tracks.iter().find(|t| t.is_audio()));
+    entry.duration = Some(info.duration_secs());
+    entry.codec = track.map(|t| movie::codec_name(&t.codec)
+        .map_or_else(|| crate::atoms::fourcc_to_string(&t.codec).trim_end().to_string(), str::to_string));
+    entry.resolution = video.map(|t| t.display_size()).filter(|&(w, h)| w > 0 && h > 0);
+    entry.fps = video.and_then(|t| t.frame_rate());
+    entry.created = info.creation_time;
+    entry
+}
+
+/// Sorts by a column; files without a value for it go last either way.
+pub fn sort(entries: &mut [Entry], column: Column, reverse: bool) {
+    fn by<T: PartialOrd>(a: Option<T>, b: Option<T>, reverse: bool) -> Ordering {
+        match (a, b) {
+            (Some(a), Some(b)) => {
+                let ordering = a.partial_cmp(&b).unwrap_or(Ordering::Equal);
+                if reverse { ordering.reverse() } else { ordering }
+            }
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        }
+    }
+    let pixels = |entry: &Entry| entry.resolution.map(|(w, h)| w as u64 * h as u64);
+    entries.sort_by(|a, b| match column {
+        Column::Name => by(Some(&a.path), Some(&b.path), reverse),
+        Column::Duration => by(a.duration, b.duration, reverse),
+        Column::Codec => by(a.codec.as_ref(), b.codec.as_ref(), reverse),
+        Column::Resolution => by(pixels(a), pixels(b), reverse),
+        Column::Fps => by(a.fps, b.fps, reverse),
+        Column::Size => by(Some(a.size), Some(b.size), reverse),
+        Column::Created => by(a.created, b.created, reverse),
+    });
+}
+
+/// `1:02:03.500` style duration.
+fn format_duration(seconds: f64) -> String {
+    let millis = (seconds * 1000.0).round() as u64;
+    let (hours, minutes) = (millis / 3_600_000, millis / 60_000 % 60);
+    let seconds = millis % 60_000;
+    format!("{}:{:02}:{:02}.{:03}", hours, minutes, seconds / 1000, seconds % 1000)
+}
+
+/// Size with a binary unit, e.g. `1.5G`.
+fn format_size(bytes: u64) -> String {
+    let units = [("G", 1u64 << 30), ("M", 1 << 20), ("K", 1 << 10)];
+    match units.iter().find(|&&(_, unit)| bytes >= unit) {
+        Some((suffix, unit)) => format!("{:.1}{}", bytes as f64 / *unit as f64, suffix),
+        None => bytes.to_string(),
+    }
+}
+
+/// Prints the entries as aligned columns.
+pub fn print(entries: &[Entry]) {
+    let unknown = || "-".to_string();
+    let rows: Vec<[String; 7]> = entries.iter().map(|entry| [
+        entry.path.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+        entry.duration.map_or_else(unknown, format_duration),
+        entry.codec.clone().unwrap_or_else(unknown),
+        entry.resolution.map_or_else(unknown, |(w, h)| format!("{}x{}", w, h)),
+        entry.fps.map_or_else(unknown, |fps| format!("{:.2}", fps)),
+        format_size(entry.size),
+        entry.created.map_or_else(unknown, movie::format_timestamp),
+    ]).collect();
+
+    let header = ["NAME", "DURATION", "CODEC", "RESOLUTION", "FPS", "SIZE", "CREATED"];
+    let mut widths = header.map(str::len);
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    // Numbers are right-aligned
+    let line = |cells: [&str; 7]| {
+        let mut line = String::new();
+        for (i, (cell, width)) in cells.iter().zip(widths).enumerate() {
+            let cell = match i {
+                1 | 4 | 5 => format!("{:>width$}", cell, width = width),
+                _ => format!("{:<width$}", cell, width = width),
+            };
+            line.push_str(&cell);
+            line.push_str("  ");
+        }
+        line.trim_end().to_string()
+    };
+    println!("{}", line(header));
+    for (entry, row) in entries.iter().zip(&rows) {
+        match &entry.error {
+            // The reason takes the place of the media columns
+            Some(error) => println!("{:<width$}  error: {}", row[0], error, width = widths[0]),
+            None => println!("{}", line(row.each_ref().map(String::as_str))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::movie::tests::{movie_file, TestTrack};
+
+    #[test]
+    fn test_list_and_sort() {
+        let dir = std::env::temp_dir().join("movcat_test_ls");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let clip = |frames: u32| movie_file(None, &[TestTrack {
+            samples: frames, width: 1920, height: 1080, timescale: 15360, media_duration: frames * 512,
+            ..TestTrack::new(1, b"vide")
+        }.build()]);
+        std::fs::write(dir.join("a.mov"), clip(30)).unwrap();
+        std::fs::write(dir.join("b.MP4"), clip(60)).unwrap();
+        std::fs::write(dir.join("broken.mov"), b"not a movie").unwrap();
+        std::fs::write(dir.join("notes.txt"), b"ignored").unwrap();
+
+        let mut entries = list(&dir).unwrap();
+        let names = |entries: &[Entry]| entries.iter()
+            .map(|e| e.path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(names(&entries), ["a.mov", "b.MP4", "broken.mov"]);
+        assert_eq!(entries[0].codec.as_deref(), Some("h264"));
+        assert_eq!(entries[0].resolution, Some((1920, 1080)));
+        assert_eq!(entries[0].fps, Some(30.0));
+        assert!(entries[2].error.is_some());
+
+        // Files that could not be analyzed stay last
+        sort(&mut entries, Column::Size, true);
+        assert_eq!(names(&entries), ["b.MP4", "a.mov", "broken.mov"]);
+        sort(&mut entries, Column::Fps, true);
+        assert_eq!(names(&entries)[2], "broken.mov");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_format_columns() {
+        assert_eq!(format_duration(3723.5), "1:02:03.500");
+        assert_eq!(format_duration(9.9994), "0:00:09.999");
+        assert_eq!(format_size(512), "512");
+        assert_eq!(format_size(1536 * 1024 * 1024), "1.5G");
+    }
+}
@@ -1,18 +1,212 @@
-use clap::Parser;
+mod atoms;
+mod audio;
+mod backend;
+mod codec_config;
+mod fake;
+mod filter;
+mod group;
+mod index;
+mod job;
+mod joins;
+mod keys;
+mod ls;
+mod movie;
+mod offsets;
+mod plan;
+mod probe;
+mod process;
+mod profile;
+mod schedule;
+mod stereo;
+mod target;
+mod triage;
+mod trim;
+mod upload;
+mod verify;
+
+use clap::{Parser, Subcommand};
 use anyhow::{Result, Context};
-use std::path::PathBuf;
-use glob::glob;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use glob::{glob, MatchOptions, Pattern};
 
 #[derive(Parser)]
 #[command(name = "movcat")]
 #[command(about = "Lossless mov file concatenation tool")]
 #[command(long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     #[arg(help = "Input mov files or patterns to concatenate (supports wildcards)", required = true)]
     inputs: Vec<String>,
 
-    #[arg(short, long, help = "Output file path")]
-    output: PathBuf,
+    #[arg(short, long, help = "Output file path", required = true)]
+    output: Option<PathBuf>,
+
+    #[arg(short, long, global = true, help = "Show sample table details for inputs and output")]
+    verbose: bool,
+
+    #[arg(long, value_enum, global = true, default_value_t = backend::BackendChoice::Ffmpeg,
+        help = "Concatenation backend; it is probed against the inputs before copying")]
+    backend: backend::BackendChoice,
+
+    #[arg(long, value_name = "SIZE", global = true, value_parser = parse_size,
+        help = "Fail before copying if the output moov would exceed this size (e.g. 64M)")]
+    max_moov_size: Option<u64>,
+
+    #[arg(long, global = true, help = "Fail instead of warning when inputs mix portrait and landscape orientation")]
+    strict_orientation: bool,
+
+    #[arg(long, global = true, help = "Reorder inputs by creation time when it contradicts the given order")]
+    auto_reorder: bool,
+
+    #[arg(long, value_enum, global = true, default_value_t = joins::FillGaps::None,
+        help = "Fill recording gaps between clips (from their creation times) so the timeline matches wall-clock time; \
+            only freeze frames are implemented, black and slate fillers are not")]
+    fill_gaps: joins::FillGaps,
+
+    #[arg(long, value_name = "CSV", global = true,
+        help = "Place inputs on a synced timeline from file,offset rows (e.g. exported by a clap-sync tool)")]
+    offsets: Option<PathBuf>,
+
+    #[arg(long, value_enum, global = true,
+        help = "Check inputs and output against a player or NLE and adjust output options for it")]
+    target: Option<target::Target>,
+
+    #[arg(long, value_enum, conflicts_with = "also_output",
+        help = "Split the inputs into one merge per group, written as <output>-1.mov, <output>-2.mov, ...")]
+    group: Option<group::Grouping>,
+
+    #[arg(long, value_name = "EXPR", value_parser = filter::Filter::parse,
+        help = "Only use inputs matching all conditions, e.g. \"codec=hevc,height>=2160\" (repeatable)")]
+    filter: Vec<filter::Filter>,
+
+    #[arg(long, value_name = "FILE", global = true,
+        help = "Also write a faststart copy in another container (e.g. review.mp4) in the same pass")]
+    also_output: Option<PathBuf>,
+
+    #[arg(long = "keys", value_name = "RULE", global = true, value_parser = keys::KeyRule::parse,
+        help = "Carry over the first input's metadata keys: keep:PREFIX, drop:PREFIX or set:KEY=VALUE (repeatable)")]
+    key_rules: Vec<keys::KeyRule>,
+
+    #[arg(long, global = true, help = "Write a keyframe index sidecar (<output>.movcat-index.json) for fast seeking")]
+    index: bool,
+
+    #[arg(long, global = true, help = "Create the output directory if it does not exist")]
+    mkdirs: bool,
+
+    #[arg(long, value_name = "DIR", global = true,
+        help = "Move finished outputs into DIR only after they pass verification, for watch folders")]
+    publish_dir: Option<PathBuf>,
+
+    #[arg(long, value_name = "FILE", global = true,
+        help = "Record timing spans to a folded-stacks file, or a Chrome trace if FILE ends in .json")]
+    profile: Option<PathBuf>,
+
+    #[arg(long, global = true, help = "Never run external programs such as ffmpeg; features that need them fail")]
+    no_subprocess: bool,
+
+    #[arg(long, global = true, conflicts_with = "restart",
+        help = "Finish the output of an interrupted run instead of asking what to do")]
+    resume: bool,
+
+    #[arg(long, global = true, help = "Delete partial output of an interrupted run and start over without asking")]
+    restart: bool,
+
+    #[arg(long, value_name = "WINDOW", global = true, value_parser = schedule::Window::parse,
+        help = "Analyze right away but only start copying within a daily local time window, e.g. 02:00-06:00")]
+    schedule: Option<schedule::Window>,
+
+    #[arg(long, global = true, help = "Analyze right away but only start copying once the system load is low")]
+    when_idle: bool,
+
+    #[arg(long, global = true, help = "Leave out inputs that are not media files (empty files, .DS_Store, thumbnails) instead of failing")]
+    skip_bad: bool,
+
+    #[arg(long, global = true, help = "Decode the finished output once and report decode errors with the source clip they come from")]
+    verify_decode: bool,
+
+    #[arg(long, global = true, help = "Continue without requested options that are unavailable instead of failing")]
+    ignore_unavailable: bool,
+
+    #[arg(long, value_name = "URL", help = "Upload the finished output to s3://bucket/key or an http(s) URL")]
+    upload: Option<String>,
+
+    #[arg(long, requires = "upload",
+        help = "Accept an upload destination that reports no checksum after comparing only the size")]
+    upload_size_only: bool,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    #[command(about = "Run a job file describing inputs, trims, order, chapters and metadata")]
+    Run {
+        #[arg(help = "Job file (TOML)")]
+        job: PathBuf,
+
+        #[arg(long, help = "Analyze the inputs and preview where trims land, without writing anything")]
+        dry_run: bool,
+    },
+
+    #[command(about = "Resolve and analyze inputs without concatenating, optionally comparing with a saved plan")]
+    Plan {
+        #[arg(help = "Input mov files or patterns (supports wildcards)", required = true)]
+        inputs: Vec<String>,
+
+        #[arg(short, long, help = "Output file path the plan is for")]
+        output: Option<PathBuf>,
+
+        #[arg(long, help = "Save the plan as JSON")]
+        save: Option<PathBuf>,
+
+        #[arg(long, help = "Compare with a previously saved plan; exits with status 2 if it changed")]
+        diff: Option<PathBuf>,
+
+        #[arg(long, value_name = "EXPR", value_parser = filter::Filter::parse,
+            help = "Only use inputs matching all conditions (repeatable)")]
+        filter: Vec<filter::Filter>,
+    },
+
+    #[command(about = "Show what movcat reads from media files, as text or ffprobe-compatible JSON")]
+    Info {
+        #[arg(help = "Media files", required = true)]
+        files: Vec<PathBuf>,
+
+        #[arg(long, value_enum, default_value_t = InfoFormat::Text, help = "Output format")]
+        format: InfoFormat,
+    },
+
+    #[command(about = "List the media files of a directory, one line per file")]
+    Ls {
+        #[arg(help = "Directory to list", default_value = ".")]
+        dir: PathBuf,
+
+        #[arg(long, value_enum, default_value_t = ls::Column::Name, help = "Column to sort by")]
+        sort: ls::Column,
+
+        #[arg(long, help = "Sort in descending order")]
+        reverse: bool,
+    },
+
+    #[command(about = "Compare the codec configuration of two files track by track")]
+    Compare {
+        #[arg(help = "First file")]
+        first: PathBuf,
+
+        #[arg(help = "Second file")]
+        second: PathBuf,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum InfoFormat {
+    /// The file analysis printed before a merge
+    Text,
+    /// The JSON of `ffprobe -print_format json -show_format -show_streams`
+    FfprobeJson,
 }
 
 #[derive(Debug)]
@@ -24,23 +218,193 @@ struct MovInfo {
     track_count: usize,
     video_tracks: usize,
     audio_tracks: usize,
+    tracks: Vec<movie::Track>,
+    moov_size: u64,
+    mdat_regions: Vec<(u64, u64)>,
+    /// Seconds since 1904-01-01 UTC, if the file records it.
+    creation_time: Option<u64>,
+    trim_start: Option<f64>,
+    trim_end: Option<f64>,
+}
+
+impl MovInfo {
+    fn duration_secs(&self) -> f64 {
+        self.duration as f64 / self.timescale as f64
+    }
+
+    /// Duration of the part of the file that ends up in the output.
+    fn used_duration_secs(&self) -> f64 {
+        let end = self.trim_end.unwrap_or(f64::MAX).min(self.duration_secs());
+        (end - self.trim_start.unwrap_or(0.0)).max(0.0)
+    }
+}
+
+/// Options applied to the concatenated output.
+#[derive(Debug, Default)]
+struct OutputOptions {
+    metadata: Vec<(String, String)>,
+    chapters: Vec<job::Chapter>,
+    faststart: bool,
+    /// Refuse to write outputs whose moov is expected to exceed this size.
+    max_moov_size: Option<u64>,
+    /// Second container variant written in the same FFmpeg pass.
+    also_output: Option<PathBuf>,
+    /// Rules for the first input's metadata keys; none leaves them out.
+    key_rules: Vec<keys::KeyRule>,
+    /// Finish the partial outputs of an interrupted run instead of
+    /// running FFmpeg again.
+    resume: bool,
+    fill_gaps: joins::FillGaps,
+    /// Gaps after every input but the last from `--offsets`, in
+    /// microseconds; they replace the recording gaps of `--fill-gaps`.
+    timeline_gaps_us: Option<Vec<u64>>,
+    /// Tag HEVC video `hvc1` instead of keeping the input's `hev1`.
+    hvc1_tag: bool,
+}
+
+/// Moov size above which some players are known to fail to open a file.
+const MOOV_SIZE_WARNING: u64 = 64 * 1024 * 1024;
+
+const MOOV_SIZE_GUIDANCE: &str = "Some players fail to open files with very large headers. \
+    Consider splitting the merge into several outputs (for example one per hour).";
+
+/// Parses a byte size such as `512K`, `64M` or `1G`.
+fn parse_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let (number, multiplier) = match value.char_indices().last() {
+        Some((i, 'K' | 'k')) => (&value[..i], 1024),
+        Some((i, 'M' | 'm')) => (&value[..i], 1024 * 1024),
+        Some((i, 'G' | 'g')) => (&value[..i], 1024 * 1024 * 1024),
+        _ => (value, 1),
+    };
+    number.trim().parse::<u64>().ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .ok_or_else(|| format!("invalid size '{}' (expected e.g. 512K, 64M or 1G)", value))
 }
 
-fn analyze_mov_file(path: &PathBuf) -> Result<MovInfo> {
-    // Temporarily return dummy data
-    // TODO: Implement using the correct mp4 crate API
+/// Checks the expected output header size before copying.
+///
+/// The output's sample tables are roughly the input tables laid end to
+/// end, so the sum of the input moov sizes is a good estimate.
+fn check_estimated_moov_size(infos: &[MovInfo], limit: Option<u64>) -> Result<()> {
+    let estimated: u64 = infos.iter().map(|info| info.moov_size).sum();
+    match limit {
+        Some(limit) if estimated > limit => anyhow::bail!(
+            "Estimated output moov size ({} bytes) exceeds --max-moov-size ({} bytes). {}",
+            estimated, limit, MOOV_SIZE_GUIDANCE
+        ),
+        None if estimated > MOOV_SIZE_WARNING => {
+            println!("Warning: Estimated output moov size is {} bytes. {}", estimated, MOOV_SIZE_GUIDANCE);
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn analyze_mov_file(path: &Path) -> Result<MovInfo> {
+    let _span = profile::span(format!("analyze {}", path.display()));
+    let movie = movie::read_movie(path)?;
+
     Ok(MovInfo {
-        path: path.clone(),
-        duration: 0,
-        timescale: 1000,
-        major_brand: "mp4".to_string(),
-        track_count: 1,
-        video_tracks: 1,
-        audio_tracks: 0,
+        path: path.to_path_buf(),
+        duration: movie.duration,
+        timescale: movie.timescale,
+        major_brand: movie.major_brand,
+        track_count: movie.tracks.len(),
+        video_tracks: movie.tracks.iter().filter(|t| t.is_video()).count(),
+        audio_tracks: movie.tracks.iter().filter(|t| t.is_audio()).count(),
+        tracks: movie.tracks,
+        moov_size: movie.moov_size,
+        mdat_regions: movie.mdat_regions,
+        creation_time: Some(movie.creation_time).filter(|&t| t != 0),
+        trim_start: None,
+        trim_end: None,
     })
 }
 
-fn validate_input_files(files: &[PathBuf]) -> Result<Vec<MovInfo>> {
+fn print_file_info(info: &MovInfo, verbose: bool) {
+    println!("File: {:?}", info.path);
+    println!("  Duration: {}s", info.duration_secs());
+    if info.trim_start.is_some() || info.trim_end.is_some() {
+        println!("  Trim: {:.3}s - {:.3}s ({:.3}s used)",
+            info.trim_start.unwrap_or(0.0),
+            info.trim_end.unwrap_or(info.duration_secs()),
+            info.used_duration_secs());
+    }
+    println!("  Tracks: {} (Video: {}, Audio: {})",
+        info.track_count, info.video_tracks, info.audio_tracks);
+    println!("  Major Brand: {}", info.major_brand);
+    if let Some((sample_rate, samples)) = audio::first_track_samples(&info.tracks) {
+        println!("  Audio samples: {} at {} Hz", samples, sample_rate);
+    }
+    if let Some(fps) = info.tracks.iter().find(|t| t.is_video() && t.is_timelapse()).and_then(|t| t.frame_rate()) {
+        println!("  Timelapse: {:.2} fps", fps);
+    }
+    let layout = stereo::layout(info);
+    if layout != stereo::StereoLayout::Mono {
+        println!("  Stereo: {}", layout.describe());
+    }
+    if verbose {
+        let mdat_bytes: u64 = info.mdat_regions.iter().map(|(start, end)| end - start).sum();
+        println!("  Media data: {} mdat atom(s), {} bytes", info.mdat_regions.len(), mdat_bytes);
+        println!("  Sample tables:");
+        print_sample_tables(&info.tracks);
+    }
+    println!();
+}
+
+fn print_sample_tables(tracks: &[movie::Track]) {
+    for track in tracks {
+        let sizes = if track.stsz_entries == 0 {
+            "uniform".to_string()
+        } else {
+            track.stsz_entries.to_string()
+        };
+        let codec = atoms::fourcc_to_string(&track.codec);
+        let codec = if codec.trim().is_empty() { "unknown" } else { codec.trim_end() };
+        println!("    Track {} ({}, {}): {} samples, stts {}, stsc {}, stsz {}, chunks {}",
+            track.id, atoms::fourcc_to_string(&track.handler), codec, track.sample_count,
+            track.stts_entries, track.stsc_entries, sizes, track.chunk_count);
+    }
+}
+
+/// Reports the output's audio sample count and checks it against the
+/// inputs and the video duration.
+fn report_audio_samples(infos: &[MovInfo], output_path: &Path) -> Result<()> {
+    let movie = movie::read_movie(output_path)
+        .with_context(|| format!("Failed to analyze output file: {:?}", output_path))?;
+    if let Some(check) = audio::check(infos, &movie) {
+        check.print();
+    }
+    Ok(())
+}
+
+/// Decodes the output (`--verify-decode`) and fails on decode errors, before
+/// the output is published or uploaded.
+fn verify_output(infos: &[MovInfo], output_path: &Path, options: &OutputOptions) -> Result<()> {
+    println!("Decoding the output to verify it...");
+    let errors = verify::decode(output_path)?;
+    let starts = verify::clip_starts(infos, planned_join_timing(infos, options).as_ref());
+    verify::print(&errors, &starts, infos);
+    if !errors.is_empty() {
+        anyhow::bail!("The output has {} decode error(s); it is left at {:?} for inspection", errors.len(), output_path);
+    }
+    Ok(())
+}
+
+fn report_output(output_path: &Path) -> Result<()> {
+    let movie = movie::read_movie(output_path)
+        .with_context(|| format!("Failed to analyze output file: {:?}", output_path))?;
+
+    println!("Output moov size: {} bytes", movie.moov_size);
+    println!("  Sample tables:");
+    print_sample_tables(&movie.tracks);
+    Ok(())
+}
+
+/// Analyzes the inputs, drops those not matching every filter and checks
+/// the remaining ones for compatibility.
+fn validate_input_files(files: &[PathBuf], filters: &[filter::Filter]) -> Result<Vec<MovInfo>> {
     let mut infos = Vec::new();
 
     for file in files {
@@ -50,13 +414,43 @@ fn validate_input_files(files: &[PathBuf]) -> Result<Vec<MovInfo>> {
 
         let info = analyze_mov_file(file)?;
 
+        if let Some(failed) = filters.iter().find(|f| !f.matches(&info)) {
+            println!("Skipping {:?} (does not match filter '{}')", file, failed.expression());
+            continue;
+        }
+
         if info.video_tracks == 0 && info.audio_tracks == 0 {
             anyhow::bail!("File has no video or audio tracks: {:?}", file);
         }
 
+        for track in &info.tracks {
+            let outside = movie::chunks_outside(track, &info.mdat_regions);
+            if outside > 0 {
+                anyhow::bail!("Track {} of {:?} has {} of {} chunks outside its media data (mdat); \
+                    the file is truncated, damaged or references external media",
+                    track.id, file, outside, track.chunk_offsets.len());
+            }
+        }
+
+        for track in info.tracks.iter().filter(|t| t.is_dv_or_mjpeg()) {
+            let codec = atoms::fourcc_to_string(&track.codec);
+            if track.has_composition_offsets {
+                anyhow::bail!("Track {} ({}) of {:?} reorders frames, which is invalid for an intra-only codec",
+                    track.id, codec, file);
+            }
+            if movie::is_dv(&track.codec) && track.constant_sample_size.is_none() {
+                anyhow::bail!("Track {} ({}) of {:?} has varying sample sizes; DV frames must all be the same size",
+                    track.id, codec, file);
+            }
+        }
+
         infos.push(info);
     }
 
+    if infos.is_empty() && !files.is_empty() {
+        anyhow::bail!("None of the {} input files match the filters", files.len());
+    }
+
     // Check compatibility
     if infos.len() > 1 {
         let first_brand = &infos[0].major_brand;
@@ -72,15 +466,265 @@ fn validate_input_files(files: &[PathBuf]) -> Result<Vec<MovInfo>> {
                     first_timescale, info.timescale);
             }
         }
+
+        // A stream copy keeps one stereo layout for the whole output
+        let first_layout = stereo::layout(&infos[0]);
+        for info in &infos[1..] {
+            let layout = stereo::layout(info);
+            if layout != first_layout {
+                let mixing = if first_layout == stereo::StereoLayout::Mono || layout == stereo::StereoLayout::Mono {
+                    "Mixing 2D and 3D clips"
+                } else {
+                    "Mixing stereo layouts"
+                };
+                println!("Warning: {}: {:?} is {} but {:?} is {}", mixing,
+                    infos[0].path, first_layout.describe(), info.path, layout.describe());
+            }
+        }
+
+        // Timelapse clips play back far faster than real time
+        let timelapse_rate = |info: &MovInfo| info.tracks.iter()
+            .find(|t| t.is_video())
+            .filter(|t| t.is_timelapse())
+            .and_then(|t| t.frame_rate());
+        let timelapses: Vec<(&MovInfo, f64)> = infos.iter()
+            .filter_map(|info| timelapse_rate(info).map(|fps| (info, fps)))
+            .collect();
+        if !timelapses.is_empty() && timelapses.len() < infos.len() {
+            println!("Warning: Mixing timelapse and normal-speed clips; the playback speed changes at the joins. Timelapse clips:");
+            for (info, fps) in &timelapses {
+                println!("  {:?} ({:.2} fps)", info.path, fps);
+            }
+        }
+
+        // A stream copy keeps the first input's codec configuration
+        for (name, video) in [("Video", true), ("Audio", false)] {
+            let is_kind = |t: &&movie::Track| if video { t.is_video() } else { t.is_audio() };
+            let first_track = infos[0].tracks.iter().find(is_kind);
+            for info in &infos[1..] {
+                let track = info.tracks.iter().find(is_kind);
+                if let Some(difference) = first_track.zip(track).and_then(|(a, b)| track_difference(a, b)) {
+                    println!("Warning: {} codec configuration of {:?} differs from {:?}:\n{}",
+                        name, info.path, infos[0].path, difference);
+                }
+            }
+        }
+
+        // DV frame size identifies the format (NTSC/PAL, DV25/DV50)
+        let dv_frame_size = |info: &MovInfo| info.tracks.iter()
+            .find(|t| movie::is_dv(&t.codec))
+            .and_then(|t| t.constant_sample_size);
+        let mut dv_sizes = infos.iter().filter_map(dv_frame_size);
+        if let Some(first_size) = dv_sizes.next() {
+            if let Some(other) = dv_sizes.find(|&size| size != first_size) {
+                println!("Warning: Different DV frame sizes detected ({} vs {} bytes); \
+                    mixed DV formats (NTSC/PAL or DV25/DV50) cannot be joined losslessly",
+                    first_size, other);
+            }
+        }
     }
 
     Ok(infos)
 }
 
+/// Describes how the codec of one track differs from another, field by
+/// field where the configuration records could be decoded. `None` means
+/// no difference was found.
+fn track_difference(first: &movie::Track, second: &movie::Track) -> Option<String> {
+    if first.codec != second.codec {
+        return Some(format!("    codec: {} -> {} (breaks stream copy)",
+            atoms::fourcc_to_string(&first.codec).trim_end(), atoms::fourcc_to_string(&second.codec).trim_end()));
+    }
+    let diffs = codec_config::diff(first.config.as_ref()?, second.config.as_ref()?);
+    if diffs.is_empty() {
+        None
+    } else {
+        Some(codec_config::format_diff(&diffs))
+    }
+}
+
+/// Prints the analysis of each file. As JSON, every file gets its own
+/// document, as if ffprobe had been run once per file.
+fn run_info(files: &[PathBuf], format: InfoFormat, verbose: bool) -> Result<()> {
+    for file in files {
+        let info = analyze_mov_file(file)?;
+        match format {
+            InfoFormat::Text => print_file_info(&info, verbose),
+            InfoFormat::FfprobeJson => {
+                let size = std::fs::metadata(file)
+                    .with_context(|| format!("Failed to read file size: {:?}", file))?
+                    .len();
+                println!("{}", serde_json::to_string_pretty(&probe::ffprobe_json(&info, size))?);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Prints the codec differences of two files, pairing their video tracks
+/// and their audio tracks in order.
+fn run_compare(first: &Path, second: &Path) -> Result<()> {
+    let a = analyze_mov_file(first)?;
+    let b = analyze_mov_file(second)?;
+
+    for (name, video) in [("Video", true), ("Audio", false)] {
+        let is_kind = |t: &&movie::Track| if video { t.is_video() } else { t.is_audio() };
+        let first_tracks: Vec<_> = a.tracks.iter().filter(is_kind).collect();
+        let second_tracks: Vec<_> = b.tracks.iter().filter(is_kind).collect();
+        for i in 0..first_tracks.len().max(second_tracks.len()) {
+            let label = format!("{} track {}", name, i + 1);
+            let (track, other) = match (first_tracks.get(i), second_tracks.get(i)) {
+                (Some(track), Some(other)) => (track, other),
+                (Some(_), None) => {
+                    println!("{}: only in {:?}", label, first);
+                    continue;
+                }
+                _ => {
+                    println!("{}: only in {:?}", label, second);
+                    continue;
+                }
+            };
+            match track_difference(track, other) {
+                Some(difference) => println!("{}: differs\n{}", label, difference),
+                None if track.config.is_none() || other.config.is_none() => {
+                    println!("{}: same codec ({}), configuration not decoded", label,
+                        atoms::fourcc_to_string(&track.codec).trim_end());
+                }
+                None => println!("{}: identical", label),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Compares the display orientation of the first video track across inputs.
+///
+/// A portrait clip in a landscape merge (or the other way round) is almost
+/// always a mistake, so it is reported before anything is copied.
+fn check_orientation(infos: &[MovInfo], strict: bool) -> Result<()> {
+    let videos: Vec<(&MovInfo, &movie::Track)> = infos.iter()
+        .filter_map(|info| info.tracks.iter().find(|t| t.is_video()).map(|track| (info, track)))
+        .filter(|(_, track)| track.width > 0 && track.height > 0)
+        .collect();
+
+    let (portrait, landscape): (Vec<_>, Vec<_>) = videos.iter()
+        .filter(|(_, track)| track.width != track.height)
+        .partition(|(_, track)| {
+            let (width, height) = track.display_size();
+            width < height
+        });
+
+    if !portrait.is_empty() && !landscape.is_empty() {
+        let minority = if portrait.len() <= landscape.len() {
+            ("portrait", &portrait)
+        } else {
+            ("landscape", &landscape)
+        };
+        let mut message = format!("Mixed display orientations: {} landscape, {} portrait",
+            landscape.len(), portrait.len());
+        for (info, track) in minority.1 {
+            let (width, height) = track.display_size();
+            message.push_str(&format!("\n  {}: {:?} ({}x{}, rotation {})",
+                minority.0, info.path, width, height, track.rotation));
+        }
+        if strict {
+            anyhow::bail!("{}", message);
+        }
+        println!("Warning: {}", message);
+    }
+
+    if let Some((_, first)) = videos.first() {
+        if let Some((info, track)) = videos.iter().find(|(_, t)| t.rotation != first.rotation) {
+            println!("Warning: Different rotations detected ({} vs {} degrees in {:?}); \
+                stream copy keeps only the first file's rotation", first.rotation, track.rotation, info.path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns input indices sorted by creation time, or `None` if the inputs
+/// are already chronological or some input has no creation time.
+///
+/// The sort is stable, so inputs recorded at the same time keep their
+/// relative order.
+fn chronological_order(infos: &[MovInfo]) -> Option<Vec<usize>> {
+    let times: Vec<u64> = infos.iter().map(|info| info.creation_time).collect::<Option<_>>()?;
+    let mut order: Vec<usize> = (0..infos.len()).collect();
+    order.sort_by_key(|&i| times[i]);
+    if order.iter().enumerate().all(|(position, &i)| position == i) {
+        None
+    } else {
+        Some(order)
+    }
+}
+
+/// Detects inputs whose order contradicts their recording order (e.g. a
+/// camera that rolled over from FILE0999 to FILE0001), prints the
+/// suggested order and applies it with `--auto-reorder`.
+fn check_chronology(infos: Vec<MovInfo>, auto_reorder: bool) -> Vec<MovInfo> {
+    let Some(order) = chronological_order(&infos) else {
+        return infos;
+    };
+
+    let first_backwards = infos.windows(2)
+        .find(|pair| pair[1].creation_time < pair[0].creation_time)
+        .map(|pair| &pair[1].path);
+    println!("Warning: Input order does not match recording order (creation time goes backwards at {:?})",
+        first_backwards.unwrap_or(&infos[0].path));
+    println!("Suggested order:");
+    for (position, &i) in order.iter().enumerate() {
+        let created = infos[i].creation_time.map(movie::format_timestamp).unwrap_or_default();
+        println!("  {}. {:?} ({})", position + 1, infos[i].path, created);
+    }
+
+    if !auto_reorder {
+        println!("Run with --auto-reorder to apply it.");
+        println!();
+        return infos;
+    }
+
+    println!("Applying suggested order (--auto-reorder)");
+    println!();
+    let mut slots: Vec<Option<MovInfo>> = infos.into_iter().map(Some).collect();
+    order.iter().filter_map(|&i| slots[i].take()).collect()
+}
+
+/// Sorts paths identically on every platform.
+///
+/// Paths are compared component by component, so the separator never takes
+/// part in the comparison (`a/b.mov` sorts before `a.b/c.mov` on Unix and
+/// Windows alike), and components are compared by their raw bytes. For
+/// Unicode names that is code point order, which does not depend on the
+/// locale, and names that are not valid Unicode stay distinct. `.`
+/// components are ignored; paths that are still equal are ordered by their
+/// canonical path, then by their spelling.
+fn sort_paths(paths: &mut [PathBuf]) {
+    let bytes = |path: &Path| path.as_os_str().as_encoded_bytes().to_vec();
+    paths.sort_by_cached_key(|path| {
+        let components: Vec<Vec<u8>> = path.components()
+            .filter(|c| !matches!(c, std::path::Component::CurDir))
+            .map(|c| c.as_os_str().as_encoded_bytes().to_vec())
+            .collect();
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.clone());
+        (components, bytes(&canonical), bytes(path))
+    });
+}
+
 fn expand_glob_patterns(patterns: &[String]) -> Result<Vec<PathBuf>> {
+    let _span = profile::span("expand patterns");
     let mut all_files = Vec::new();
+    let mut excludes = Vec::new();
 
     for pattern in patterns {
+        // Patterns starting with '!' exclude matching files from the whole input set
+        if let Some(negated) = pattern.strip_prefix('!') {
+            let exclude = Pattern::new(negated)
+                .with_context(|| format!("Invalid exclude pattern: {}", pattern))?;
+            excludes.push(exclude);
+            continue;
+        }
+
         // Check if the pattern contains glob characters
         if pattern.contains('*') || pattern.contains('?') || pattern.contains('[') {
             // It's a glob pattern
@@ -106,7 +750,7 @@ fn expand_glob_patterns(patterns: &[String]) -> Result<Vec<PathBuf>> {
             }
 
             // Sort files to ensure consistent ordering
-            pattern_files.sort();
+            sort_paths(&mut pattern_files);
             all_files.extend(pattern_files);
         } else {
             // It's a regular file path
@@ -119,139 +763,1528 @@ fn expand_glob_patterns(patterns: &[String]) -> Result<Vec<PathBuf>> {
         anyhow::bail!("No input files specified");
     }
 
+    if !excludes.is_empty() {
+        // Like the positive patterns, '*' does not cross directory separators
+        let options = MatchOptions { require_literal_separator: true, ..MatchOptions::new() };
+        all_files.retain(|path| !excludes.iter().any(|exclude| exclude.matches_path_with(path, options)));
+        if all_files.is_empty() {
+            anyhow::bail!("All input files were excluded by negative patterns");
+        }
+    }
+
     Ok(all_files)
 }
 
 
-fn concatenate_mov_files(infos: &[MovInfo], output_path: &PathBuf) -> Result<()> {
+fn concatenate_mov_files(infos: &[MovInfo], output_path: &Path, options: &OutputOptions,
+    choice: backend::BackendChoice, verbose: bool) -> Result<()> {
+    check_estimated_moov_size(infos, options.max_moov_size)?;
+
+    let requirements = backend::Requirements::from_inputs(infos);
+    let backend = {
+        let _span = profile::span("select backend");
+        backend::select(choice, &requirements, verbose)?
+    };
+
     println!("Starting concatenation...");
 
-    // Check if ffmpeg is available
-    let ffmpeg_check = std::process::Command::new("ffmpeg")
-        .arg("-version")
-        .output();
+    match backend {
+        backend::Backend::Ffmpeg => concatenate_with_ffmpeg(infos, output_path, options)?,
+        backend::Backend::Fake => fake::concatenate(infos, output_path, options)?,
+    }
 
-    match ffmpeg_check {
-        Ok(_) => {
-            concatenate_with_ffmpeg(infos, output_path)
-        }
-        Err(_) => {
-            anyhow::bail!(
-                "FFmpeg is required for mov concatenation. Please install FFmpeg:\n\
-                - macOS: brew install ffmpeg\n\
-                - Ubuntu/Debian: sudo apt install ffmpeg\n\
-                - Windows: Download from https://ffmpeg.org/download.html"
-            );
-        }
+    let moov_size = {
+        let _span = profile::span("check output");
+        movie::read_movie(output_path)?.moov_size
+    };
+    let limit = options.max_moov_size.unwrap_or(MOOV_SIZE_WARNING);
+    if moov_size > limit {
+        println!("Warning: Output moov size is {} bytes (limit {} bytes). {}", moov_size, limit, MOOV_SIZE_GUIDANCE);
     }
+
+    Ok(())
 }
 
-fn concatenate_with_ffmpeg(infos: &[MovInfo], output_path: &PathBuf) -> Result<()> {
+fn concatenate_with_ffmpeg(infos: &[MovInfo], output_path: &Path, options: &OutputOptions) -> Result<()> {
     println!("Using FFmpeg for lossless concatenation...");
 
+    // FFmpeg writes to partial files that are only renamed once complete
+    let mut outputs = vec![(output_path.to_path_buf(), options.faststart)];
+    if let Some(also_output) = &options.also_output {
+        outputs.push((also_output.clone(), true));
+    }
+    let partial_paths: Vec<PathBuf> = outputs.iter().map(|(path, _)| partial_output_path(path)).collect();
+
     // Create a temporary file list for FFmpeg concat demuxer
     let temp_dir = std::env::temp_dir();
     let filelist_path = temp_dir.join("movcat_filelist.txt");
-
-    // Write file list
-    let mut filelist_content = String::new();
-    for info in infos {
-        let absolute_path = info.path.canonicalize()
-            .with_context(|| format!("Failed to get absolute path for: {:?}", info.path))?;
-        filelist_content.push_str(&format!("file '{}'\n", absolute_path.display()));
+    let timing = join_timing(infos, options);
+    if let Some(timing) = &timing {
+        println!("Join timing: {} joins on a 1/{} s timescale; drift {:.3} ms uncorrected, {:.3} ms with error diffusion",
+            timing.durations_us.len(), timing.timescale,
+            timing.uncorrected_error * 1000.0, timing.corrected_error * 1000.0);
     }
+    let filelist_content = concat_filelist(infos, timing.as_ref())?;
 
-    std::fs::write(&filelist_path, filelist_content)
-        .with_context(|| format!("Failed to write file list: {:?}", filelist_path))?;
+    // Chapters are passed to FFmpeg as a metadata file
+    let chapters_path = temp_dir.join("movcat_chapters.txt");
+    let chapters_content = if options.chapters.is_empty() {
+        String::new()
+    } else {
+        let total = infos.iter().map(|info| info.used_duration_secs()).sum();
+        job::ffmetadata_chapters(&options.chapters, total)?
+    };
+
+    let metadata = output_metadata(infos, options)?;
 
     // Run FFmpeg concat
-    let mut ffmpeg_cmd = std::process::Command::new("ffmpeg");
+    let mut ffmpeg_cmd = process::command("ffmpeg")?;
     ffmpeg_cmd
         .arg("-f").arg("concat")
         .arg("-safe").arg("0")
-        .arg("-i").arg(&filelist_path)
-        .arg("-c").arg("copy")
-        .arg("-avoid_negative_ts").arg("make_zero")
-        .arg("-y") // Overwrite output file
-        .arg(output_path);
+        .arg("-i").arg(&filelist_path);
+    if !options.chapters.is_empty() {
+        ffmpeg_cmd.arg("-i").arg(&chapters_path);
+    }
+    let layout = stereo::layout(&infos[0]);
+    // Output options apply to the next output file, so every output gets
+    // its own copy; the inputs are still only read once
+    for ((_, faststart), partial_path) in outputs.iter().zip(&partial_paths) {
+        if !options.chapters.is_empty() {
+            ffmpeg_cmd.arg("-map_chapters").arg("1");
+        }
+        match layout {
+            // FFmpeg picks a single video stream unless told otherwise
+            stereo::StereoLayout::TrackPair => {
+                ffmpeg_cmd.arg("-map").arg("0:v").arg("-map").arg("0:a?");
+            }
+            // The mov muxer only writes st3d when unofficial atoms are allowed
+            stereo::StereoLayout::TopBottom | stereo::StereoLayout::LeftRight => {
+                ffmpeg_cmd.arg("-strict").arg("unofficial");
+            }
+            stereo::StereoLayout::Mono | stereo::StereoLayout::Multiview => {}
+        }
+        if options.hvc1_tag {
+            ffmpeg_cmd.arg("-tag:v").arg("hvc1");
+        }
+        for (key, value) in &metadata {
+            ffmpeg_cmd.arg("-metadata").arg(format!("{}={}", key, value));
+        }
+        let mut movflags = String::new();
+        if *faststart {
+            movflags.push_str("+faststart");
+        }
+        if !options.key_rules.is_empty() {
+            // Writes metadata as mdta keys instead of only the fixed udta tags
+            movflags.push_str("+use_metadata_tags");
+        }
+        if !movflags.is_empty() {
+            ffmpeg_cmd.arg("-movflags").arg(movflags);
+        }
+        ffmpeg_cmd
+            .arg("-c").arg("copy")
+            .arg("-avoid_negative_ts").arg("make_zero")
+            .arg("-y") // Overwrite output file
+            .arg(partial_path);
+    }
+
+    // The checkpoint records which inputs and settings the partial outputs
+    // are made of
+    let checkpoint = checkpoint_path(output_path);
+    let current = Checkpoint {
+        plan: plan::Plan::from_infos(infos, Some(output_path))?,
+        filelist: filelist_content,
+        chapters: chapters_content,
+        ffmpeg_args: ffmpeg_cmd.get_args().map(|arg| arg.to_string_lossy().into_owned()).collect(),
+    };
+    if options.resume {
+        return resume_partials(&outputs, &partial_paths, &checkpoint, &current);
+    }
+
+    std::fs::write(&filelist_path, &current.filelist)
+        .with_context(|| format!("Failed to write file list: {:?}", filelist_path))?;
+    if !options.chapters.is_empty() {
+        std::fs::write(&chapters_path, &current.chapters)
+            .with_context(|| format!("Failed to write chapters file: {:?}", chapters_path))?;
+    }
 
     println!("Running: {:?}", ffmpeg_cmd);
 
-    let output = ffmpeg_cmd.output()
-        .with_context(|| "Failed to execute FFmpeg")?;
+    current.save(&checkpoint)?;
+    let output = {
+        let _span = profile::span("ffmpeg");
+        ffmpeg_cmd.output()
+    };
+    let output = match output {
+        Ok(output) => output,
+        Err(e) => {
+            let _ = std::fs::remove_file(&checkpoint);
+            return Err(e).context("Failed to execute FFmpeg");
+        }
+    };
 
-    // Clean up temp file
+    // Clean up temp files
     let _ = std::fs::remove_file(&filelist_path);
+    let _ = std::fs::remove_file(&chapters_path);
 
     if output.status.success() {
+        for ((path, _), partial_path) in outputs.iter().zip(&partial_paths) {
+            if let Err(e) = finalize_output(partial_path, path) {
+                for partial_path in &partial_paths {
+                    let _ = std::fs::remove_file(partial_path);
+                }
+                let _ = std::fs::remove_file(&checkpoint);
+                return Err(e);
+            }
+        }
+        let _ = std::fs::remove_file(&checkpoint);
         println!("Concatenation completed successfully!");
         Ok(())
     } else {
+        for partial_path in &partial_paths {
+            let _ = std::fs::remove_file(partial_path);
+        }
+        let _ = std::fs::remove_file(&checkpoint);
         let stderr = String::from_utf8_lossy(&output.stderr);
         anyhow::bail!("FFmpeg failed: {}", stderr);
     }
 }
 
-fn main() -> Result<()> {
-    let args = Args::parse();
-
-    println!("Expanding input patterns...");
-    let input_files = expand_glob_patterns(&args.inputs)?;
-
-    println!("Found {} files:", input_files.len());
-    for file in &input_files {
-        println!("  {:?}", file);
+/// Plans the join durations and reports the recording gaps that
+/// `--fill-gaps freeze` fills.
+fn join_timing(infos: &[MovInfo], options: &OutputOptions) -> Option<joins::JoinTiming> {
+    if options.timeline_gaps_us.is_none() && options.fill_gaps == joins::FillGaps::Freeze {
+        let gaps = joins::recording_gaps_us(infos);
+        let filled: Vec<&u64> = gaps.iter().filter(|&&gap| gap > 0).collect();
+        if !filled.is_empty() {
+            println!("Filling {} recording gap(s), {} in total, with freeze frames",
+                filled.len(), joins::format_us(filled.iter().copied().sum()) + "s");
+        }
     }
-    println!();
+    planned_join_timing(infos, options)
+}
 
-    println!("Analyzing input files...");
-    let file_infos = validate_input_files(&input_files)?;
+/// The join durations, lengthened by the gaps of the synced timeline or,
+/// with `--fill-gaps freeze`, by the recording gaps.
+fn planned_join_timing(infos: &[MovInfo], options: &OutputOptions) -> Option<joins::JoinTiming> {
+    let mut timing = joins::plan(infos)?;
+    if let Some(gaps) = &options.timeline_gaps_us {
+        timing.fill_gaps(gaps);
+    } else if options.fill_gaps == joins::FillGaps::Freeze {
+        timing.fill_gaps(&joins::recording_gaps_us(infos));
+    }
+    Some(timing)
+}
 
-    for info in &file_infos {
-        println!("File: {:?}", info.path);
-        println!("  Duration: {}s", info.duration as f64 / info.timescale as f64);
-        println!("  Tracks: {} (Video: {}, Audio: {})",
-            info.track_count, info.video_tracks, info.audio_tracks);
-        println!("  Major Brand: {}", info.major_brand);
-        println!();
+/// Metadata written to the output: the requested tags plus, when rules ask
+/// for them, the first input's metadata keys.
+fn output_metadata(infos: &[MovInfo], options: &OutputOptions) -> Result<Vec<(String, String)>> {
+    let mut metadata = options.metadata.clone();
+    if !options.key_rules.is_empty() {
+        let input_keys = keys::read(&infos[0].path)?;
+        let kept = keys::apply(&input_keys, &options.key_rules);
+        println!("Metadata keys: {} of {} from {:?} kept, {} written",
+            kept.iter().filter(|(key, _)| input_keys.iter().any(|(k, _)| k == key)).count(),
+            input_keys.len(), infos[0].path, kept.len());
+        metadata.extend(kept);
     }
+    Ok(metadata)
+}
 
-    println!("Total files: {}", file_infos.len());
-    println!("Output file: {:?}", args.output);
+/// Builds the file list for FFmpeg's concat demuxer.
+///
+/// With join timing, every input but the last gets an explicit duration so
+/// that the next one starts at its error-diffused position.
+fn concat_filelist(infos: &[MovInfo], timing: Option<&joins::JoinTiming>) -> Result<String> {
+    let mut content = String::new();
+    for (i, info) in infos.iter().enumerate() {
+        let absolute_path = info.path.canonicalize()
+            .with_context(|| format!("Failed to get absolute path for: {:?}", info.path))?;
+        let path = filelist_path(&absolute_path.to_string_lossy(), cfg!(windows));
+        content.push_str(&format!("file '{}'\n", path));
+        if let Some(&duration) = timing.and_then(|timing| timing.durations_us.get(i)) {
+            content.push_str(&format!("duration {}\n", joins::format_us(duration)));
+        } else if info.tracks.iter().any(|t| t.is_dv_or_mjpeg()) {
+            // FFmpeg derives the end of the file from packet timestamps,
+            // which are unreliable for DV and MJPEG; give it the container
+            // duration. The demuxer does not subtract the inpoint from it.
+            content.push_str(&format!("duration {}\n", info.used_duration_secs()));
+        }
+        if let Some(start) = info.trim_start {
+            content.push_str(&format!("inpoint {}\n", start));
+        }
+        if let Some(end) = info.trim_end {
+            content.push_str(&format!("outpoint {}\n", end));
+        }
+    }
+    Ok(content)
+}
 
-    // Perform concatenation
-    concatenate_mov_files(&file_infos, &args.output)?;
+/// A path in the form the concat demuxer reads inside single quotes.
+///
+/// On Windows, `canonicalize` returns verbatim paths (`\\?\C:\...`,
+/// `\\?\UNC\server\share\...`) that FFmpeg cannot open. They become
+/// `C:/...` and `//server/share/...` with forward slashes throughout, so
+/// inputs on different drives and network shares can be mixed in one list.
+/// A quote in a name would end the quoted string and is written as `'\''`.
+fn filelist_path(path: &str, windows: bool) -> String {
+    let path = if windows {
+        let path = if let Some(unc) = path.strip_prefix(r"\\?\UNC\") {
+            format!(r"\\{}", unc)
+        } else {
+            path.strip_prefix(r"\\?\").unwrap_or(path).to_string()
+        };
+        path.replace('\\', "/")
+    } else {
+        path.to_string()
+    };
+    path.replace('\'', r"'\''")
+}
 
+fn write_index(output_path: &Path) -> Result<()> {
+    let _span = profile::span("index");
+    let index = index::build(output_path)?;
+    let sidecar = index::sidecar_path(output_path);
+    index.save(&sidecar)?;
+    println!("Index written to {:?} ({} keyframes)", sidecar, index.keyframes.len());
     Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_analyze_mov_file_nonexistent() {
-        let path = PathBuf::from("nonexistent.mov");
-        let result = analyze_mov_file(&path);
-        assert!(result.is_err());
+/// Checks that the output can be written before any analysis runs.
+///
+/// A missing, read-only or stale (network) output directory would otherwise
+/// only show up as an FFmpeg error after all inputs have been analyzed.
+fn check_output_dir(output_path: &Path, mkdirs: bool) -> Result<()> {
+    if output_path.is_dir() {
+        anyhow::bail!("Output path is a directory: {:?}", output_path);
     }
 
-    #[test]
-    fn test_validate_input_files_empty() {
-        let files = vec![];
-        let result = validate_input_files(&files);
-        assert!(result.is_ok());
-        assert_eq!(result.unwrap().len(), 0);
+    let dir = match output_path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+
+    match std::fs::metadata(dir) {
+        Ok(metadata) if metadata.is_dir() => {}
+        Ok(_) => anyhow::bail!("Output directory is not a directory: {:?}", dir),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            if !mkdirs {
+                anyhow::bail!("Output directory does not exist: {:?} (use --mkdirs to create it)", dir);
+            }
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create output directory: {:?}", dir))?;
+            println!("Created output directory {:?}", dir);
+        }
+        Err(e) => {
+            let hint = io_error_hint(&e);
+            return Err(e).with_context(|| format!("Output directory is not accessible: {:?}{}", dir, hint));
+        }
     }
 
-    #[test]
-    fn test_validate_input_files_nonexistent() {
-        let files = vec![PathBuf::from("nonexistent.mov")];
-        let result = validate_input_files(&files);
-        assert!(result.is_err());
+    // Write, flush and remove a probe file; a read-only or stale mount may
+    // only fail on the actual write
+    let probe_path = dir.join(format!(".movcat-write-test-{}", std::process::id()));
+    let probe = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&probe_path)
+        .and_then(|mut file| {
+            use std::io::Write;
+            file.write_all(b"movcat")?;
+            file.sync_all()
+        });
+    let _ = std::fs::remove_file(&probe_path);
+    if let Err(e) = probe {
+        let hint = io_error_hint(&e);
+        return Err(e).with_context(|| format!("Output directory is not writable: {:?}{}", dir, hint));
     }
 
-    #[test]
+    Ok(())
+}
+
+/// A requested option that cannot be honored in this environment.
+#[derive(Debug)]
+struct Unavailable {
+    option: String,
+    reason: String,
+    /// Whether the run can go ahead without the option.
+    optional: bool,
+}
+
+/// Checks up front that a backend is installed at all; whether it supports
+/// the inputs' codecs is only known after analysis.
+fn check_backend_available(choice: backend::BackendChoice) -> Vec<Unavailable> {
+    let requirements = backend::Requirements::default();
+    let probes: Vec<backend::Probe> = backend::candidates(choice).into_iter()
+        .map(|candidate| backend::probe(candidate, &requirements))
+        .collect();
+    if probes.iter().any(|probe| probe.is_usable()) {
+        return Vec::new();
+    }
+
+    probes.into_iter()
+        .flat_map(|probe| probe.blockers.into_iter().map(move |reason| Unavailable {
+            option: format!("concatenation ({} backend)", probe.backend.name()),
+            reason,
+            optional: false,
+        }))
+        .collect()
+}
+
+/// Reads the local UTC offset for `--schedule` and checks that the system
+/// load can be read for `--when-idle`. When they may be ignored, a window
+/// without a local offset is taken as UTC and an unreadable load is
+/// dropped.
+fn check_schedule(args: &Args, unavailable: &mut Vec<Unavailable>) -> schedule::Schedule {
+    let mut allowed = schedule::Schedule { window: args.schedule, when_idle: args.when_idle, ..Default::default() };
+    if allowed.window.is_some() {
+        match schedule::local_utc_offset() {
+            Ok(offset) => allowed.utc_offset_secs = offset,
+            Err(e) => unavailable.push(Unavailable {
+                option: "--schedule".to_string(),
+                reason: format!("{:#}; the window would be taken as UTC", e),
+                optional: true,
+            }),
+        }
+    }
+    if allowed.when_idle {
+        if let Err(e) = schedule::load_average() {
+            unavailable.push(Unavailable { option: "--when-idle".to_string(), reason: format!("{:#}", e), optional: true });
+            allowed.when_idle = false;
+        }
+    }
+    allowed
+}
+
+/// Checks that FFmpeg can decode the output for `--verify-decode`, which
+/// it has to even when another backend writes the output.
+fn check_verify_decode(args: &Args, unavailable: &mut Vec<Unavailable>) -> bool {
+    if !args.verify_decode {
+        return false;
+    }
+    let probe = backend::probe(backend::Backend::Ffmpeg, &backend::Requirements::default());
+    for reason in &probe.blockers {
+        unavailable.push(Unavailable { option: "--verify-decode".to_string(), reason: reason.clone(), optional: true });
+    }
+    probe.is_usable()
+}
+
+/// Reports all unavailable options at once. Fails unless every one of them
+/// is optional and `--ignore-unavailable` was given.
+fn report_unavailable(unavailable: &[Unavailable], ignore: bool) -> Result<()> {
+    if unavailable.is_empty() {
+        return Ok(());
+    }
+
+    let mut message = String::from("These requested options are unavailable:");
+    for item in unavailable {
+        message.push_str(&format!("\n  {}: {}", item.option, item.reason));
+    }
+
+    if unavailable.iter().any(|item| !item.optional) {
+        if process::is_disabled() {
+            anyhow::bail!("{}\nConcatenation requires FFmpeg; with --no-subprocess only analysis \
+                (movcat plan) is available", message);
+        }
+        anyhow::bail!("{}\n\
+            FFmpeg is required for mov concatenation. Please install FFmpeg:\n\
+            - macOS: brew install ffmpeg\n\
+            - Ubuntu/Debian: sudo apt install ffmpeg\n\
+            - Windows: Download from https://ffmpeg.org/download.html", message);
+    }
+    if !ignore {
+        anyhow::bail!("{}\nUse --ignore-unavailable to continue without them", message);
+    }
+
+    println!("Warning: {}", message);
+    println!("Continuing without them (--ignore-unavailable)");
+    println!();
+    Ok(())
+}
+
+fn check_also_output(output_path: &Path, also_output: Option<&Path>, mkdirs: bool) -> Result<()> {
+    let Some(also_output) = also_output else {
+        return Ok(());
+    };
+    if also_output == output_path {
+        anyhow::bail!("--also-output must differ from the output path: {:?}", also_output);
+    }
+    check_output_dir(also_output, mkdirs)
+}
+
+/// Checks the publish directory like the output directory; publishing
+/// into the directory the output is written to would not hide anything.
+fn check_publish_dir(output_path: &Path, publish_dir: Option<&Path>, mkdirs: bool) -> Result<()> {
+    let Some(publish_dir) = publish_dir else {
+        return Ok(());
+    };
+    let name = output_path.file_name().context("Output path has no file name")?;
+    check_output_dir(&publish_dir.join(name), mkdirs)?;
+
+    let output_dir = match output_path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    if std::fs::canonicalize(output_dir).ok() == std::fs::canonicalize(publish_dir).ok() {
+        anyhow::bail!("--publish-dir must differ from the output directory: {:?}", publish_dir);
+    }
+    Ok(())
+}
+
+/// Moves the finished outputs into the publish directory: sidecars first
+/// and the main output last, so a watch folder sees the movie once
+/// everything belonging to it is in place.
+fn publish_outputs(output_path: &Path, also_output: Option<&Path>, index: bool, publish_dir: &Path) -> Result<PathBuf> {
+    let _span = profile::span("publish");
+    if index {
+        publish_file(&index::sidecar_path(output_path), publish_dir, false)?;
+    }
+    let fake_record = fake::record_path(output_path);
+    if fake_record.exists() {
+        publish_file(&fake_record, publish_dir, false)?;
+    }
+    if let Some(also_output) = also_output {
+        publish_file(also_output, publish_dir, true)?;
+    }
+    let published = publish_file(output_path, publish_dir, true)?;
+    println!("Published {:?}", published);
+    Ok(published)
+}
+
+/// Moves a file into a directory with a single rename. Across file systems
+/// the file is copied to a hidden partial name in the directory first and
+/// renamed from there, so the final name never shows an incomplete file.
+fn publish_file(source: &Path, dir: &Path, verify: bool) -> Result<PathBuf> {
+    let name = source.file_name().with_context(|| format!("Output path has no file name: {:?}", source))?;
+    let destination = dir.join(name);
+    if verify {
+        movie::read_movie(source)
+            .with_context(|| format!("Output failed verification and was not published: {:?}", source))?;
+    }
+
+    match std::fs::rename(source, &destination) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {
+            let partial_path = partial_output_path(&destination);
+            let copied = std::fs::copy(source, &partial_path)
+                .and_then(|_| std::fs::File::open(&partial_path)?.sync_all())
+                .and_then(|_| std::fs::rename(&partial_path, &destination));
+            if let Err(e) = copied {
+                let _ = std::fs::remove_file(&partial_path);
+                return Err(e).with_context(|| format!("Failed to publish {:?} to {:?}", source, dir));
+            }
+            std::fs::remove_file(source)
+                .with_context(|| format!("Published {:?}, but failed to remove it from the working directory", source))?;
+        }
+        Err(e) => return Err(e).with_context(|| format!("Failed to publish {:?} to {:?}", source, dir)),
+    }
+
+    #[cfg(unix)]
+    let _ = std::fs::File::open(dir).and_then(|d| d.sync_all());
+    Ok(destination)
+}
+
+/// Explains I/O errors that point at the file system rather than the file.
+fn io_error_hint(error: &std::io::Error) -> &'static str {
+    match error.kind() {
+        std::io::ErrorKind::PermissionDenied => " (permission denied)",
+        std::io::ErrorKind::ReadOnlyFilesystem => " (read-only file system)",
+        std::io::ErrorKind::StaleNetworkFileHandle => " (stale network mount; try remounting it)",
+        std::io::ErrorKind::TimedOut => " (timed out; is the network mount reachable?)",
+        _ => "",
+    }
+}
+
+/// Path the output is written to until it is complete.
+///
+/// The file is hidden and keeps the output's extension, so FFmpeg still
+/// picks the right muxer while an interrupted run never leaves a file
+/// under the final name.
+fn partial_output_path(output_path: &Path) -> PathBuf {
+    let stem = output_path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match output_path.extension() {
+        Some(ext) => format!(".{}.movcat-partial.{}", stem, ext.to_string_lossy()),
+        None => format!(".{}.movcat-partial", stem),
+    };
+    output_path.with_file_name(name)
+}
+
+/// Path of the checkpoint written next to the partial output while FFmpeg
+/// runs; it survives a crash and identifies the inputs of the partial file.
+fn checkpoint_path(output_path: &Path) -> PathBuf {
+    let name = output_path.file_name().unwrap_or_default().to_string_lossy();
+    output_path.with_file_name(format!(".{}.movcat-checkpoint.json", name))
+}
+
+/// What the partial outputs of a run are made of: the inputs, and the
+/// concat file list, chapters and FFmpeg arguments that trims, gaps,
+/// offsets, metadata and output options end up in.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct Checkpoint {
+    plan: plan::Plan,
+    filelist: String,
+    chapters: String,
+    ffmpeg_args: Vec<String>,
+}
+
+impl Checkpoint {
+    fn load(path: &Path) -> Result<Checkpoint> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read checkpoint: {:?}", path))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("Invalid checkpoint file: {:?}; use --restart", path))
+    }
+
+    fn save(&self, path: &Path) -> Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, content + "\n")
+            .with_context(|| format!("Failed to write checkpoint: {:?}", path))
+    }
+
+    /// The settings that differ from another checkpoint's.
+    fn changed_settings(&self, other: &Checkpoint) -> Vec<&'static str> {
+        let mut changed = Vec::new();
+        if self.filelist != other.filelist {
+            changed.push("file list (trims, gaps, offsets)");
+        }
+        if self.chapters != other.chapters {
+            changed.push("chapters");
+        }
+        if self.ffmpeg_args != other.ffmpeg_args {
+            changed.push("output options");
+        }
+        changed
+    }
+}
+
+/// Looks for partial outputs left by an interrupted run and decides what
+/// to do with them: returns whether to resume. Without `--resume` or
+/// `--restart` the user is asked, or the run fails when nobody can answer,
+/// so that earlier progress is never overwritten silently.
+fn check_leftover_partials(output_path: &Path, also_output: Option<&Path>, resume: bool, restart: bool,
+    interactive: bool) -> Result<bool> {
+    let mut leftovers: Vec<PathBuf> = std::iter::once(output_path).chain(also_output)
+        .map(partial_output_path)
+        .chain(std::iter::once(checkpoint_path(output_path)))
+        .filter(|path| path.exists())
+        .collect();
+    if leftovers.is_empty() {
+        if resume {
+            println!("Nothing to resume for {:?}; starting a new run", output_path);
+        }
+        return Ok(false);
+    }
+    leftovers.sort();
+
+    println!("Found output of an interrupted run:");
+    for path in &leftovers {
+        println!("  {:?}", path);
+    }
+
+    let restart = if resume || restart {
+        restart
+    } else if interactive {
+        loop {
+            print!("[r]esume, [s]tart over or [a]bort? ");
+            std::io::Write::flush(&mut std::io::stdout())?;
+            let mut answer = String::new();
+            std::io::stdin().read_line(&mut answer)?;
+            match answer.trim().to_lowercase().as_str() {
+                "r" | "resume" => break false,
+                "s" | "start over" => break true,
+                "a" | "abort" | "" => anyhow::bail!("Aborted; the partial output was left in place"),
+                _ => {}
+            }
+        }
+    } else {
+        anyhow::bail!("Partial output of an interrupted run exists for {:?}. \
+            Use --resume to finish it or --restart to delete it and start over", output_path);
+    };
+
+    if restart {
+        for path in &leftovers {
+            std::fs::remove_file(path)
+                .with_context(|| format!("Failed to remove partial output: {:?}", path))?;
+        }
+        println!("Removed partial output; starting over");
+    }
+    Ok(!restart)
+}
+
+/// Finishes the partial outputs of an interrupted run.
+///
+/// Stream copy writes the moov last, so only outputs that FFmpeg completed
+/// before the interruption (a crash before the final rename) can be
+/// finished; a half-written file has to be started over.
+fn resume_partials(outputs: &[(PathBuf, bool)], partial_paths: &[PathBuf], checkpoint: &Path,
+    current: &Checkpoint) -> Result<()> {
+    if !checkpoint.exists() {
+        anyhow::bail!("No checkpoint found for the partial output, so its inputs are unknown; use --restart");
+    }
+    let previous = Checkpoint::load(checkpoint)?;
+    if !plan::diff(&previous.plan, &current.plan).is_empty() {
+        anyhow::bail!("The inputs changed since the interrupted run; use --restart to start over");
+    }
+    let changed = previous.changed_settings(current);
+    if !changed.is_empty() {
+        anyhow::bail!("The settings changed since the interrupted run ({}); use --restart to start over",
+            changed.join(", "));
+    }
+
+    for ((path, _), partial_path) in outputs.iter().zip(partial_paths) {
+        if path.exists() && !partial_path.exists() {
+            // Already moved into place before the interruption
+            continue;
+        }
+        if movie::read_movie(partial_path).is_err() {
+            anyhow::bail!("The interrupted run stopped while FFmpeg was writing {:?}. \
+                Stream copy writes the header last, so a half-written output cannot be continued; \
+                use --restart to start over", partial_path);
+        }
+        println!("Resuming: FFmpeg had completed {:?}; moving it into place", path);
+        finalize_output(partial_path, path)?;
+    }
+    let _ = std::fs::remove_file(checkpoint);
+    println!("Concatenation completed successfully!");
+    Ok(())
+}
+
+/// Checks a completed partial output, flushes it to disk and moves it to
+/// its final name.
+fn finalize_output(partial_path: &Path, output_path: &Path) -> Result<()> {
+    let _span = profile::span("finalize");
+    movie::read_movie(partial_path)
+        .with_context(|| format!("FFmpeg output is incomplete: {:?}", partial_path))?;
+
+    {
+        let _span = profile::span("fsync");
+        std::fs::File::open(partial_path)
+            .and_then(|file| file.sync_all())
+            .with_context(|| format!("Failed to flush output to disk: {:?}", partial_path))?;
+    }
+    std::fs::rename(partial_path, output_path)
+        .with_context(|| format!("Failed to move output into place: {:?}", output_path))?;
+
+    // Make the rename itself durable
+    #[cfg(unix)]
+    if let Some(dir) = output_path.parent() {
+        let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+        let _ = std::fs::File::open(dir).and_then(|d| d.sync_all());
+    }
+
+    Ok(())
+}
+
+fn run_job(job_path: &Path, dry_run: bool, args: &Args) -> Result<()> {
+    // A job file selects and orders its inputs itself
+    let input_selection = [("--skip-bad", args.skip_bad), ("--filter", !args.filter.is_empty()), ("--auto-reorder", args.auto_reorder)];
+    if let Some((option, _)) = input_selection.iter().find(|(_, given)| *given) {
+        anyhow::bail!("{} cannot be used with 'movcat run': the job file lists its inputs and their order", option);
+    }
+
+    println!("Loading job file {:?}...", job_path);
+    let job = job::load(job_path)?;
+    let inputs = job.ordered_inputs()?;
+    let job_offsets = job::Job::offsets(&inputs)?;
+    if job_offsets.is_some() && args.offsets.is_some() {
+        anyhow::bail!("The job file gives input offsets; --offsets cannot be used with it");
+    }
+    let mut unavailable = check_backend_available(args.backend);
+    let schedule = check_schedule(args, &mut unavailable);
+    let verify_decode = check_verify_decode(args, &mut unavailable);
+    report_unavailable(&unavailable, args.ignore_unavailable)?;
+    // A dry run leaves the output location alone
+    let resume = if dry_run {
+        false
+    } else {
+        check_output_dir(&job.output, args.mkdirs)?;
+        check_also_output(&job.output, args.also_output.as_deref(), args.mkdirs)?;
+        check_publish_dir(&job.output, args.publish_dir.as_deref(), args.mkdirs)?;
+        check_leftover_partials(&job.output, args.also_output.as_deref(), args.resume, args.restart,
+            std::io::stdin().is_terminal())?
+    };
+
+    println!("Analyzing input files...");
+    let files: Vec<PathBuf> = inputs.iter().map(|input| input.path.clone()).collect();
+    let mut file_infos = validate_input_files(&files, &[])?;
+    check_orientation(&file_infos, args.strict_orientation)?;
+
+    for (info, input) in file_infos.iter_mut().zip(&inputs) {
+        if input.trim_start.is_some_and(|t| t >= info.duration_secs()) {
+            anyhow::bail!("trim_start of input '{}' is beyond its duration ({}s)",
+                input.name, info.duration_secs());
+        }
+        info.trim_start = input.trim_start;
+        info.trim_end = input.trim_end;
+    }
+    let (file_infos, timeline_gaps_us) = match job_offsets {
+        Some(offsets) => {
+            let (infos, gaps_us) = offsets::place(offsets.into_iter().zip(file_infos).collect())?;
+            println!("Placed {} inputs on the synced timeline from the job's offsets", infos.len());
+            println!();
+            (infos, Some(gaps_us))
+        }
+        None => apply_offsets(file_infos, args.offsets.as_deref())?,
+    };
+    for info in &file_infos {
+        print_file_info(info, args.verbose);
+    }
+    print_trim_preview(&file_infos, &inputs)?;
+    check_target(args.target, &file_infos, &job.output)?;
+    if dry_run {
+        println!("Dry run: nothing was written.");
+        return Ok(());
+    }
+
+    println!("Total files: {}", file_infos.len());
+    println!("Output file: {:?}", job.output);
+    if let Some(also_output) = &args.also_output {
+        println!("Also writing: {:?}", also_output);
+    }
+
+    let mut options = OutputOptions {
+        metadata: job.metadata.clone().into_iter().collect(),
+        chapters: job.chapters.clone(),
+        faststart: job.faststart,
+        max_moov_size: args.max_moov_size,
+        also_output: args.also_output.clone(),
+        key_rules: args.key_rules.clone(),
+        resume,
+        fill_gaps: args.fill_gaps,
+        timeline_gaps_us,
+        hvc1_tag: false,
+    };
+    apply_target(args.target, &file_infos, &mut options);
+    schedule::wait(schedule)?;
+    concatenate_mov_files(&file_infos, &job.output, &options, args.backend, args.verbose)?;
+    report_audio_samples(&file_infos, &job.output)?;
+    if verify_decode {
+        verify_output(&file_infos, &job.output, &options)?;
+    }
+
+    if args.index {
+        write_index(&job.output)?;
+    }
+    if args.verbose {
+        report_output(&job.output)?;
+    }
+    if let Some(publish_dir) = &args.publish_dir {
+        publish_outputs(&job.output, args.also_output.as_deref(), args.index, publish_dir)?;
+    }
+
+    Ok(())
+}
+
+/// Fails before anything is copied if the inputs or the output name do
+/// not suit the `--target` player.
+fn check_target(target: Option<target::Target>, infos: &[MovInfo], output: &Path) -> Result<()> {
+    let Some(target) = target else {
+        return Ok(());
+    };
+    let problems = target::check(target, infos, output);
+    if !problems.is_empty() {
+        anyhow::bail!("The output would not work in the target player:\n  {}", problems.join("\n  "));
+    }
+    Ok(())
+}
+
+fn apply_target(target: Option<target::Target>, infos: &[MovInfo], options: &mut OutputOptions) {
+    for note in target.map(|target| target::apply(target, infos, options)).unwrap_or_default() {
+        println!("Target: {}", note);
+    }
+}
+
+/// Places the inputs on the synced timeline of an offsets CSV, if one was
+/// given, returning them in timeline order with the gaps between them.
+fn apply_offsets(infos: Vec<MovInfo>, path: Option<&Path>) -> Result<(Vec<MovInfo>, Option<Vec<u64>>)> {
+    let Some(path) = path else {
+        return Ok((infos, None));
+    };
+    let (infos, gaps_us) = offsets::apply(infos, &offsets::load(path)?)?;
+    let overlaps = infos.iter().skip(1).zip(&gaps_us).filter(|(_, &gap)| gap == 0).count();
+    println!("Placed {} inputs on the synced timeline from {:?}: {} gap(s) held on the previous frame, {} overlap(s) trimmed",
+        infos.len(), path, gaps_us.iter().filter(|&&gap| gap > 0).count(), overlaps);
+    println!();
+    Ok((infos, Some(gaps_us)))
+}
+
+/// Shows where the trims of a job land when stream copying.
+fn print_trim_preview(infos: &[MovInfo], inputs: &[&job::JobInput]) -> Result<()> {
+    let mut header_printed = false;
+    for info in infos {
+        let Some(preview) = trim::preview_input(info)? else {
+            continue;
+        };
+        let name = inputs.iter().find(|input| input.path == info.path).map_or("", |input| input.name.as_str());
+        if !header_printed {
+            println!("Trim preview (stream copy starts each clip at a keyframe):");
+            header_printed = true;
+        }
+        println!("  {} ({:?}):", name, info.path);
+        preview.print();
+    }
+    if header_printed {
+        println!();
+    }
+    Ok(())
+}
+
+fn run_plan(args: &Args, inputs: &[String], output: Option<&Path>, save: Option<&Path>,
+    diff: Option<&Path>, filters: &[filter::Filter]) -> Result<()> {
+    let input_files = expand_glob_patterns(inputs)?;
+    let input_files = triage::check(input_files, args.skip_bad)?;
+
+    println!("Analyzing input files...");
+    let file_infos = validate_input_files(&input_files, filters)?;
+    check_orientation(&file_infos, args.strict_orientation)?;
+    let file_infos = check_chronology(file_infos, args.auto_reorder);
+    for info in &file_infos {
+        print_file_info(info, args.verbose);
+    }
+
+    let current = plan::Plan::from_infos(&file_infos, output)?;
+    if let Some(path) = save {
+        current.save(path)?;
+        println!("Plan saved to {:?}", path);
+    }
+
+    if let Some(path) = diff {
+        let previous = plan::Plan::load(path)?;
+        let changes = plan::diff(&previous, &current);
+        plan::print_diff(&changes, &previous, &current);
+        if !changes.is_empty() {
+            return Err(plan::PlanChanged.into());
+        }
+    }
+
+    Ok(())
+}
+
+fn main() -> Result<ExitCode> {
+    let args = Args::parse();
+
+    let result = match args.profile.clone() {
+        None => run(args),
+        Some(profile_path) => {
+            profile::enable();
+            let result = {
+                let _span = profile::span("movcat");
+                run(args)
+            };
+            // Write the profile even if the run failed; slow failures are worth profiling too
+            profile::write(&profile_path)?;
+            result
+        }
+    };
+    match result {
+        // A changed plan is a result, not an error
+        Err(e) if e.is::<plan::PlanChanged>() => Ok(ExitCode::from(2)),
+        result => result.map(|()| ExitCode::SUCCESS),
+    }
+}
+
+fn run(args: Args) -> Result<()> {
+    if args.no_subprocess {
+        process::disable();
+    }
+
+    match &args.command {
+        Some(Command::Run { job, dry_run }) => return run_job(job, *dry_run, &args),
+        Some(Command::Plan { inputs, output, save, diff, filter }) => {
+            return run_plan(&args, inputs, output.as_deref(), save.as_deref(), diff.as_deref(), filter);
+        }
+        Some(Command::Compare { first, second }) => return run_compare(first, second),
+        Some(Command::Info { files, format }) => return run_info(files, *format, args.verbose),
+        Some(Command::Ls { dir, sort, reverse }) => {
+            let mut entries = ls::list(dir)?;
+            ls::sort(&mut entries, *sort, *reverse);
+            ls::print(&entries);
+            return Ok(());
+        }
+        None => {}
+    }
+
+    let output = args.output.clone().context("Output file path is required")?;
+    check_output_dir(&output, args.mkdirs)?;
+    check_also_output(&output, args.also_output.as_deref(), args.mkdirs)?;
+    check_publish_dir(&output, args.publish_dir.as_deref(), args.mkdirs)?;
+
+    // Check everything the requested options depend on before doing any work
+    let mut unavailable = check_backend_available(args.backend);
+    let schedule = check_schedule(&args, &mut unavailable);
+    let verify_decode = check_verify_decode(&args, &mut unavailable);
+    let mut upload_url = args.upload.as_deref();
+    if let Some(url) = upload_url {
+        let file_name = output.file_name().unwrap_or_default().to_string_lossy();
+        let destination = upload::Destination::parse(url, &file_name)?;
+        if let Err(e) = upload::check_tool(&destination) {
+            unavailable.push(Unavailable { option: "--upload".to_string(), reason: e.to_string(), optional: true });
+            upload_url = None;
+        }
+    }
+    report_unavailable(&unavailable, args.ignore_unavailable)?;
+
+    println!("Expanding input patterns...");
+    let input_files = expand_glob_patterns(&args.inputs)?;
+
+    println!("Found {} files:", input_files.len());
+    for file in &input_files {
+        println!("  {:?}", file);
+    }
+    println!();
+    let input_files = triage::check(input_files, args.skip_bad)?;
+
+    let merges = match args.group {
+        Some(group::Grouping::Signature) => group_inputs(&input_files, &output, &args.filter, upload_url)?,
+        None => vec![(output, input_files)],
+    };
+
+    // Ask about interrupted runs of every output before the first merge
+    let mut resume = Vec::new();
+    for (output, _) in &merges {
+        resume.push(check_leftover_partials(output, args.also_output.as_deref(), args.resume, args.restart,
+            std::io::stdin().is_terminal())?);
+    }
+
+    for ((output, input_files), resume) in merges.into_iter().zip(resume) {
+        let upload_destination = match upload_url {
+            Some(url) => {
+                let file_name = output.file_name().unwrap_or_default().to_string_lossy();
+                Some(upload::Destination::parse(url, &file_name)?)
+            }
+            None => None,
+        };
+        merge(&args, &input_files, output, resume, schedule, verify_decode, upload_destination.as_ref())?;
+    }
+
+    Ok(())
+}
+
+/// Splits the inputs into groups of matching track signatures, each with
+/// its own output path.
+fn group_inputs(files: &[PathBuf], output: &Path, filters: &[filter::Filter], upload_url: Option<&str>)
+    -> Result<Vec<(PathBuf, Vec<PathBuf>)>> {
+    let mut infos = Vec::new();
+    for file in files {
+        let info = analyze_mov_file(file)?;
+        if filters.iter().all(|f| f.matches(&info)) {
+            infos.push(info);
+        }
+    }
+    let groups = group::by_signature(&infos);
+    if groups.len() < 2 {
+        return Ok(vec![(output.to_path_buf(), groups.into_iter().flat_map(|(_, paths)| paths).collect())]);
+    }
+    if upload_url.is_some_and(|url| !url.ends_with('/')) {
+        anyhow::bail!("--upload must be a prefix ending in '/' when --group writes several outputs");
+    }
+
+    println!("Grouped {} inputs into {} merges by track signature:", infos.len(), groups.len());
+    let merges: Vec<(PathBuf, Vec<PathBuf>)> = groups.into_iter().enumerate()
+        .map(|(i, (signature, paths))| {
+            println!("  {:?}: {} file(s), {}", group::output_path(output, i + 1), paths.len(), signature);
+            (group::output_path(output, i + 1), paths)
+        })
+        .collect();
+    println!();
+    Ok(merges)
+}
+
+/// Validates, concatenates, publishes and uploads one merge.
+fn merge(args: &Args, input_files: &[PathBuf], output: PathBuf, resume: bool, schedule: schedule::Schedule,
+    verify_decode: bool, upload_destination: Option<&upload::Destination>) -> Result<()> {
+    println!("Analyzing input files...");
+    let file_infos = validate_input_files(input_files, &args.filter)?;
+    check_orientation(&file_infos, args.strict_orientation)?;
+    let file_infos = check_chronology(file_infos, args.auto_reorder);
+    let (file_infos, timeline_gaps_us) = apply_offsets(file_infos, args.offsets.as_deref())?;
+
+    for info in &file_infos {
+        print_file_info(info, args.verbose);
+    }
+    check_target(args.target, &file_infos, &output)?;
+
+    println!("Total files: {}", file_infos.len());
+    println!("Output file: {:?}", output);
+    if let Some(also_output) = &args.also_output {
+        println!("Also writing: {:?}", also_output);
+    }
+
+    // Perform concatenation
+    let mut options = OutputOptions {
+        max_moov_size: args.max_moov_size,
+        also_output: args.also_output.clone(),
+        key_rules: args.key_rules.clone(),
+        resume,
+        fill_gaps: args.fill_gaps,
+        timeline_gaps_us,
+        ..OutputOptions::default()
+    };
+    apply_target(args.target, &file_infos, &mut options);
+    schedule::wait(schedule)?;
+    concatenate_mov_files(&file_infos, &output, &options, args.backend, args.verbose)?;
+    report_audio_samples(&file_infos, &output)?;
+    if verify_decode {
+        verify_output(&file_infos, &output, &options)?;
+    }
+
+    if args.index {
+        write_index(&output)?;
+    }
+    if args.verbose {
+        report_output(&output)?;
+    }
+    let output = match &args.publish_dir {
+        Some(publish_dir) => publish_outputs(&output, args.also_output.as_deref(), args.index, publish_dir)?,
+        None => output,
+    };
+
+    if let Some(destination) = upload_destination {
+        println!("Uploading {:?}...", output);
+        let _span = profile::span("upload");
+        upload::upload(&output, destination, args.upload_size_only)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_analyze_mov_file_nonexistent() {
+        let path = PathBuf::from("nonexistent.mov");
+        let result = analyze_mov_file(&path);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_analyze_mov_file_counts_tracks() {
+        use movie::tests::{movie_file, trak, write_temp};
+
+        let data = movie_file(Some(b"qt  "), &[
+            trak(1, b"vide", 4, 0),
+            trak(2, b"soun", 8, 2),
+            trak(3, b"tmcd", 1, 4),
+        ]);
+        let path = write_temp("movcat_test_analyze.mov", &data);
+        let info = analyze_mov_file(&path).unwrap();
+        assert_eq!(info.track_count, 3);
+        assert_eq!(info.video_tracks, 1);
+        assert_eq!(info.audio_tracks, 1);
+        assert_eq!(info.duration, 6000);
+        assert_eq!(info.timescale, 600);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_partial_output_path() {
+        assert_eq!(partial_output_path(Path::new("out/final.mov")),
+            PathBuf::from("out/.final.movcat-partial.mov"));
+        assert_eq!(partial_output_path(Path::new("final")),
+            PathBuf::from(".final.movcat-partial"));
+    }
+
+    #[test]
+    fn test_check_leftover_partials() {
+        use movie::tests::{movie_file, trak, write_temp};
+
+        let output = std::env::temp_dir().join("movcat_test_leftover.mov");
+        assert!(!check_leftover_partials(&output, None, true, false, false).unwrap());
+
+        let partial = write_temp(".movcat_test_leftover.movcat-partial.mov",
+            &movie_file(Some(b"qt  "), &[trak(1, b"vide", 2, 0)]));
+        // Never overwritten without a decision
+        assert!(check_leftover_partials(&output, None, false, false, false).is_err());
+        assert!(check_leftover_partials(&output, None, true, false, false).unwrap());
+        assert!(partial.exists());
+
+        assert!(!check_leftover_partials(&output, None, false, true, false).unwrap());
+        assert!(!partial.exists());
+    }
+
+    #[test]
+    fn test_resume_partials() {
+        use movie::tests::{movie_file, trak, write_temp};
+
+        let input = write_temp("movcat_test_resume_input.mov", &movie_file(Some(b"qt  "), &[trak(1, b"vide", 2, 0)]));
+        let output = std::env::temp_dir().join("movcat_test_resume.mov");
+        let outputs = vec![(output.clone(), false)];
+        let partial_paths = vec![partial_output_path(&output)];
+        let checkpoint = checkpoint_path(&output);
+        let current = Checkpoint {
+            plan: plan::Plan::from_infos(&[analyze_mov_file(&input).unwrap()], Some(&output)).unwrap(),
+            filelist: format!("file '{}'\n", input.display()),
+            chapters: String::new(),
+            ffmpeg_args: vec!["-c".to_string(), "copy".to_string()],
+        };
+
+        // Without a checkpoint the partial file cannot be trusted
+        assert!(resume_partials(&outputs, &partial_paths, &checkpoint, &current).is_err());
+
+        // A half-written partial file cannot be finished
+        current.save(&checkpoint).unwrap();
+        std::fs::write(&partial_paths[0], [0, 0, 0, 8, b'm', b'd', b'a', b't']).unwrap();
+        assert!(resume_partials(&outputs, &partial_paths, &checkpoint, &current).is_err());
+
+        // Nor one written with other settings, e.g. a trim
+        std::fs::copy(&input, &partial_paths[0]).unwrap();
+        let trimmed = Checkpoint { filelist: current.filelist.clone() + "inpoint 1\n", ..Checkpoint::load(&checkpoint).unwrap() };
+        let error = resume_partials(&outputs, &partial_paths, &checkpoint, &trimmed).unwrap_err();
+        assert!(error.to_string().contains("file list"));
+
+        // A complete one is moved into place
+        resume_partials(&outputs, &partial_paths, &checkpoint, &current).unwrap();
+        assert!(output.exists() && !partial_paths[0].exists() && !checkpoint.exists());
+
+        for path in [input, output] {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    #[test]
+    fn test_check_output_dir() {
+        let dir = std::env::temp_dir().join("movcat_test_output_dir");
+        let _ = std::fs::remove_dir_all(&dir);
+        let output = dir.join("nested").join("out.mov");
+
+        let err = check_output_dir(&output, false).unwrap_err();
+        assert!(err.to_string().contains("--mkdirs"));
+
+        check_output_dir(&output, true).unwrap();
+        assert!(output.parent().unwrap().is_dir());
+        // The write probe is cleaned up
+        assert_eq!(std::fs::read_dir(output.parent().unwrap()).unwrap().count(), 0);
+
+        assert!(check_output_dir(&dir, false).is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_check_also_output() {
+        let output = std::env::temp_dir().join("movcat_test_master.mov");
+        let review = std::env::temp_dir().join("movcat_test_review.mp4");
+        assert!(check_also_output(&output, None, false).is_ok());
+        assert!(check_also_output(&output, Some(&review), false).is_ok());
+        assert!(check_also_output(&output, Some(&output), false).is_err());
+    }
+
+    #[test]
+    fn test_report_unavailable() {
+        assert!(report_unavailable(&[], false).is_ok());
+
+        let upload = || Unavailable {
+            option: "--upload".to_string(),
+            reason: "'aws' is required".to_string(),
+            optional: true,
+        };
+        let err = report_unavailable(&[upload()], false).unwrap_err().to_string();
+        assert!(err.contains("--upload: 'aws' is required"));
+        assert!(err.contains("--ignore-unavailable"));
+        assert!(report_unavailable(&[upload()], true).is_ok());
+
+        // Required capabilities cannot be skipped
+        let backend = Unavailable {
+            option: "concatenation (ffmpeg backend)".to_string(),
+            reason: "ffmpeg was not found in PATH".to_string(),
+            optional: false,
+        };
+        let err = report_unavailable(&[upload(), backend], true).unwrap_err().to_string();
+        assert!(err.contains("--upload") && err.contains("ffmpeg was not found"));
+    }
+
+    #[test]
+    fn test_finalize_output() {
+        use movie::tests::{movie_file, trak, write_temp};
+
+        let output = std::env::temp_dir().join("movcat_test_finalize.mov");
+        let partial = write_temp(".movcat_test_finalize.movcat-partial.mov",
+            &movie_file(Some(b"qt  "), &[trak(1, b"vide", 2, 0)]));
+        finalize_output(&partial, &output).unwrap();
+        assert!(!partial.exists());
+        assert!(analyze_mov_file(&output).is_ok());
+        let _ = std::fs::remove_file(&output);
+
+        // A partial file without a moov atom is never moved into place
+        let partial = write_temp(".movcat_test_unfinished.movcat-partial.mov", &[0, 0, 0, 8, b'm', b'd', b'a', b't']);
+        let output = std::env::temp_dir().join("movcat_test_unfinished.mov");
+        assert!(finalize_output(&partial, &output).is_err());
+        assert!(!output.exists());
+        let _ = std::fs::remove_file(&partial);
+    }
+
+    #[test]
+    fn test_publish_outputs() {
+        use movie::tests::{movie_file, trak};
+
+        let work = std::env::temp_dir().join("movcat_test_publish_work");
+        let done = std::env::temp_dir().join("movcat_test_publish_done");
+        let _ = std::fs::remove_dir_all(&work);
+        let _ = std::fs::remove_dir_all(&done);
+        std::fs::create_dir_all(&work).unwrap();
+        let output = work.join("out.mov");
+
+        assert!(check_publish_dir(&output, Some(&work), false).is_err());
+        assert!(check_publish_dir(&output, Some(&done), false).unwrap_err().to_string().contains("--mkdirs"));
+        check_publish_dir(&output, Some(&done), true).unwrap();
+
+        // An output that fails verification stays in the working directory
+        std::fs::write(&output, [0, 0, 0, 8, b'm', b'd', b'a', b't']).unwrap();
+        assert!(publish_outputs(&output, None, false, &done).is_err());
+        assert!(output.exists() && !done.join("out.mov").exists());
+
+        std::fs::write(&output, movie_file(Some(b"qt  "), &[trak(1, b"vide", 2, 0)])).unwrap();
+        std::fs::write(index::sidecar_path(&output), "{}").unwrap();
+        assert_eq!(publish_outputs(&output, None, true, &done).unwrap(), done.join("out.mov"));
+        assert!(!output.exists());
+        assert!(analyze_mov_file(&done.join("out.mov")).is_ok());
+        assert!(index::sidecar_path(&done.join("out.mov")).exists());
+
+        let _ = std::fs::remove_dir_all(&work);
+        let _ = std::fs::remove_dir_all(&done);
+    }
+
+    #[test]
+    fn test_track_difference() {
+        use atoms::tests::atom;
+        use codec_config::tests::avcc;
+        use movie::tests::{movie_file, write_temp, TestTrack};
+
+        let analyzed = |name: &str, codec: &[u8; 4], profile: u8| {
+            let track = TestTrack {
+                codec: *codec,
+                sample_entry_children: atom(b"avcC", &avcc(profile, 40, &[0x67, profile])),
+                ..TestTrack::new(1, b"vide")
+            };
+            let path = write_temp(name, &movie_file(None, &[track.build()]));
+            let info = analyze_mov_file(&path).unwrap();
+            let _ = std::fs::remove_file(&path);
+            info
+        };
+        let high = analyzed("movcat_test_config_high.mov", b"avc1", 100);
+        let main = analyzed("movcat_test_config_main.mov", b"avc1", 77);
+        let hevc = analyzed("movcat_test_config_hevc.mov", b"hvc1", 100);
+
+        assert_eq!(track_difference(&high.tracks[0], &high.tracks[0]), None);
+        let difference = track_difference(&high.tracks[0], &main.tracks[0]).unwrap();
+        assert!(difference.starts_with("    profile: High (100) -> Main (77) (breaks stream copy)"));
+        assert_eq!(track_difference(&high.tracks[0], &hevc.tracks[0]).unwrap(),
+            "    codec: avc1 -> hvc1 (breaks stream copy)");
+    }
+
+    #[test]
+    fn test_validate_legacy_codecs() {
+        use movie::tests::{movie_file, write_temp, TestTrack};
+
+        let dv = TestTrack { codec: *b"dvc ", samples: 2, uniform_size: 120000, ..TestTrack::new(1, b"vide") };
+        let path = write_temp("movcat_test_dv_ok.mov", &movie_file(None, &[dv.build()]));
+        assert!(validate_input_files(std::slice::from_ref(&path), &[]).is_ok());
+        let _ = std::fs::remove_file(&path);
+
+        let dv = TestTrack { codec: *b"dvcp", samples: 2, sample_sizes: vec![144000, 120000], ..TestTrack::new(1, b"vide") };
+        let path = write_temp("movcat_test_dv_varying.mov", &movie_file(None, &[dv.build()]));
+        assert!(validate_input_files(std::slice::from_ref(&path), &[]).is_err());
+        let _ = std::fs::remove_file(&path);
+
+        let mjpeg = TestTrack { codec: *b"mjpa", composition_offsets: true, ..TestTrack::new(1, b"vide") };
+        let path = write_temp("movcat_test_mjpeg_ctts.mov", &movie_file(None, &[mjpeg.build()]));
+        assert!(validate_input_files(std::slice::from_ref(&path), &[]).is_err());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_validate_input_files_filters() {
+        use movie::tests::{movie_file, write_temp, TestTrack};
+
+        let uhd = TestTrack { codec: *b"hvc1", width: 3840, height: 2160, ..TestTrack::new(1, b"vide") };
+        let hd = TestTrack { width: 1920, height: 1080, ..TestTrack::new(1, b"vide") };
+        let uhd_path = write_temp("movcat_test_filter_validate_uhd.mov", &movie_file(None, &[uhd.build()]));
+        let hd_path = write_temp("movcat_test_filter_validate_hd.mov", &movie_file(None, &[hd.build()]));
+        let files = [hd_path.clone(), uhd_path.clone()];
+
+        let filters = [filter::Filter::parse("codec=hevc,height>=2160").unwrap()];
+        let infos = validate_input_files(&files, &filters).unwrap();
+        assert_eq!(infos.len(), 1);
+        assert_eq!(infos[0].path, uhd_path);
+
+        let filters = [filter::Filter::parse("codec=prores").unwrap()];
+        assert!(validate_input_files(&files, &filters).is_err());
+
+        let _ = std::fs::remove_file(&uhd_path);
+        let _ = std::fs::remove_file(&hd_path);
+    }
+
+    #[test]
+    fn test_validate_chunks_outside_mdat() {
+        use movie::tests::{movie_file, write_temp, TestTrack};
+
+        let mut video = TestTrack::new(1, b"vide").build();
+        let stco = video.windows(4).position(|w| w == b"stco").unwrap();
+        video[stco + 12..stco + 16].copy_from_slice(&9999u32.to_be_bytes());
+        let path = write_temp("movcat_test_chunks_outside.mov", &movie_file(None, &[video]));
+        let err = validate_input_files(std::slice::from_ref(&path), &[]).unwrap_err();
+        assert!(err.to_string().contains("outside its media data"));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_concat_filelist_durations() {
+        use movie::tests::{movie_file, trak, write_temp, TestTrack};
+
+        let dv = TestTrack { codec: *b"dvc ", uniform_size: 120000, ..TestTrack::new(1, b"vide") };
+        let dv_path = write_temp("movcat_test_filelist_dv.mov", &movie_file(None, &[dv.build()]));
+        let avc_path = write_temp("movcat_test_filelist_avc.mov", &movie_file(None, &[trak(1, b"vide", 1, 0)]));
+
+        let mut infos = validate_input_files(&[dv_path.clone(), avc_path.clone()], &[]).unwrap();
+        infos[1].trim_start = Some(1.5);
+        let content = concat_filelist(&infos, None).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("file '") && lines[0].ends_with("movcat_test_filelist_dv.mov'"));
+        assert_eq!(lines[1], "duration 10");
+        assert!(lines[2].ends_with("movcat_test_filelist_avc.mov'"));
+        assert_eq!(lines[3], "inpoint 1.5");
+
+        // A trimmed DV clip lasts only as long as its used part
+        infos[0].trim_start = Some(2.0);
+        infos[0].trim_end = Some(9.5);
+        let content = concat_filelist(&infos, None).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines[1..4], ["duration 7.5", "inpoint 2", "outpoint 9.5"]);
+
+        let _ = std::fs::remove_file(&dv_path);
+        let _ = std::fs::remove_file(&avc_path);
+    }
+
+    #[test]
+    fn test_filelist_path() {
+        assert_eq!(filelist_path(r"\\?\C:\Clips\a.mov", true), "C:/Clips/a.mov");
+        assert_eq!(filelist_path(r"\\?\D:\b.mov", true), "D:/b.mov");
+        assert_eq!(filelist_path(r"\\?\UNC\nas\media\c.mov", true), "//nas/media/c.mov");
+        assert_eq!(filelist_path(r"\\nas\media\c.mov", true), "//nas/media/c.mov");
+        assert_eq!(filelist_path(r"E:\Kid's party\d.mov", true), r"E:/Kid'\''s party/d.mov");
+        // Backslashes are valid in Unix file names and left alone
+        assert_eq!(filelist_path(r"/media/a\b.mov", false), r"/media/a\b.mov");
+        assert_eq!(filelist_path("/media/it's.mov", false), r"/media/it'\''s.mov");
+    }
+
+    #[test]
+    fn test_concat_filelist_join_timing() {
+        use movie::tests::{movie_file, write_temp, TestTrack};
+
+        let ntsc = TestTrack { timescale: 30000, media_duration: 300300, ..TestTrack::new(1, b"vide") };
+        let path = write_temp("movcat_test_filelist_ntsc.mov", &movie_file(None, &[ntsc.build()]));
+        let infos = validate_input_files(&[path.clone(), path.clone(), path.clone()], &[]).unwrap();
+
+        let timing = joins::plan(&infos);
+        let content = concat_filelist(&infos, timing.as_ref()).unwrap();
+        let durations: Vec<&str> = content.lines().filter(|line| line.starts_with("duration")).collect();
+        // The exact track duration, not the 10 s the mvhd rounds it to; the
+        // last input needs none
+        assert_eq!(durations, vec!["duration 10.01", "duration 10.01"]);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("4096"), Ok(4096));
+        assert_eq!(parse_size("512K"), Ok(512 * 1024));
+        assert_eq!(parse_size("64m"), Ok(64 * 1024 * 1024));
+        assert_eq!(parse_size("1G"), Ok(1024 * 1024 * 1024));
+        assert!(parse_size("M").is_err());
+        assert!(parse_size("12X").is_err());
+        // Too large for 64 bits once multiplied
+        assert!(parse_size("99999999999G").is_err());
+    }
+
+    #[test]
+    fn test_check_estimated_moov_size() {
+        let mut a = test_info(600, 6000);
+        let mut b = test_info(600, 6000);
+        a.moov_size = 3000;
+        b.moov_size = 2000;
+        let infos = [a, b];
+        assert!(check_estimated_moov_size(&infos, None).is_ok());
+        assert!(check_estimated_moov_size(&infos, Some(5000)).is_ok());
+        assert!(check_estimated_moov_size(&infos, Some(4999)).is_err());
+    }
+
+    #[test]
+    fn test_check_orientation() {
+        use movie::tests::{movie_file, write_temp, TestTrack};
+
+        let landscape = TestTrack { width: 1920, height: 1080, ..TestTrack::new(1, b"vide") };
+        let rotated = TestTrack { width: 1920, height: 1080, rotation: 90, ..TestTrack::new(1, b"vide") };
+        let a = write_temp("movcat_test_orientation_a.mov", &movie_file(None, &[landscape.build()]));
+        let b = write_temp("movcat_test_orientation_b.mov", &movie_file(None, &[rotated.build()]));
+
+        let same = validate_input_files(&[a.clone(), a.clone()], &[]).unwrap();
+        assert!(check_orientation(&same, true).is_ok());
+
+        let mixed = validate_input_files(&[a.clone(), b.clone(), a.clone()], &[]).unwrap();
+        assert!(check_orientation(&mixed, false).is_ok());
+        assert!(check_orientation(&mixed, true).is_err());
+
+        let _ = std::fs::remove_file(&a);
+        let _ = std::fs::remove_file(&b);
+    }
+
+    #[test]
+    fn test_chronological_order() {
+        let mut infos: Vec<MovInfo> = (0..3).map(|_| test_info(600, 6000)).collect();
+        assert_eq!(chronological_order(&infos), None);
+
+        // Camera rolled over: FILE0998, FILE0999, FILE0001
+        infos[0].creation_time = Some(200);
+        infos[1].creation_time = Some(300);
+        infos[2].creation_time = Some(100);
+        assert_eq!(chronological_order(&infos), Some(vec![2, 0, 1]));
+
+        infos[2].creation_time = Some(300);
+        assert_eq!(chronological_order(&infos), None);
+    }
+
+    #[test]
+    fn test_check_chronology_auto_reorder() {
+        let mut infos: Vec<MovInfo> = ["FILE0999.MOV", "FILE0001.MOV"].iter().map(|name| {
+            let mut info = test_info(600, 6000);
+            info.path = PathBuf::from(name);
+            info
+        }).collect();
+        infos[0].creation_time = Some(500);
+        infos[1].creation_time = Some(600);
+
+        // Already chronological
+        let infos = check_chronology(infos, true);
+        assert_eq!(infos[0].path, PathBuf::from("FILE0999.MOV"));
+
+        let mut infos = infos;
+        infos[1].creation_time = Some(400);
+        let kept = check_chronology(infos, false);
+        assert_eq!(kept[0].path, PathBuf::from("FILE0999.MOV"));
+        let reordered = check_chronology(kept, true);
+        assert_eq!(reordered[0].path, PathBuf::from("FILE0001.MOV"));
+        assert_eq!(reordered[1].path, PathBuf::from("FILE0999.MOV"));
+    }
+
+    #[test]
+    fn test_validate_input_files_empty() {
+        let files = vec![];
+        let result = validate_input_files(&files, &[]);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_validate_input_files_nonexistent() {
+        let files = vec![PathBuf::from("nonexistent.mov")];
+        let result = validate_input_files(&files, &[]);
+        assert!(result.is_err());
+    }
+
+    #[test]
     fn test_ffmpeg_availability() {
         let result = std::process::Command::new("ffmpeg")
             .arg("-version")
@@ -268,6 +2301,99 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_args_run_subcommand() {
+        let args = Args::try_parse_from(["movcat", "run", "job.toml", "-v"]).unwrap();
+        assert!(matches!(args.command, Some(Command::Run { ref job, dry_run: false }) if job == Path::new("job.toml")));
+        assert!(args.verbose);
+
+        let args = Args::try_parse_from(["movcat", "-o", "out.mov", "a.mov"]).unwrap();
+        assert!(args.command.is_none());
+        assert_eq!(args.output, Some(PathBuf::from("out.mov")));
+
+        assert!(Args::try_parse_from(["movcat", "a.mov"]).is_err());
+    }
+
+    #[test]
+    fn test_used_duration_with_trims() {
+        let mut info = test_info(600, 6000);
+        assert_eq!(info.used_duration_secs(), 10.0);
+        info.trim_start = Some(2.0);
+        assert_eq!(info.used_duration_secs(), 8.0);
+        info.trim_end = Some(7.5);
+        assert_eq!(info.used_duration_secs(), 5.5);
+        info.trim_end = Some(30.0);
+        assert_eq!(info.used_duration_secs(), 8.0);
+    }
+
+    fn test_info(timescale: u32, duration: u64) -> MovInfo {
+        MovInfo {
+            path: PathBuf::from("test.mov"),
+            duration,
+            timescale,
+            major_brand: "qt".to_string(),
+            track_count: 0,
+            video_tracks: 0,
+            audio_tracks: 0,
+            tracks: Vec::new(),
+            moov_size: 0,
+            mdat_regions: Vec::new(),
+            creation_time: None,
+            trim_start: None,
+            trim_end: None,
+        }
+    }
+
+    #[test]
+    fn test_sort_paths_separator_independent() {
+        // Comparing the whole path strings would put "a.b/..." first, since
+        // '.' < '/'; comparing by component does not
+        let mut paths = vec![PathBuf::from("a.b").join("c.mov"), PathBuf::from("a").join("b.mov")];
+        sort_paths(&mut paths);
+        assert_eq!(paths, vec![PathBuf::from("a").join("b.mov"), PathBuf::from("a.b").join("c.mov")]);
+
+        let mut paths = vec![PathBuf::from("clips-2").join("a.mov"), PathBuf::from("clips").join("z.mov")];
+        sort_paths(&mut paths);
+        assert_eq!(paths[0], PathBuf::from("clips").join("z.mov"));
+    }
+
+    #[test]
+    fn test_sort_paths_code_point_order() {
+        // Code point order: digits < uppercase < lowercase < non-ASCII,
+        // regardless of locale collation rules
+        let mut paths: Vec<PathBuf> = ["été.mov", "b.mov", "B.mov", "10.mov", "2.mov", "Z.mov", "ä.mov"]
+            .iter().map(PathBuf::from).collect();
+        sort_paths(&mut paths);
+        let names: Vec<String> = paths.iter().map(|p| p.to_string_lossy().into_owned()).collect();
+        assert_eq!(names, vec!["10.mov", "2.mov", "B.mov", "Z.mov", "b.mov", "ä.mov", "été.mov"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_sort_paths_non_unicode() {
+        use std::os::unix::ffi::OsStrExt;
+
+        // Both names read as "\u{fffd}.mov" when converted lossily
+        let name = |bytes: &[u8]| PathBuf::from(std::ffi::OsStr::from_bytes(bytes));
+        let mut paths = vec![name(b"\xff.mov"), name(b"\xfe.mov"), name(b"a.mov")];
+        sort_paths(&mut paths);
+        assert_eq!(paths, vec![name(b"a.mov"), name(b"\xfe.mov"), name(b"\xff.mov")]);
+    }
+
+    #[test]
+    fn test_sort_paths_ties() {
+        // "./x.mov" and "x.mov" name the same file; the spelling breaks the tie
+        let mut paths = vec![PathBuf::from("x.mov"), PathBuf::from("w.mov"), PathBuf::from(".").join("x.mov")];
+        sort_paths(&mut paths);
+        assert_eq!(paths, vec![PathBuf::from("w.mov"), PathBuf::from(".").join("x.mov"), PathBuf::from("x.mov")]);
+
+        // Sorting is independent of the input order
+        let mut reversed = paths.clone();
+        reversed.reverse();
+        sort_paths(&mut reversed);
+        assert_eq!(reversed, paths);
+    }
+
     #[test]
     fn test_expand_glob_patterns_no_wildcards() {
         let patterns = vec!["file1.mov".to_string(), "file2.mov".to_string()];
@@ -286,6 +2412,36 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_expand_glob_patterns_negative() {
+        let dir = std::env::temp_dir().join("movcat_test_negative");
+        std::fs::create_dir_all(dir.join("trash")).unwrap();
+        for name in ["a.mov", "b.mov", "trash/c.mov"] {
+            std::fs::write(dir.join(name), b"").unwrap();
+        }
+
+        let patterns = vec![
+            format!("{}/**/*.mov", dir.display()),
+            "!**/trash/**".to_string(),
+            "!**/b.mov".to_string(),
+            "!*.mov".to_string(),
+        ];
+        let files = expand_glob_patterns(&patterns).unwrap();
+        assert_eq!(files, vec![dir.join("a.mov")]);
+
+        let patterns = vec![format!("{}/**/*.mov", dir.display()), "!**/*.mov".to_string()];
+        assert!(expand_glob_patterns(&patterns).is_err());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_expand_glob_patterns_only_negative() {
+        let patterns = vec!["!*.mov".to_string()];
+        let result = expand_glob_patterns(&patterns);
+        assert!(result.is_err());
+    }
+
     #[test]
     fn test_expand_glob_patterns_nonexistent_pattern() {
         let patterns = vec!["nonexistent_*.mov".to_string()];
@@ -0,0 +1,867 @@
+//! Parsing of the movie header (ftyp/moov) into the pieces movcat reports on.
+
+use crate::atoms::{self, ByteReader, FourCC};
+use crate::codec_config::{self, CodecConfig};
+use crate::profile;
+use crate::stereo::{self, StereoLayout};
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::path::Path;
+
+#[derive(Debug)]
+pub struct Movie {
+    pub major_brand: String,
+    /// Seconds since 1904-01-01 UTC, zero when unset.
+    pub creation_time: u64,
+    pub timescale: u32,
+    pub duration: u64,
+    pub moov_size: u64,
+    /// Byte ranges of the payloads of all top-level mdat atoms.
+    pub mdat_regions: Vec<(u64, u64)>,
+    pub tracks: Vec<Track>,
+}
+
+#[derive(Debug)]
+pub struct Track {
+    pub id: u32,
+    pub handler: FourCC,
+    /// Format of the first sample description, e.g. `avc1` or `mp4a`.
+    pub codec: FourCC,
+    /// Decoded configuration record (avcC, hvcC, esds) of that description.
+    pub config: Option<CodecConfig>,
+    /// Stereo layout described in the sample description (`st3d`, `vexu`).
+    pub stereo: Option<StereoLayout>,
+    /// Media timescale and duration from the mdhd; zero when missing.
+    pub timescale: u32,
+    pub media_duration: u64,
+    /// Sum of the sample durations in the stts, in the media timescale.
+    pub decode_duration: u64,
+    /// Nominal sample rate and channel count of an audio sample
+    /// description; zero otherwise.
+    pub sample_rate: u32,
+    pub channels: u32,
+    pub sample_count: u32,
+    pub stts_entries: u32,
+    pub stsc_entries: u32,
+    /// Number of per-sample size entries; zero when all samples share one size.
+    pub stsz_entries: u32,
+    pub chunk_count: u32,
+    /// File offsets of the chunks from stco/co64.
+    pub chunk_offsets: Vec<u64>,
+    /// Size shared by every sample, if all samples have the same size.
+    pub constant_sample_size: Option<u32>,
+    /// Whether the track reorders frames (has composition offsets).
+    pub has_composition_offsets: bool,
+    /// Presentation size from the track header, before rotation.
+    pub width: u32,
+    pub height: u32,
+    /// Clockwise rotation in degrees from the track matrix (0, 90, 180, 270).
+    pub rotation: u32,
+    /// Entries of the edit list (elst); empty without one.
+    pub edits: Vec<Edit>,
+}
+
+/// One entry of an edit list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edit {
+    /// Duration of the segment in the movie timescale.
+    pub segment_duration: u64,
+    /// Start of the segment in the media timescale; -1 for an empty edit.
+    pub media_time: i64,
+}
+
+impl Track {
+    /// Video tracks, including HEIF image sequences (`pict`), which some
+    /// cameras use for timelapses.
+    pub fn is_video(&self) -> bool {
+        matches!(&self.handler, b"vide" | b"pict")
+    }
+
+    pub fn is_audio(&self) -> bool {
+        &self.handler == b"soun"
+    }
+
+    /// Width and height as displayed, after applying the rotation.
+    pub fn display_size(&self) -> (u32, u32) {
+        if self.rotation % 180 == 90 {
+            (self.height, self.width)
+        } else {
+            (self.width, self.height)
+        }
+    }
+
+    /// Frames per second, from the sample count and the media duration.
+    pub fn frame_rate(&self) -> Option<f64> {
+        (self.media_duration > 0 && self.timescale > 0)
+            .then(|| self.sample_count as f64 * self.timescale as f64 / self.media_duration as f64)
+    }
+
+    /// Decoded audio samples (per channel) at the nominal sample rate. For
+    /// PCM every stored sample is one; an AAC packet holds 1024.
+    pub fn audio_sample_count(&self) -> Option<u64> {
+        if !self.is_audio() || self.sample_rate == 0 || self.timescale == 0 {
+            return None;
+        }
+        // Audio timescales are normally the sample rate itself
+        Some((self.decode_duration as u128 * self.sample_rate as u128 / self.timescale as u128) as u64)
+    }
+
+    /// Presentation duration in seconds from an edit list that changes the
+    /// timing: one that skips media (AAC priming, a trimmed start), delays
+    /// it with an empty edit, or shows less or more than the media. `None`
+    /// without an edit list or for one that presents the media unchanged.
+    pub fn edited_duration_secs(&self, movie_timescale: u32) -> Option<f64> {
+        if self.edits.is_empty() || movie_timescale == 0 {
+            return None;
+        }
+        let edited = self.edits.iter().map(|edit| edit.segment_duration).sum::<u64>() as f64 / movie_timescale as f64;
+        if let [Edit { media_time: 0, .. }] = self.edits[..] {
+            // Writers round the segment to the coarser movie timescale
+            let media = self.media_duration as f64 / self.timescale.max(1) as f64;
+            if self.timescale == 0 || (edited - media).abs() <= 1.0 / movie_timescale as f64 {
+                return None;
+            }
+        }
+        Some(edited)
+    }
+
+    /// Timelapse footage: a video track below 10 frames per second, usually
+    /// with large, mostly intra-coded frames.
+    pub fn is_timelapse(&self) -> bool {
+        self.is_video() && self.frame_rate().is_some_and(|fps| fps < TIMELAPSE_MAX_FRAME_RATE)
+    }
+
+    /// DV and Motion JPEG: intra-only legacy codecs found in old camcorder
+    /// footage.
+    pub fn is_dv_or_mjpeg(&self) -> bool {
+        is_dv(&self.codec) || matches!(&self.codec, b"jpeg" | b"mjpa")
+    }
+}
+
+const TIMELAPSE_MAX_FRAME_RATE: f64 = 10.0;
+
+/// Counts the chunks of a track that do not start inside any media data
+/// region, which means the file is truncated or damaged.
+pub fn chunks_outside(track: &Track, mdat_regions: &[(u64, u64)]) -> usize {
+    track.chunk_offsets.iter()
+        .filter(|&&offset| !mdat_regions.iter().any(|&(start, end)| offset >= start && offset < end))
+        .count()
+}
+
+pub fn is_dv(codec: &FourCC) -> bool {
+    matches!(codec, b"dvc " | b"dvcp" | b"dvpp" | b"dv5n" | b"dv5p" | b"dvh5" | b"dvh6")
+}
+
+/// Returns FFmpeg's name for the codec of a sample description format.
+pub fn codec_name(codec: &FourCC) -> Option<&'static str> {
+    Some(match codec {
+        b"avc1" | b"avc3" => "h264",
+        b"hvc1" | b"hev1" => "hevc",
+        b"apch" | b"apcn" | b"apcs" | b"apco" | b"ap4h" | b"ap4x" => "prores",
+        b"mp4v" => "mpeg4",
+        b"av01" => "av1",
+        b"vp09" => "vp9",
+        b"jpeg" | b"mjpa" => "mjpeg",
+        codec if is_dv(codec) => "dvvideo",
+        b"raw " => "rawvideo",
+        b"mp4a" => "aac",
+        b"ac-3" => "ac3",
+        b"ec-3" => "eac3",
+        b"alac" => "alac",
+        b"Opus" => "opus",
+        b"sowt" => "pcm_s16le",
+        b"twos" => "pcm_s16be",
+        b"in24" => "pcm_s24be",
+        b"in32" => "pcm_s32be",
+        b"fl32" => "pcm_f32be",
+        b"fl64" => "pcm_f64be",
+        b"lpcm" => "pcm_s16le",
+        _ => return None,
+    })
+}
+
+/// Formats a QuickTime timestamp (seconds since 1904-01-01) as UTC.
+pub fn format_timestamp(seconds: u64) -> String {
+    // Days between 1904-01-01 and 1970-01-01
+    const UNIX_OFFSET_DAYS: i64 = 24107;
+    let days = (seconds / 86400) as i64 - UNIX_OFFSET_DAYS;
+    let secs = seconds % 86400;
+
+    // Civil date from days since the Unix epoch (Howard Hinnant's algorithm)
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year, month, day, secs / 3600, secs % 3600 / 60, secs % 60)
+}
+
+/// Reads the ftyp and moov atoms of a QuickTime / MP4 file.
+pub fn read_movie(path: &Path) -> Result<Movie> {
+    let mut file = File::open(path)
+        .with_context(|| format!("Failed to open file: {:?}", path))?;
+    let top_level = {
+        let _span = profile::span("scan atoms");
+        atoms::top_level_atoms(&mut file)
+            .with_context(|| format!("Failed to read atoms of {:?}", path))?
+    };
+
+    // Files without ftyp are classic QuickTime movies
+    let major_brand = match top_level.iter().find(|a| &a.kind == b"ftyp") {
+        Some(ftyp) => {
+            let data = atoms::read_payload(&mut file, ftyp)?;
+            let brand = ByteReader::new(&data).fourcc()?;
+            atoms::fourcc_to_string(&brand).trim_end().to_string()
+        }
+        None => "qt".to_string(),
+    };
+
+    let moov = top_level.iter().find(|a| &a.kind == b"moov")
+        .with_context(|| format!("No moov atom found in {:?}", path))?;
+    let data = {
+        let _span = profile::span("read moov");
+        atoms::read_payload(&mut file, moov)?
+    };
+
+    let _span = profile::span("parse moov");
+    let mut movie = parse_moov(&data)
+        .with_context(|| format!("Failed to parse moov atom of {:?}", path))?;
+    movie.major_brand = major_brand;
+    movie.moov_size = moov.size;
+    // Recorders may split media data over several mdat atoms, for example
+    // one per 4 GB, possibly with free/wide padding in between
+    movie.mdat_regions = top_level.iter()
+        .filter(|a| &a.kind == b"mdat")
+        .map(|a| (a.data_offset(), a.offset + a.size))
+        .collect();
+    Ok(movie)
+}
+
+fn parse_moov(data: &[u8]) -> Result<Movie> {
+    let mvhd = atoms::find_child(data, b"mvhd")?.context("No mvhd atom found")?;
+    let mut reader = ByteReader::new(mvhd);
+    let (version, _) = reader.version_and_flags()?;
+    let (creation_time, timescale, duration) = if version == 1 {
+        let creation_time = reader.u64()?;
+        reader.skip(8)?;
+        (creation_time, reader.u32()?, reader.u64()?)
+    } else {
+        let creation_time = reader.u32()? as u64;
+        reader.skip(4)?;
+        (creation_time, reader.u32()?, reader.u32()? as u64)
+    };
+
+    let mut tracks = Vec::new();
+    for (kind, trak) in atoms::children(data)? {
+        if &kind == b"trak" {
+            tracks.push(parse_trak(trak)?);
+        }
+    }
+
+    Ok(Movie {
+        major_brand: String::new(),
+        creation_time,
+        timescale,
+        duration,
+        moov_size: 0,
+        mdat_regions: Vec::new(),
+        tracks,
+    })
+}
+
+fn parse_trak(data: &[u8]) -> Result<Track> {
+    let tkhd = atoms::find_child(data, b"tkhd")?.context("No tkhd atom found")?;
+    let mut reader = ByteReader::new(tkhd);
+    let (version, _) = reader.version_and_flags()?;
+    reader.skip(if version == 1 { 16 } else { 8 })?;
+    let id = reader.u32()?;
+
+    // Older writers sometimes emit a tkhd without matrix and dimensions
+    let (mut rotation, mut width, mut height) = (0, 0, 0);
+    if reader.skip(if version == 1 { 12 } else { 8 }).is_ok() && reader.remaining() >= 60 {
+        reader.skip(16)?;
+        let a = reader.u32()? as i32;
+        let b = reader.u32()? as i32;
+        reader.skip(28)?;
+        rotation = matrix_rotation(a, b);
+        width = reader.u32()? >> 16;
+        height = reader.u32()? >> 16;
+    }
+
+    let handler = match atoms::find_path(data, &[b"mdia", b"hdlr"])? {
+        Some(hdlr) => {
+            let mut reader = ByteReader::new(hdlr);
+            reader.skip(8)?;
+            reader.fourcc()?
+        }
+        None => *b"    ",
+    };
+
+    let (mut timescale, mut media_duration) = (0, 0);
+    if let Some(mdhd) = atoms::find_path(data, &[b"mdia", b"mdhd"])? {
+        let mut reader = ByteReader::new(mdhd);
+        let (version, _) = reader.version_and_flags()?;
+        if version == 1 {
+            reader.skip(16)?;
+            timescale = reader.u32()?;
+            media_duration = reader.u64()?;
+        } else {
+            reader.skip(8)?;
+            timescale = reader.u32()?;
+            media_duration = reader.u32()? as u64;
+        }
+    }
+
+    let mut track = Track {
+        id,
+        handler,
+        codec: *b"    ",
+        config: None,
+        stereo: None,
+        timescale,
+        media_duration,
+        decode_duration: 0,
+        sample_rate: 0,
+        channels: 0,
+        sample_count: 0,
+        stts_entries: 0,
+        stsc_entries: 0,
+        stsz_entries: 0,
+        chunk_count: 0,
+        chunk_offsets: Vec::new(),
+        constant_sample_size: None,
+        has_composition_offsets: false,
+        width,
+        height,
+        rotation,
+        edits: Vec::new(),
+    };
+
+    track.edits = edit_list(data)?;
+
+    let Some(stbl) = atoms::find_path(data, &[b"mdia", b"minf", b"stbl"])? else {
+        return Ok(track);
+    };
+
+    for (kind, payload) in atoms::children(stbl)? {
+        let mut reader = ByteReader::new(payload);
+        reader.version_and_flags()?;
+        match &kind {
+            b"stts" => {
+                track.stts_entries = reader.u32()?;
+                for _ in 0..track.stts_entries {
+                    let count = reader.u32()? as u64;
+                    track.decode_duration += count * reader.u32()? as u64;
+                }
+            }
+            b"stsc" => track.stsc_entries = reader.u32()?,
+            b"stco" | b"co64" => {
+                track.chunk_count = reader.u32()?;
+                for _ in 0..track.chunk_count {
+                    let offset = if &kind == b"co64" { reader.u64()? } else { reader.u32()? as u64 };
+                    track.chunk_offsets.push(offset);
+                }
+            }
+            b"stsd" => {
+                let entry_count = reader.u32()?;
+                if entry_count > 0 {
+                    let start = reader.position();
+                    let size = reader.u32()? as usize;
+                    track.codec = reader.fourcc()?;
+                    reader.seek(start);
+                    if let Ok(entry) = reader.bytes(size) {
+                        track.config = codec_config::parse_sample_entry(&track.handler, entry);
+                        if track.is_video() {
+                            track.stereo = stereo::sample_entry_layout(entry);
+                        }
+                        if track.is_audio() {
+                            (track.sample_rate, track.channels) = audio_format(entry);
+                        }
+                    }
+                }
+            }
+            b"stsz" => {
+                let sample_size = reader.u32()?;
+                track.sample_count = reader.u32()?;
+                if sample_size == 0 {
+                    track.stsz_entries = track.sample_count;
+                    track.constant_sample_size = constant_size(&mut reader, track.sample_count)?;
+                } else {
+                    track.stsz_entries = 0;
+                    track.constant_sample_size = Some(sample_size);
+                }
+            }
+            b"ctts" => track.has_composition_offsets = reader.u32()? > 0,
+            _ => {}
+        }
+    }
+
+    Ok(track)
+}
+
+/// Entries of a track's edit list (`edts/elst`); empty without one.
+pub fn edit_list(trak: &[u8]) -> Result<Vec<Edit>> {
+    let mut edits = Vec::new();
+    let Some(elst) = atoms::find_path(trak, &[b"edts", b"elst"])? else {
+        return Ok(edits);
+    };
+    let mut reader = ByteReader::new(elst);
+    let (version, _) = reader.version_and_flags()?;
+    let count = reader.u32()?;
+    for _ in 0..count {
+        let edit = if version == 1 {
+            Edit { segment_duration: reader.u64()?, media_time: reader.u64()? as i64 }
+        } else {
+            Edit { segment_duration: reader.u32()? as u64, media_time: reader.u32()? as i32 as i64 }
+        };
+        reader.skip(4)?; // media rate
+        edits.push(edit);
+    }
+    Ok(edits)
+}
+
+/// Sample rate and channel count of a QuickTime sound description. The
+/// rate is 16.16 fixed point in versions 0 and 1, a 64-bit float in
+/// version 2.
+fn audio_format(entry: &[u8]) -> (u32, u32) {
+    let mut reader = ByteReader::new(entry);
+    let Ok(version) = reader.skip(16).and_then(|_| reader.u16()) else {
+        return (0, 0);
+    };
+    let format = if version == 2 {
+        reader.skip(22).and_then(|_| {
+            let rate = f64::from_bits(reader.u64()?).round() as u32;
+            Ok((rate, reader.u32()?))
+        })
+    } else {
+        reader.skip(6).and_then(|_| {
+            let channels = reader.u16()? as u32;
+            reader.skip(6)?;
+            Ok((reader.u32()? >> 16, channels))
+        })
+    };
+    format.unwrap_or((0, 0))
+}
+
+/// Clockwise rotation in degrees, rounded to a multiple of 90, from the
+/// first row (a, b) of a track matrix in 16.16 fixed point.
+fn matrix_rotation(a: i32, b: i32) -> u32 {
+    let degrees = (b as f64).atan2(a as f64).to_degrees();
+    let quarter = (degrees / 90.0).round() as i32;
+    (quarter.rem_euclid(4) * 90) as u32
+}
+
+/// Returns the common size of a per-sample size table, if there is one.
+fn constant_size(reader: &mut ByteReader, count: u32) -> Result<Option<u32>> {
+    let mut common = None;
+    for _ in 0..count {
+        let size = reader.u32()?;
+        match common {
+            None => common = Some(size),
+            Some(c) if c != size => return Ok(None),
+            Some(_) => {}
+        }
+    }
+    Ok(common)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::atoms::tests::atom;
+
+    pub fn full_atom(kind: &FourCC, version: u8, fields: &[u32]) -> Vec<u8> {
+        let mut payload = vec![version, 0, 0, 0];
+        for field in fields {
+            payload.extend_from_slice(&field.to_be_bytes());
+        }
+        atom(kind, &payload)
+    }
+
+    /// Description of a synthetic track for tests.
+    pub struct TestTrack {
+        pub id: u32,
+        pub handler: FourCC,
+        pub codec: FourCC,
+        pub samples: u32,
+        /// Size stored in the stsz header; zero writes a per-sample table.
+        pub uniform_size: u32,
+        /// Per-sample sizes when `uniform_size` is zero (defaults to 100 each).
+        pub sample_sizes: Vec<u32>,
+        pub composition_offsets: bool,
+        pub width: u32,
+        pub height: u32,
+        pub rotation: u32,
+        /// Media timescale of an mdhd atom; zero leaves the mdhd out.
+        pub timescale: u32,
+        pub media_duration: u32,
+        /// Child atoms of the sample description, e.g. an avcC; when set,
+        /// the description gets the full fixed fields of its handler.
+        pub sample_entry_children: Vec<u8>,
+        /// Sample rate written into a stereo audio description (version 0).
+        pub sample_rate: u32,
+        /// Edit list entries (segment duration, media time); empty leaves
+        /// the edts out.
+        pub edits: Vec<(u32, i32)>,
+    }
+
+    impl TestTrack {
+        pub fn new(id: u32, handler: &FourCC) -> Self {
+            let codec = match handler {
+                b"vide" => *b"avc1",
+                b"soun" => *b"mp4a",
+                _ => *handler,
+            };
+            TestTrack {
+                id,
+                handler: *handler,
+                codec,
+                samples: 1,
+                uniform_size: 0,
+                sample_sizes: Vec::new(),
+                composition_offsets: false,
+                width: 0,
+                height: 0,
+                rotation: 0,
+                timescale: 0,
+                media_duration: 0,
+                sample_entry_children: Vec::new(),
+                sample_rate: 0,
+                edits: Vec::new(),
+            }
+        }
+
+        pub fn build(&self) -> Vec<u8> {
+            let samples = self.samples;
+            let mut hdlr = vec![0u8; 8];
+            hdlr.extend_from_slice(&self.handler);
+            hdlr.extend_from_slice(&[0; 12]);
+
+            let mut entry = vec![0u8; 8];
+            if !self.sample_entry_children.is_empty() || self.sample_rate > 0 {
+                entry.resize(if matches!(&self.handler, b"vide" | b"pict") { 78 } else { 28 }, 0);
+                if &self.handler == b"soun" {
+                    entry[16..18].copy_from_slice(&2u16.to_be_bytes());
+                    entry[24..28].copy_from_slice(&(self.sample_rate << 16).to_be_bytes());
+                }
+                entry.extend_from_slice(&self.sample_entry_children);
+            }
+            let mut stsd = vec![0, 0, 0, 0, 0, 0, 0, 1];
+            stsd.extend(atom(&self.codec, &entry));
+
+            let mut stbl = atom(b"stsd", &stsd);
+            stbl.extend(full_atom(b"stts", 0, &[1, samples, 512]));
+            if self.composition_offsets {
+                stbl.extend(full_atom(b"ctts", 0, &[1, samples, 512]));
+            }
+            stbl.extend(full_atom(b"stsc", 0, &[1, 1, samples, 1]));
+            let mut stsz = vec![self.uniform_size, samples];
+            if self.uniform_size == 0 {
+                if self.sample_sizes.is_empty() {
+                    stsz.extend(std::iter::repeat_n(100, samples as usize));
+                } else {
+                    stsz.extend_from_slice(&self.sample_sizes);
+                }
+            }
+            stbl.extend(full_atom(b"stsz", 0, &stsz));
+            stbl.extend(full_atom(b"stco", 0, &[1, 48]));
+
+            let mut mdia = Vec::new();
+            if self.timescale > 0 {
+                mdia.extend(full_atom(b"mdhd", 0, &[0, 0, self.timescale, self.media_duration]));
+            }
+            mdia.extend(atom(b"hdlr", &hdlr));
+            mdia.extend(atom(b"minf", &atom(b"stbl", &stbl)));
+
+            let one = 0x0001_0000u32;
+            let (a, b) = match self.rotation {
+                90 => (0, one),
+                180 => (one.wrapping_neg(), 0),
+                270 => (0, one.wrapping_neg()),
+                _ => (one, 0),
+            };
+            let (c, d) = (b.wrapping_neg(), a);
+            let mut data = full_atom(b"tkhd", 0, &[
+                0, 0, self.id, 0, 0, 0, 0, 0, 0,
+                a, b, 0, c, d, 0, 0, 0, 0x4000_0000,
+                self.width << 16, self.height << 16,
+            ]);
+            if !self.edits.is_empty() {
+                let mut elst = vec![self.edits.len() as u32];
+                for &(segment_duration, media_time) in &self.edits {
+                    elst.extend([segment_duration, media_time as u32, 0x0001_0000]);
+                }
+                data.extend(atom(b"edts", &full_atom(b"elst", 0, &elst)));
+            }
+            data.extend(atom(b"mdia", &mdia));
+            atom(b"trak", &data)
+        }
+    }
+
+    /// Builds a trak atom with the given handler and sample table entry counts.
+    pub fn trak(id: u32, handler: &FourCC, samples: u32, uniform_size: u32) -> Vec<u8> {
+        TestTrack { samples, uniform_size, ..TestTrack::new(id, handler) }.build()
+    }
+
+    /// Builds a complete movie file from ftyp brand and trak atoms.
+    pub fn movie_file(brand: Option<&FourCC>, traks: &[Vec<u8>]) -> Vec<u8> {
+        movie_file_created(brand, traks, 0)
+    }
+
+    /// Like `movie_file`, with an mvhd creation time.
+    pub fn movie_file_created(brand: Option<&FourCC>, traks: &[Vec<u8>], creation_time: u32) -> Vec<u8> {
+        let mut data = Vec::new();
+        if let Some(brand) = brand {
+            let mut ftyp = brand.to_vec();
+            ftyp.extend_from_slice(&[0, 0, 2, 0]);
+            data.extend(atom(b"ftyp", &ftyp));
+        }
+        data.extend(atom(b"mdat", &[0; 64]));
+
+        let mut moov = full_atom(b"mvhd", 0, &[creation_time, 0, 600, 6000]);
+        for trak in traks {
+            moov.extend_from_slice(trak);
+        }
+        data.extend(atom(b"moov", &moov));
+        data
+    }
+
+    pub fn write_temp(name: &str, data: &[u8]) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(name);
+        std::fs::write(&path, data).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_read_movie() {
+        let data = movie_file(Some(b"qt  "), &[
+            trak(1, b"vide", 10, 0),
+            trak(2, b"soun", 20, 4),
+        ]);
+        let path = write_temp("movcat_test_read_movie.mov", &data);
+
+        let movie = read_movie(&path).unwrap();
+        assert_eq!(movie.major_brand, "qt");
+        assert_eq!(movie.timescale, 600);
+        assert_eq!(movie.duration, 6000);
+        assert_eq!(movie.tracks.len(), 2);
+
+        let video = &movie.tracks[0];
+        assert!(video.is_video());
+        assert_eq!(&video.codec, b"avc1");
+        assert_eq!(video.sample_count, 10);
+        assert_eq!(video.stts_entries, 1);
+        assert_eq!(video.stsc_entries, 1);
+        assert_eq!(video.stsz_entries, 10);
+        assert_eq!(video.chunk_count, 1);
+        assert_eq!(video.constant_sample_size, Some(100));
+        assert!(!video.has_composition_offsets);
+
+        let audio = &movie.tracks[1];
+        assert!(audio.is_audio());
+        assert_eq!(audio.sample_count, 20);
+        assert_eq!(audio.stsz_entries, 0);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_read_movie_sample_sizes_and_reordering() {
+        let data = movie_file(Some(b"qt  "), &[
+            TestTrack {
+                codec: *b"jpeg",
+                samples: 3,
+                sample_sizes: vec![100, 120, 90],
+                composition_offsets: true,
+                ..TestTrack::new(1, b"vide")
+            }.build(),
+        ]);
+        let path = write_temp("movcat_test_sample_sizes.mov", &data);
+        let movie = read_movie(&path).unwrap();
+        let track = &movie.tracks[0];
+        assert!(track.is_dv_or_mjpeg());
+        assert_eq!(track.constant_sample_size, None);
+        assert!(track.has_composition_offsets);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_read_movie_rotation() {
+        let data = movie_file(Some(b"qt  "), &[
+            TestTrack { width: 1920, height: 1080, rotation: 90, ..TestTrack::new(1, b"vide") }.build(),
+            TestTrack { width: 1920, height: 1080, rotation: 180, ..TestTrack::new(2, b"vide") }.build(),
+        ]);
+        let path = write_temp("movcat_test_rotation.mov", &data);
+        let movie = read_movie(&path).unwrap();
+        assert_eq!(movie.tracks[0].rotation, 90);
+        assert_eq!(movie.tracks[0].display_size(), (1080, 1920));
+        assert_eq!(movie.tracks[1].rotation, 180);
+        assert_eq!(movie.tracks[1].display_size(), (1920, 1080));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_read_movie_codec_config() {
+        use crate::codec_config::tests::avcc;
+
+        let video = TestTrack {
+            sample_entry_children: atom(b"avcC", &avcc(77, 31, &[0x67, 0x4d])),
+            ..TestTrack::new(1, b"vide")
+        };
+        let data = movie_file(None, &[video.build(), TestTrack::new(2, b"soun").build()]);
+        let path = write_temp("movcat_test_codec_config.mov", &data);
+        let movie = read_movie(&path).unwrap();
+        let config = movie.tracks[0].config.as_ref().unwrap();
+        assert_eq!(&config.kind, b"avcC");
+        assert_eq!(config.fields[0].value, "Main (77)");
+        assert_eq!(&movie.tracks[0].codec, b"avc1");
+        // Descriptions without a configuration record are not decoded
+        assert!(movie.tracks[1].config.is_none());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_read_movie_media_duration() {
+        let data = movie_file(None, &[
+            TestTrack { timescale: 30000, media_duration: 300300, ..TestTrack::new(1, b"vide") }.build(),
+            TestTrack::new(2, b"soun").build(),
+        ]);
+        let path = write_temp("movcat_test_media_duration.mov", &data);
+        let movie = read_movie(&path).unwrap();
+        assert_eq!(movie.tracks[0].timescale, 30000);
+        assert_eq!(movie.tracks[0].media_duration, 300300);
+        assert_eq!(movie.tracks[1].timescale, 0);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_read_movie_edit_list() {
+        let data = movie_file(None, &[
+            // Identity edit, rounded to the movie timescale of 600
+            TestTrack { timescale: 30000, media_duration: 300300, edits: vec![(6006, 0)], ..TestTrack::new(1, b"vide") }.build(),
+            // AAC priming: 1024 samples skipped at 48 kHz
+            TestTrack { timescale: 48000, media_duration: 481024, edits: vec![(6000, 1024)], ..TestTrack::new(2, b"soun") }.build(),
+            TestTrack { timescale: 30000, media_duration: 300300, edits: vec![(300, -1), (6006, 0)], ..TestTrack::new(3, b"vide") }.build(),
+        ]);
+        let path = write_temp("movcat_test_edit_list.mov", &data);
+        let movie = read_movie(&path).unwrap();
+        assert_eq!(movie.tracks[1].edits, vec![Edit { segment_duration: 6000, media_time: 1024 }]);
+        assert_eq!(movie.tracks[2].edits[0].media_time, -1);
+
+        assert_eq!(movie.tracks[0].edited_duration_secs(600), None);
+        assert_eq!(movie.tracks[1].edited_duration_secs(600), Some(10.0));
+        assert_eq!(movie.tracks[2].edited_duration_secs(600), Some(10.51));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_read_movie_audio_sample_count() {
+        let data = movie_file(None, &[
+            TestTrack { samples: 10, timescale: 48000, media_duration: 5120, sample_rate: 48000, ..TestTrack::new(1, b"soun") }.build(),
+            // Media timescale other than the sample rate
+            TestTrack { samples: 10, timescale: 600, media_duration: 5120, sample_rate: 44100, ..TestTrack::new(2, b"soun") }.build(),
+            TestTrack::new(3, b"soun").build(),
+        ]);
+        let path = write_temp("movcat_test_audio_samples.mov", &data);
+        let movie = read_movie(&path).unwrap();
+        assert_eq!(movie.tracks[0].decode_duration, 5120);
+        assert_eq!(movie.tracks[0].sample_rate, 48000);
+        assert_eq!(movie.tracks[0].channels, 2);
+        assert_eq!(movie.tracks[0].audio_sample_count(), Some(5120));
+        assert_eq!(movie.tracks[1].audio_sample_count(), Some(376320));
+        assert_eq!(movie.tracks[2].audio_sample_count(), None);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_read_movie_image_sequence_timelapse() {
+        // HEIF image sequence at 1 fps with 40 MB frames
+        let timelapse = TestTrack {
+            codec: *b"hvc1",
+            samples: 3,
+            sample_sizes: vec![40_000_000, 41_000_000, 39_500_000],
+            timescale: 600,
+            media_duration: 1800,
+            ..TestTrack::new(1, b"pict")
+        };
+        let data = movie_file(Some(b"msf1"), &[timelapse.build()]);
+        let path = write_temp("movcat_test_timelapse.mov", &data);
+        let movie = read_movie(&path).unwrap();
+        let track = &movie.tracks[0];
+        assert!(track.is_video());
+        assert!(track.is_timelapse());
+        assert_eq!(track.frame_rate(), Some(1.0));
+        assert_eq!(track.stsz_entries, 3);
+        assert_eq!(track.constant_sample_size, None);
+        let _ = std::fs::remove_file(&path);
+
+        let normal = TestTrack { timescale: 30000, media_duration: 1001, ..TestTrack::new(1, b"vide") };
+        let path = write_temp("movcat_test_normal_speed.mov", &movie_file(None, &[normal.build()]));
+        assert!(!read_movie(&path).unwrap().tracks[0].is_timelapse());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_read_movie_multiple_mdat() {
+        let mut video = TestTrack::new(1, b"vide").build();
+        // Point the single chunk into the second mdat
+        let stco = video.windows(4).position(|w| w == b"stco").unwrap();
+        video[stco + 12..stco + 16].copy_from_slice(&100u32.to_be_bytes());
+
+        let mut data = atom(b"mdat", &[0; 40]);
+        data.extend(atom(b"wide", &[0; 8]));
+        // 64-bit size header
+        data.extend_from_slice(&1u32.to_be_bytes());
+        data.extend_from_slice(b"mdat");
+        data.extend_from_slice(&(16u64 + 40).to_be_bytes());
+        data.extend_from_slice(&[0; 40]);
+        let mut moov = full_atom(b"mvhd", 0, &[0, 0, 600, 6000]);
+        moov.extend(video);
+        data.extend(atom(b"moov", &moov));
+
+        let path = write_temp("movcat_test_multiple_mdat.mov", &data);
+        let movie = read_movie(&path).unwrap();
+        assert_eq!(movie.mdat_regions, vec![(8, 48), (80, 120)]);
+        assert_eq!(movie.tracks[0].chunk_offsets, vec![100]);
+        assert_eq!(chunks_outside(&movie.tracks[0], &movie.mdat_regions), 0);
+        // Offsets in padding or beyond the media data are not covered
+        assert_eq!(chunks_outside(&movie.tracks[0], &movie.mdat_regions[..1]), 1);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(0), "1904-01-01 00:00:00 UTC");
+        // 2024-02-29 12:34:56 UTC
+        assert_eq!(format_timestamp(1709210096 + 2082844800), "2024-02-29 12:34:56 UTC");
+    }
+
+    #[test]
+    fn test_matrix_rotation() {
+        assert_eq!(matrix_rotation(0x10000, 0), 0);
+        assert_eq!(matrix_rotation(0, 0x10000), 90);
+        assert_eq!(matrix_rotation(-0x10000, 0), 180);
+        assert_eq!(matrix_rotation(0, -0x10000), 270);
+    }
+
+    #[test]
+    fn test_read_movie_without_ftyp() {
+        let data = movie_file(None, &[trak(1, b"vide", 1, 0)]);
+        let path = write_temp("movcat_test_no_ftyp.mov", &data);
+        let movie = read_movie(&path).unwrap();
+        assert_eq!(movie.major_brand, "qt");
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_read_movie_without_moov() {
+        let path = write_temp("movcat_test_no_moov.mov", &atom(b"mdat", &[0; 8]));
+        assert!(read_movie(&path).is_err());
+        let _ = std::fs::remove_file(&path);
+    }
+}
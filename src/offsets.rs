@@ -0,0 +1,223 @@
+//! Timeline offsets for multi-device sessions (`--offsets sync.csv`).
+//!
+//! Clap-sync tools export where every recording starts on a shared
+//! timeline as `file,offset` rows. movcat orders the inputs by offset and
+//! turns the differences into concat directives: a gap before a clip
+//! lengthens the previous clip (its last frame is held), and an overlap is
+//! trimmed from the start of the later clip. Nothing is re-encoded.
+
+use crate::MovInfo;
+use anyhow::{Context, Result};
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Offset {
+    /// Path or file name as written in the CSV.
+    pub file: String,
+    /// Start of the recording on the shared timeline, in seconds.
+    pub seconds: f64,
+}
+
+/// Reads an offsets CSV.
+pub fn load(path: &Path) -> Result<Vec<Offset>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read offsets: {:?}", path))?;
+    parse(&content).with_context(|| format!("Invalid offsets file: {:?}", path))
+}
+
+/// Parses `file,offset` rows. Empty lines, `#` comments and a header row
+/// are skipped; offsets are seconds and may be negative.
+pub fn parse(content: &str) -> Result<Vec<Offset>> {
+    let mut offsets = Vec::new();
+    let mut first_row = true;
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let header_allowed = std::mem::replace(&mut first_row, false);
+        // The file name may itself contain commas, the offset cannot
+        let Some((file, offset)) = line.rsplit_once(',') else {
+            anyhow::bail!("Line {}: expected 'file,offset', got '{}'", number + 1, line);
+        };
+        let file = file.trim().trim_matches('"');
+        match offset.trim().parse::<f64>() {
+            Ok(seconds) if seconds.is_finite() => offsets.push(Offset { file: file.to_string(), seconds }),
+            _ if header_allowed => {}
+            _ => anyhow::bail!("Line {}: '{}' is not an offset in seconds", number + 1, offset.trim()),
+        }
+    }
+    Ok(offsets)
+}
+
+/// Whether a CSV entry refers to an input: by the path as given, or by
+/// its file name.
+fn matches(entry: &str, path: &Path) -> bool {
+    Path::new(entry) == path || path.file_name().is_some_and(|name| name.to_string_lossy() == entry)
+}
+
+/// Looks up every input's offset in the CSV and places the inputs with
+/// [`place`]. Cameras often reuse file names, so a row must match inputs
+/// of one path only and an input only one row.
+pub fn apply(infos: Vec<MovInfo>, offsets: &[Offset]) -> Result<(Vec<MovInfo>, Vec<u64>)> {
+    for offset in offsets {
+        let mut paths: Vec<&Path> = infos.iter().map(|info| info.path.as_path()).filter(|path| matches(&offset.file, path)).collect();
+        paths.sort();
+        paths.dedup();
+        if paths.len() > 1 {
+            anyhow::bail!("Offset for '{}' matches several inputs {:?}; give the path instead of the file name", offset.file, paths);
+        }
+    }
+
+    let mut placed = Vec::new();
+    let mut missing = Vec::new();
+    for info in infos {
+        let rows: Vec<&Offset> = offsets.iter().filter(|offset| matches(&offset.file, &info.path)).collect();
+        match rows[..] {
+            [offset] => placed.push((offset.seconds, info)),
+            [] => missing.push(info.path),
+            _ => anyhow::bail!("{:?} matches several offset rows ({}); give each input once",
+                info.path, rows.iter().map(|offset| format!("'{}'", offset.file)).collect::<Vec<_>>().join(", ")),
+        }
+    }
+    if !missing.is_empty() {
+        anyhow::bail!("No offset given for {} input(s): {:?}", missing.len(), missing);
+    }
+    for offset in offsets.iter().filter(|offset| !placed.iter().any(|(_, info)| matches(&offset.file, &info.path))) {
+        println!("Warning: Offset for '{}' does not match any input", offset.file);
+    }
+    place(placed)
+}
+
+/// Orders inputs with their offsets by timeline position and converts the
+/// offsets into trims and gaps. Returns the reordered inputs and the gap
+/// after every input but the last, in microseconds.
+///
+/// An offset is where the recording starts; a clip that is already
+/// trimmed at the start enters the timeline that much later.
+pub fn place(placed: Vec<(f64, MovInfo)>) -> Result<(Vec<MovInfo>, Vec<u64>)> {
+    let mut placed: Vec<(f64, MovInfo)> = placed.into_iter()
+        .map(|(seconds, info)| (seconds + info.trim_start.unwrap_or(0.0), info))
+        .collect();
+    placed.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut gaps_us = Vec::new();
+    let mut infos: Vec<MovInfo> = Vec::new();
+    let mut timeline_end: Option<f64> = None;
+    for (start, mut info) in placed {
+        if let Some(end) = timeline_end {
+            let overlap = end - start;
+            if overlap > 0.0 {
+                // Skip what the previous clip already covers
+                if overlap >= info.used_duration_secs() {
+                    anyhow::bail!("{:?} lies entirely within the previous clip on the synced timeline", info.path);
+                }
+                info.trim_start = Some(info.trim_start.unwrap_or(0.0) + overlap);
+                gaps_us.push(0);
+            } else {
+                gaps_us.push((-overlap * 1_000_000.0).round() as u64);
+            }
+        }
+        let clip_start = start.max(timeline_end.unwrap_or(f64::MIN));
+        timeline_end = Some(clip_start + info.used_duration_secs());
+        infos.push(info);
+    }
+    Ok((infos, gaps_us))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::movie::tests::{movie_file, trak, write_temp};
+
+    /// A 10 s clip.
+    fn clip(name: &str) -> MovInfo {
+        let path = write_temp(name, &movie_file(None, &[trak(1, b"vide", 1, 0)]));
+        let info = crate::analyze_mov_file(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        info
+    }
+
+    #[test]
+    fn test_parse_offsets() {
+        let offsets = parse("file,offset\n# camera B\ncamB.mov, 12.5\n\"cam, A.mov\",-1.25\n").unwrap();
+        assert_eq!(offsets, vec![
+            Offset { file: "camB.mov".to_string(), seconds: 12.5 },
+            Offset { file: "cam, A.mov".to_string(), seconds: -1.25 },
+        ]);
+        assert!(parse("a.mov,1\nb.mov,soon\n").is_err());
+        assert!(parse("a.mov\n").is_err());
+    }
+
+    #[test]
+    fn test_apply_offsets() {
+        let infos = vec![
+            clip("movcat_test_offsets_b.mov"),
+            clip("movcat_test_offsets_a.mov"),
+            clip("movcat_test_offsets_c.mov"),
+        ];
+        let offsets = parse("movcat_test_offsets_a.mov,0\nmovcat_test_offsets_b.mov,25\nmovcat_test_offsets_c.mov,32.5\n").unwrap();
+        let (infos, gaps) = apply(infos, &offsets).unwrap();
+
+        // a (0-10), 15 s gap, b (25-35), c overlaps b by 2.5 s
+        let names: Vec<_> = infos.iter().map(|info| info.path.file_name().unwrap().to_string_lossy().into_owned()).collect();
+        assert_eq!(names, ["movcat_test_offsets_a.mov", "movcat_test_offsets_b.mov", "movcat_test_offsets_c.mov"]);
+        assert_eq!(gaps, vec![15_000_000, 0]);
+        assert_eq!(infos[2].trim_start, Some(2.5));
+    }
+
+    #[test]
+    fn test_apply_offsets_trimmed() {
+        let mut infos = vec![
+            clip("movcat_test_offsets_t1.mov"),
+            clip("movcat_test_offsets_t2.mov"),
+            clip("movcat_test_offsets_t3.mov"),
+        ];
+        infos[1].trim_start = Some(3.0);
+        infos[2].trim_start = Some(4.0);
+        let offsets = parse("movcat_test_offsets_t1.mov,0\nmovcat_test_offsets_t2.mov,5\nmovcat_test_offsets_t3.mov,20\n").unwrap();
+        let (infos, gaps) = apply(infos, &offsets).unwrap();
+
+        // t1 (0-10); t2 is used from 5+3 = 8 s, so 2 s overlap t1 (10-15);
+        // t3 is used from 20+4 = 24 s, 9 s after t2 ends
+        assert_eq!(infos[1].trim_start, Some(5.0));
+        assert_eq!(infos[2].trim_start, Some(4.0));
+        assert_eq!(gaps, vec![0, 9_000_000]);
+    }
+
+    #[test]
+    fn test_apply_offsets_same_name() {
+        let dir = std::env::temp_dir().join("movcat_test_offsets_cams");
+        let clip_in = |camera: &str| {
+            std::fs::create_dir_all(dir.join(camera)).unwrap();
+            let path = dir.join(camera).join("C0001.MP4");
+            std::fs::write(&path, movie_file(None, &[trak(1, b"vide", 1, 0)])).unwrap();
+            crate::analyze_mov_file(&path).unwrap()
+        };
+        let infos = || vec![clip_in("camA"), clip_in("camB")];
+
+        // A bare name matching both cameras' clips is ambiguous
+        let offsets = parse("C0001.MP4,0\nC0001.MP4,12\n").unwrap();
+        assert!(apply(infos(), &offsets).is_err());
+
+        // So is an input matching both its path and its name
+        let csv = format!("{},0\n{},12\nC0001.MP4,3\n", dir.join("camA/C0001.MP4").display(), dir.join("camB/C0001.MP4").display());
+        assert!(apply(infos(), &parse(&csv).unwrap()).is_err());
+
+        let csv = format!("{},0\n{},12\n", dir.join("camA/C0001.MP4").display(), dir.join("camB/C0001.MP4").display());
+        let (placed, gaps) = apply(infos(), &parse(&csv).unwrap()).unwrap();
+        assert_eq!(placed[1].path, dir.join("camB/C0001.MP4"));
+        assert_eq!(gaps, vec![2_000_000]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_apply_offsets_errors() {
+        let offsets = parse("movcat_test_offsets_x.mov,0\n").unwrap();
+        assert!(apply(vec![clip("movcat_test_offsets_y.mov")], &offsets).is_err());
+
+        // A clip entirely covered by the previous one cannot be placed
+        let offsets = parse("movcat_test_offsets_p.mov,0\nmovcat_test_offsets_q.mov,0\n").unwrap();
+        assert!(apply(vec![clip("movcat_test_offsets_p.mov"), clip("movcat_test_offsets_q.mov")], &offsets).is_err());
+    }
+}
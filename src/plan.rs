@@ -0,0 +1,269 @@
+//! Concatenation plans: the resolved, ordered input set of a run.
+//!
+//! A plan can be saved as JSON and compared against a later one, so that
+//! scheduled jobs can detect when the input set changed unexpectedly.
+
+use crate::MovInfo;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Returned by `movcat plan --diff` when the input set changed; movcat
+/// exits with status 2 for it.
+#[derive(Debug)]
+pub struct PlanChanged;
+
+impl std::fmt::Display for PlanChanged {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "The plan changed")
+    }
+}
+
+impl std::error::Error for PlanChanged {}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Plan {
+    pub output: Option<PathBuf>,
+    pub files: Vec<PlanFile>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlanFile {
+    pub path: PathBuf,
+    pub size: u64,
+    /// Duration in seconds.
+    pub duration: f64,
+    pub major_brand: String,
+    pub video_tracks: usize,
+    pub audio_tracks: usize,
+}
+
+impl Plan {
+    pub fn from_infos(infos: &[MovInfo], output: Option<&Path>) -> Result<Plan> {
+        let mut files = Vec::new();
+        for info in infos {
+            let size = std::fs::metadata(&info.path)
+                .with_context(|| format!("Failed to read metadata of {:?}", info.path))?
+                .len();
+            files.push(PlanFile {
+                path: info.path.clone(),
+                size,
+                duration: info.duration_secs(),
+                major_brand: info.major_brand.clone(),
+                video_tracks: info.video_tracks,
+                audio_tracks: info.audio_tracks,
+            });
+        }
+        Ok(Plan { output: output.map(Path::to_path_buf), files })
+    }
+
+    pub fn load(path: &Path) -> Result<Plan> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read plan: {:?}", path))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("Invalid plan file: {:?}", path))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, content + "\n")
+            .with_context(|| format!("Failed to write plan: {:?}", path))
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct PlanDiff {
+    pub added: Vec<PathBuf>,
+    pub removed: Vec<PathBuf>,
+    /// Files whose position relative to the other retained files changed,
+    /// with their old and new positions (1-based).
+    pub reordered: Vec<(PathBuf, usize, usize)>,
+    /// Files present in both plans whose properties changed.
+    pub changed: Vec<(PathBuf, Vec<String>)>,
+    pub output_changed: bool,
+}
+
+impl PlanDiff {
+    pub fn is_empty(&self) -> bool {
+        *self == PlanDiff::default()
+    }
+}
+
+/// Every file with its occurrence number: a path listed twice, like a
+/// repeated job input, is `(path, 0)` and `(path, 1)`.
+fn occurrences(plan: &Plan) -> Vec<(&PlanFile, usize)> {
+    plan.files.iter().enumerate()
+        .map(|(i, file)| (file, plan.files[..i].iter().filter(|f| f.path == file.path).count()))
+        .collect()
+}
+
+/// Compares two plans for the same job. Repeated paths are matched by
+/// occurrence, the n-th copy in one plan against the n-th in the other.
+pub fn diff(previous: &Plan, current: &Plan) -> PlanDiff {
+    let (old_files, new_files) = (occurrences(previous), occurrences(current));
+    let contains = |files: &[(&PlanFile, usize)], (file, n): &(&PlanFile, usize)| {
+        files.iter().any(|(f, m)| f.path == file.path && m == n)
+    };
+
+    let added = new_files.iter()
+        .filter(|entry| !contains(&old_files, entry))
+        .map(|(f, _)| f.path.clone())
+        .collect();
+    let removed = old_files.iter()
+        .filter(|entry| !contains(&new_files, entry))
+        .map(|(f, _)| f.path.clone())
+        .collect();
+
+    // Only compare order among files present in both plans, so that an
+    // insertion does not make every following file look reordered
+    let retained_old: Vec<&(&PlanFile, usize)> = old_files.iter().filter(|entry| contains(&new_files, entry)).collect();
+    let retained_new: Vec<&(&PlanFile, usize)> = new_files.iter().filter(|entry| contains(&old_files, entry)).collect();
+
+    let mut reordered = Vec::new();
+    let mut changed = Vec::new();
+    for (new_index, &&(file, n)) in retained_new.iter().enumerate() {
+        let old_index = retained_old.iter().position(|(f, m)| f.path == file.path && *m == n).unwrap_or(new_index);
+        if old_index != new_index {
+            reordered.push((file.path.clone(), old_index + 1, new_index + 1));
+        }
+
+        let (old, _) = retained_old[old_index];
+        let changes = describe_changes(old, file);
+        if !changes.is_empty() {
+            changed.push((file.path.clone(), changes));
+        }
+    }
+
+    PlanDiff {
+        added,
+        removed,
+        reordered,
+        changed,
+        output_changed: previous.output != current.output,
+    }
+}
+
+fn describe_changes(old: &PlanFile, new: &PlanFile) -> Vec<String> {
+    let mut changes = Vec::new();
+    if old.size != new.size {
+        changes.push(format!("size {} -> {} bytes", old.size, new.size));
+    }
+    if (old.duration - new.duration).abs() > 0.0005 {
+        changes.push(format!("duration {:.3}s -> {:.3}s", old.duration, new.duration));
+    }
+    if old.major_brand != new.major_brand {
+        changes.push(format!("major brand {} -> {}", old.major_brand, new.major_brand));
+    }
+    if old.video_tracks != new.video_tracks || old.audio_tracks != new.audio_tracks {
+        changes.push(format!("tracks video {}/audio {} -> video {}/audio {}",
+            old.video_tracks, old.audio_tracks, new.video_tracks, new.audio_tracks));
+    }
+    changes
+}
+
+pub fn print_diff(diff: &PlanDiff, previous: &Plan, current: &Plan) {
+    if diff.is_empty() {
+        println!("No changes since previous plan");
+        return;
+    }
+
+    println!("Changes since previous plan:");
+    if diff.output_changed {
+        println!("  Output: {:?} -> {:?}", previous.output, current.output);
+    }
+    for path in &diff.added {
+        println!("  + {:?}", path);
+    }
+    for path in &diff.removed {
+        println!("  - {:?}", path);
+    }
+    for (path, old, new) in &diff.reordered {
+        println!("  ~ {:?} moved from position {} to {}", path, old, new);
+    }
+    for (path, changes) in &diff.changed {
+        println!("  * {:?}: {}", path, changes.join(", "));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str, size: u64) -> PlanFile {
+        PlanFile {
+            path: PathBuf::from(path),
+            size,
+            duration: 10.0,
+            major_brand: "qt".to_string(),
+            video_tracks: 1,
+            audio_tracks: 1,
+        }
+    }
+
+    fn plan(files: Vec<PlanFile>) -> Plan {
+        Plan { output: Some(PathBuf::from("out.mov")), files }
+    }
+
+    #[test]
+    fn test_diff_repeated_inputs() {
+        // A job may use the same clip more than once
+        let a = plan(vec![file("intro.mov", 1), file("a.mov", 2), file("intro.mov", 1)]);
+        assert!(diff(&a, &a).is_empty());
+
+        let b = plan(vec![file("intro.mov", 1), file("a.mov", 2), file("intro.mov", 1), file("intro.mov", 1)]);
+        let d = diff(&a, &b);
+        assert_eq!(d.added, vec![PathBuf::from("intro.mov")]);
+        assert!(d.removed.is_empty() && d.reordered.is_empty());
+
+        let c = plan(vec![file("a.mov", 2), file("intro.mov", 1), file("intro.mov", 1)]);
+        assert_eq!(diff(&a, &c).reordered, vec![(PathBuf::from("a.mov"), 2, 1), (PathBuf::from("intro.mov"), 1, 2)]);
+    }
+
+    #[test]
+    fn test_diff_identical() {
+        let a = plan(vec![file("a.mov", 1), file("b.mov", 2)]);
+        let b = plan(vec![file("a.mov", 1), file("b.mov", 2)]);
+        assert!(diff(&a, &b).is_empty());
+    }
+
+    #[test]
+    fn test_diff_added_and_removed() {
+        let a = plan(vec![file("a.mov", 1), file("b.mov", 2)]);
+        let b = plan(vec![file("c.mov", 3), file("a.mov", 1)]);
+        let d = diff(&a, &b);
+        assert_eq!(d.added, vec![PathBuf::from("c.mov")]);
+        assert_eq!(d.removed, vec![PathBuf::from("b.mov")]);
+        // An insertion in front is not a reorder
+        assert!(d.reordered.is_empty());
+        assert!(d.changed.is_empty());
+    }
+
+    #[test]
+    fn test_diff_reordered_and_changed() {
+        let a = plan(vec![file("a.mov", 1), file("b.mov", 2)]);
+        let mut changed = file("a.mov", 5);
+        changed.duration = 12.0;
+        let b = plan(vec![file("b.mov", 2), changed]);
+        let d = diff(&a, &b);
+        assert_eq!(d.reordered, vec![
+            (PathBuf::from("b.mov"), 2, 1),
+            (PathBuf::from("a.mov"), 1, 2),
+        ]);
+        assert_eq!(d.changed.len(), 1);
+        assert_eq!(d.changed[0].1, vec![
+            "size 1 -> 5 bytes".to_string(),
+            "duration 10.000s -> 12.000s".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn test_plan_save_and_load() {
+        let path = std::env::temp_dir().join("movcat_test_plan.json");
+        let original = plan(vec![file("a.mov", 1)]);
+        original.save(&path).unwrap();
+        let loaded = Plan::load(&path).unwrap();
+        assert_eq!(loaded.output, original.output);
+        assert_eq!(loaded.files, original.files);
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,187 @@
+//! ffprobe-compatible JSON (`movcat info --format ffprobe-json`).
+//!
+//! Emits the structure of `ffprobe -print_format json -show_format
+//! -show_streams` for the fields movcat knows, with ffprobe's names and
+//! value types (numbers that ffprobe prints as strings stay strings), so
+//! scripts written against ffprobe can read it unchanged. Fields movcat
+//! does not know, such as bit rates of single streams, are left out
+//! rather than guessed.
+
+use crate::movie::{self, Track};
+use crate::{atoms, MovInfo};
+use serde_json::{json, Map, Value};
+
+/// ffprobe's name for the QuickTime / MP4 demuxer.
+const FORMAT_NAME: &str = "mov,mp4,m4a,3gp,3g2,mj2";
+
+/// The ffprobe document for a file of `size` bytes.
+pub fn ffprobe_json(info: &MovInfo, size: u64) -> Value {
+    let streams: Vec<Value> = info.tracks.iter().enumerate()
+        .map(|(index, track)| stream(index, track, info.creation_time))
+        .collect();
+
+    let duration = info.duration_secs();
+    let mut tags = Map::new();
+    // ffprobe keeps the brand padded to four characters
+    tags.insert("major_brand".into(), json!(format!("{:<4}", info.major_brand)));
+    if let Some(created) = info.creation_time {
+        tags.insert("creation_time".into(), json!(iso_timestamp(created)));
+    }
+    let mut format = json!({
+        "filename": info.path.to_string_lossy(),
+        "nb_streams": info.tracks.len(),
+        "nb_programs": 0,
+        "format_name": FORMAT_NAME,
+        "format_long_name": "QuickTime / MOV",
+        "start_time": "0.000000",
+        "duration": seconds(duration),
+        "size": size.to_string(),
+        "probe_score": 100,
+        "tags": tags,
+    });
+    if duration > 0.0 {
+        format["bit_rate"] = json!(((size * 8) as f64 / duration).round().to_string());
+    }
+    json!({ "streams": streams, "format": format })
+}
+
+fn stream(index: usize, track: &Track, creation_time: Option<u64>) -> Value {
+    let codec_type = if track.is_video() {
+        "video"
+    } else if track.is_audio() {
+        "audio"
+    } else if matches!(&track.handler, b"sbtl" | b"subt" | b"text") {
+        "subtitle"
+    } else {
+        "data"
+    };
+    let tag = u32::from_le_bytes(track.codec);
+
+    let mut stream = Map::new();
+    stream.insert("index".into(), json!(index));
+    if let Some(name) = movie::codec_name(&track.codec) {
+        stream.insert("codec_name".into(), json!(name));
+    }
+    stream.insert("codec_type".into(), json!(codec_type));
+    stream.insert("codec_tag_string".into(), json!(atoms::fourcc_to_string(&track.codec)));
+    stream.insert("codec_tag".into(), json!(format!("0x{:08x}", tag)));
+
+    if track.is_video() {
+        stream.insert("width".into(), json!(track.width));
+        stream.insert("height".into(), json!(track.height));
+    }
+    if track.is_audio() && track.sample_rate > 0 {
+        stream.insert("sample_rate".into(), json!(track.sample_rate.to_string()));
+        stream.insert("channels".into(), json!(track.channels));
+    }
+
+    stream.insert("id".into(), json!(format!("0x{:x}", track.id)));
+    let frame_rate = if track.is_video() {
+        fraction(track.sample_count as u64 * track.timescale as u64, track.media_duration)
+    } else {
+        "0/0".to_string()
+    };
+    stream.insert("r_frame_rate".into(), json!(frame_rate));
+    stream.insert("avg_frame_rate".into(), json!(frame_rate));
+    if track.timescale > 0 {
+        stream.insert("time_base".into(), json!(format!("1/{}", track.timescale)));
+        stream.insert("start_pts".into(), json!(0));
+        stream.insert("start_time".into(), json!("0.000000"));
+        stream.insert("duration_ts".into(), json!(track.media_duration));
+        stream.insert("duration".into(), json!(seconds(track.media_duration as f64 / track.timescale as f64)));
+    }
+    stream.insert("nb_frames".into(), json!(track.sample_count.to_string()));
+
+    let mut tags = Map::new();
+    if let Some(created) = creation_time {
+        tags.insert("creation_time".into(), json!(iso_timestamp(created)));
+    }
+    stream.insert("tags".into(), Value::Object(tags));
+
+    // ffprobe reports the display matrix counterclockwise
+    if track.is_video() && track.rotation != 0 {
+        let rotation = match track.rotation {
+            90 => -90,
+            270 => 90,
+            degrees => degrees as i32,
+        };
+        stream.insert("side_data_list".into(), json!([{
+            "side_data_type": "Display Matrix",
+            "rotation": rotation,
+        }]));
+    }
+    Value::Object(stream)
+}
+
+/// Seconds with six decimals, as ffprobe prints them.
+fn seconds(value: f64) -> String {
+    format!("{:.6}", value)
+}
+
+/// A reduced `num/den` fraction; `0/0` when undefined, like ffprobe.
+fn fraction(numerator: u64, denominator: u64) -> String {
+    if numerator == 0 || denominator == 0 {
+        return "0/0".to_string();
+    }
+    let (mut a, mut b) = (numerator, denominator);
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    format!("{}/{}", numerator / a, denominator / a)
+}
+
+/// `2024-05-03T09:12:44.000000Z` from a QuickTime timestamp.
+fn iso_timestamp(seconds: u64) -> String {
+    let text = movie::format_timestamp(seconds);
+    let text = text.trim_end_matches(" UTC");
+    format!("{}.000000Z", text.replacen(' ', "T", 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::movie::tests::{movie_file, write_temp, TestTrack};
+
+    #[test]
+    fn test_ffprobe_json() {
+        let video = TestTrack {
+            samples: 300, width: 1920, height: 1080, rotation: 90, timescale: 30000, media_duration: 300300,
+            ..TestTrack::new(1, b"vide")
+        };
+        let audio = TestTrack { timescale: 48000, media_duration: 480512, sample_rate: 48000, ..TestTrack::new(2, b"soun") };
+        let path = write_temp("movcat_test_probe.mov", &movie_file(Some(b"qt  "), &[video.build(), audio.build()]));
+        let info = crate::analyze_mov_file(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        let probe = ffprobe_json(&info, 1_000_000);
+        let video = &probe["streams"][0];
+        assert_eq!(video["codec_name"], "h264");
+        assert_eq!(video["codec_type"], "video");
+        assert_eq!(video["codec_tag_string"], "avc1");
+        assert_eq!(video["codec_tag"], "0x31637661");
+        assert_eq!(video["avg_frame_rate"], "30000/1001");
+        assert_eq!(video["time_base"], "1/30000");
+        assert_eq!(video["duration"], "10.010000");
+        assert_eq!(video["nb_frames"], "300");
+        assert_eq!(video["side_data_list"][0]["rotation"], -90);
+
+        let audio = &probe["streams"][1];
+        assert_eq!(audio["codec_type"], "audio");
+        assert_eq!(audio["sample_rate"], "48000");
+        assert_eq!(audio["channels"], 2);
+        assert_eq!(audio["r_frame_rate"], "0/0");
+
+        assert_eq!(probe["format"]["nb_streams"], 2);
+        assert_eq!(probe["format"]["format_name"], FORMAT_NAME);
+        assert_eq!(probe["format"]["tags"]["major_brand"], "qt  ");
+        assert_eq!(probe["format"]["size"], "1000000");
+    }
+
+    #[test]
+    fn test_iso_timestamp() {
+        // 2024-01-01 00:00:00 UTC
+        assert_eq!(iso_timestamp(3_786_912_000), "2024-01-01T00:00:00.000000Z");
+        assert_eq!(fraction(24000, 1001), "24000/1001");
+        assert_eq!(fraction(60000, 2000), "30/1");
+    }
+}
@@ -0,0 +1,28 @@
+//! The single place where movcat starts external programs.
+//!
+//! With `--no-subprocess`, every attempt to start one fails with a
+//! capability error instead, so the guarantee does not depend on each
+//! feature remembering to check the flag.
+
+use anyhow::Result;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static DISABLED: AtomicBool = AtomicBool::new(false);
+
+/// Forbids starting external programs for the rest of the process.
+pub fn disable() {
+    DISABLED.store(true, Ordering::SeqCst);
+}
+
+pub fn is_disabled() -> bool {
+    DISABLED.load(Ordering::SeqCst)
+}
+
+/// Creates a command for an external program, unless that is forbidden.
+pub fn command(program: &str) -> Result<Command> {
+    if is_disabled() {
+        anyhow::bail!("'{}' cannot be run because --no-subprocess forbids running external programs", program);
+    }
+    Ok(Command::new(program))
+}
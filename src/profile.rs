@@ -0,0 +1,186 @@
+//! Timing spans for `--profile`.
+//!
+//! Parsing each input, the atom reads behind it, backend probes and the
+//! FFmpeg run are recorded as nested spans. The result is written either as
+//! folded stacks (for flamegraph.pl or inferno) or, for a `.json` path, as a
+//! Chrome trace that can be opened in Perfetto or `chrome://tracing`.
+//!
+//! Recording is off unless [`enable`] is called; until then [`span`]
+//! returns a no-op guard. Spans are recorded per thread, and movcat does
+//! all of its work on the main thread.
+
+use anyhow::{Context, Result};
+use std::cell::RefCell;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+thread_local! {
+    static RECORDER: RefCell<Option<Recorder>> = const { RefCell::new(None) };
+}
+
+#[derive(Debug)]
+struct Recorder {
+    start: Instant,
+    /// Indices into `spans` of the spans that are still open.
+    open: Vec<usize>,
+    spans: Vec<Span>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Span {
+    /// Names of the enclosing spans, outermost first, ending with this one.
+    stack: Vec<String>,
+    start: Duration,
+    duration: Duration,
+    /// Time spent in directly nested spans.
+    children: Duration,
+}
+
+/// Starts recording spans.
+pub fn enable() {
+    RECORDER.with_borrow_mut(|recorder| {
+        *recorder = Some(Recorder { start: Instant::now(), open: Vec::new(), spans: Vec::new() });
+    });
+}
+
+/// Closes its span when dropped.
+#[must_use = "the span ends when the guard is dropped"]
+pub struct SpanGuard {
+    index: Option<usize>,
+}
+
+/// Opens a span nested in the innermost open span.
+pub fn span(name: impl Into<String>) -> SpanGuard {
+    RECORDER.with_borrow_mut(|recorder| {
+        let Some(recorder) = recorder.as_mut() else {
+            return SpanGuard { index: None };
+        };
+
+        // ';' separates frames in the folded format
+        let name = name.into().replace(';', ",");
+        let mut stack = recorder.open.last()
+            .map(|&parent| recorder.spans[parent].stack.clone())
+            .unwrap_or_default();
+        stack.push(name);
+
+        let index = recorder.spans.len();
+        recorder.spans.push(Span {
+            stack,
+            start: recorder.start.elapsed(),
+            duration: Duration::ZERO,
+            children: Duration::ZERO,
+        });
+        recorder.open.push(index);
+        SpanGuard { index: Some(index) }
+    })
+}
+
+impl Drop for SpanGuard {
+    fn drop(&mut self) {
+        let Some(index) = self.index else { return };
+        RECORDER.with_borrow_mut(|recorder| {
+            let Some(recorder) = recorder.as_mut() else { return };
+
+            let end = recorder.start.elapsed();
+            let span = &mut recorder.spans[index];
+            span.duration = end.saturating_sub(span.start);
+            let duration = span.duration;
+
+            recorder.open.retain(|&open| open != index);
+            if let Some(&parent) = recorder.open.last() {
+                recorder.spans[parent].children += duration;
+            }
+        });
+    }
+}
+
+/// Writes the recorded spans; the format is chosen by the file extension.
+pub fn write(path: &Path) -> Result<()> {
+    let spans = RECORDER.with_borrow(|recorder| {
+        recorder.as_ref().map(|recorder| recorder.spans.clone()).unwrap_or_default()
+    });
+    let content = if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json")) {
+        chrome_trace(&spans)
+    } else {
+        folded_stacks(&spans)
+    };
+    std::fs::write(path, content)
+        .with_context(|| format!("Failed to write profile: {:?}", path))?;
+    println!("Profile written to {:?}", path);
+    Ok(())
+}
+
+/// One line per span with its self time in microseconds, so that the
+/// flamegraph width of a frame equals its total time.
+fn folded_stacks(spans: &[Span]) -> String {
+    let mut content = String::new();
+    for span in spans {
+        let self_time = span.duration.saturating_sub(span.children).as_micros();
+        if self_time > 0 {
+            content.push_str(&format!("{} {}\n", span.stack.join(";"), self_time));
+        }
+    }
+    content
+}
+
+/// Complete ("X") events of the Chrome trace event format.
+fn chrome_trace(spans: &[Span]) -> String {
+    let events: Vec<serde_json::Value> = spans.iter().map(|span| serde_json::json!({
+        "name": span.stack.last(),
+        "ph": "X",
+        "ts": span.start.as_micros() as u64,
+        "dur": span.duration.as_micros() as u64,
+        "pid": 1,
+        "tid": 1,
+    })).collect();
+    serde_json::json!({ "traceEvents": events, "displayTimeUnit": "ms" }).to_string() + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(stack: &[&str], start: u64, duration: u64, children: u64) -> Span {
+        Span {
+            stack: stack.iter().map(|s| s.to_string()).collect(),
+            start: Duration::from_micros(start),
+            duration: Duration::from_micros(duration),
+            children: Duration::from_micros(children),
+        }
+    }
+
+    #[test]
+    fn test_folded_stacks() {
+        let spans = vec![
+            span(&["movcat"], 0, 1000, 900),
+            span(&["movcat", "analyze a.mov"], 10, 300, 0),
+            span(&["movcat", "ffmpeg"], 400, 600, 0),
+            span(&["movcat", "rename"], 1000, 0, 0),
+        ];
+        assert_eq!(folded_stacks(&spans), "movcat 100\nmovcat;analyze a.mov 300\nmovcat;ffmpeg 600\n");
+    }
+
+    #[test]
+    fn test_chrome_trace() {
+        let trace = chrome_trace(&[span(&["movcat", "ffmpeg"], 400, 600, 0)]);
+        let value: serde_json::Value = serde_json::from_str(&trace).unwrap();
+        let event = &value["traceEvents"][0];
+        assert_eq!(event["name"], "ffmpeg");
+        assert_eq!(event["ph"], "X");
+        assert_eq!(event["ts"], 400);
+        assert_eq!(event["dur"], 600);
+    }
+
+    #[test]
+    fn test_span_nesting() {
+        enable();
+        {
+            let _outer = super::span("outer");
+            let _inner = super::span("inner;with separator");
+        }
+        let spans = RECORDER.with_borrow_mut(|recorder| recorder.take().unwrap().spans);
+        assert_eq!(spans.len(), 2);
+        assert_eq!(spans[1].stack, vec!["outer".to_string(), "inner,with separator".to_string()]);
+        assert_eq!(spans[0].children, spans[1].duration);
+    }
+}
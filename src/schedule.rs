@@ -0,0 +1,203 @@
+//! Deferring the copy phase (`--schedule`, `--when-idle`).
+//!
+//! On a shared edit workstation a multi-hour merge saturates the disks
+//! that everybody else is working from. Analysis and planning are cheap
+//! and run right away, so problems show up immediately; only the copy
+//! waits for the configured time window or for the system to be idle. A
+//! copy that has started is not interrupted when the window closes.
+//!
+//! The local UTC offset is read once, when the run starts, so waiting
+//! needs no external programs. Where it cannot be read (on Windows, or
+//! with `--no-subprocess`) `--schedule` is unavailable; with
+//! `--ignore-unavailable` the window is taken as UTC.
+
+use crate::process;
+use anyhow::{Context, Result};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How often the window and the load are checked while waiting.
+const POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Load average per CPU below which the system counts as idle.
+const IDLE_LOAD_PER_CPU: f64 = 0.25;
+
+/// A daily time window in local time; it may wrap around midnight.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Window {
+    /// Minutes after midnight.
+    pub start: u32,
+    pub end: u32,
+}
+
+impl Window {
+    /// Parses `HH:MM-HH:MM`, e.g. `02:00-06:00` or `22:00-06:00`.
+    pub fn parse(value: &str) -> Result<Window, String> {
+        let time = |text: &str| -> Option<u32> {
+            let (hours, minutes) = text.trim().split_once(':')?;
+            let (hours, minutes) = (hours.parse::<u32>().ok()?, minutes.parse::<u32>().ok()?);
+            (hours <= 24 && minutes < 60 && hours * 60 + minutes <= 24 * 60).then_some(hours * 60 + minutes)
+        };
+        let window = value.split_once('-')
+            .and_then(|(start, end)| Some(Window { start: time(start)?, end: time(end)? }))
+            .ok_or_else(|| format!("'{}' is not a time window (expected HH:MM-HH:MM, e.g. 02:00-06:00)", value))?;
+        if window.start == window.end {
+            return Err(format!("'{}' is an empty time window", value));
+        }
+        Ok(window)
+    }
+
+    pub fn contains(&self, minute: u32) -> bool {
+        if self.start < self.end {
+            (self.start..self.end).contains(&minute)
+        } else {
+            minute >= self.start || minute < self.end
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        format!("{}-{}", format_minute(self.start), format_minute(self.end))
+    }
+}
+
+fn format_minute(minute: u32) -> String {
+    format!("{:02}:{:02}", minute / 60, minute % 60)
+}
+
+/// Minute of the day at a Unix time, for a UTC offset in seconds.
+pub fn minute_of_day(unix_secs: u64, utc_offset_secs: i64) -> u32 {
+    ((unix_secs as i64 + utc_offset_secs).rem_euclid(86400) / 60) as u32
+}
+
+/// Parses the `+hhmm` / `-hhmm` offset printed by `date +%z`.
+fn parse_utc_offset(text: &str) -> Option<i64> {
+    let text = text.trim();
+    let (sign, digits) = match text.split_at_checked(1)? {
+        ("+", digits) => (1, digits),
+        ("-", digits) => (-1, digits),
+        _ => return None,
+    };
+    if digits.len() != 4 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let (hours, minutes) = (digits[..2].parse::<i64>().ok()?, digits[2..].parse::<i64>().ok()?);
+    Some(sign * (hours * 3600 + minutes * 60))
+}
+
+/// The local UTC offset, from `date`; the standard library has no time
+/// zone support.
+pub fn local_utc_offset() -> Result<i64> {
+    let output = process::command("date")?.arg("+%z").output()
+        .context("Failed to run 'date' to read the local time zone")?;
+    parse_utc_offset(&String::from_utf8_lossy(&output.stdout))
+        .context("Could not read the local time zone from 'date +%z'")
+}
+
+/// The one-minute load average.
+pub fn load_average() -> Result<f64> {
+    let content = std::fs::read_to_string("/proc/loadavg")
+        .context("The system load is only available on Linux (/proc/loadavg)")?;
+    content.split_whitespace().next()
+        .and_then(|load| load.parse().ok())
+        .context("Unexpected format of /proc/loadavg")
+}
+
+pub fn is_idle(load: f64, cpus: usize) -> bool {
+    load / (cpus.max(1) as f64) < IDLE_LOAD_PER_CPU
+}
+
+fn cpu_count() -> usize {
+    std::thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// When the copy phase may run.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Schedule {
+    pub window: Option<Window>,
+    /// Offset of the window's local time from UTC, in seconds.
+    pub utc_offset_secs: i64,
+    pub when_idle: bool,
+}
+
+/// Blocks until the copy may start: inside the window, if one is given,
+/// and with the system idle, if requested.
+pub fn wait(schedule: Schedule) -> Result<()> {
+    let Schedule { window, utc_offset_secs, when_idle } = schedule;
+    let mut reported = String::new();
+    loop {
+        let mut waiting_for = Vec::new();
+        if let Some(window) = window {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+            let minute = minute_of_day(now, utc_offset_secs);
+            if !window.contains(minute) {
+                waiting_for.push(format!("the time window {} (now {})", window.describe(), format_minute(minute)));
+            }
+        }
+        if when_idle {
+            let (load, cpus) = (load_average()?, cpu_count());
+            if !is_idle(load, cpus) {
+                waiting_for.push(format!("the system to be idle (load {:.2} on {} CPUs)", load, cpus));
+            }
+        }
+        if waiting_for.is_empty() {
+            if !reported.is_empty() {
+                println!("Starting the copy");
+            }
+            return Ok(());
+        }
+
+        // Report only when the reason changes, not every minute
+        let message = format!("Waiting for {} before copying...", waiting_for.join(" and "));
+        if message != reported {
+            println!("{}", message);
+            reported = message;
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_window() {
+        assert_eq!(Window::parse("02:00-06:00").unwrap(), Window { start: 120, end: 360 });
+        assert_eq!(Window::parse("22:30 - 6:00").unwrap(), Window { start: 1350, end: 360 });
+        assert_eq!(Window::parse("18:00-24:00").unwrap().end, 1440);
+        assert!(Window::parse("02:00").is_err());
+        assert!(Window::parse("25:00-06:00").is_err());
+        assert!(Window::parse("02:60-06:00").is_err());
+        assert!(Window::parse("06:00-06:00").is_err());
+    }
+
+    #[test]
+    fn test_window_contains() {
+        let night = Window::parse("02:00-06:00").unwrap();
+        assert!(night.contains(120) && night.contains(359));
+        assert!(!night.contains(360) && !night.contains(90));
+
+        // Wrapping around midnight
+        let overnight = Window::parse("22:00-06:00").unwrap();
+        assert!(overnight.contains(23 * 60) && overnight.contains(0) && overnight.contains(5 * 60));
+        assert!(!overnight.contains(12 * 60));
+    }
+
+    #[test]
+    fn test_local_time() {
+        assert_eq!(parse_utc_offset("+0900\n"), Some(9 * 3600));
+        assert_eq!(parse_utc_offset("-0330"), Some(-(3 * 3600 + 30 * 60)));
+        assert_eq!(parse_utc_offset("JST"), None);
+
+        // 2024-01-01 00:30 UTC is 09:30 at UTC+9 and 20:30 the day before at UTC-4
+        let now = 1_704_069_000;
+        assert_eq!(minute_of_day(now, 9 * 3600), 9 * 60 + 30);
+        assert_eq!(minute_of_day(now, -4 * 3600), 20 * 60 + 30);
+    }
+
+    #[test]
+    fn test_is_idle() {
+        assert!(is_idle(0.5, 8));
+        assert!(!is_idle(3.0, 8));
+        assert!(!is_idle(0.5, 1));
+    }
+}
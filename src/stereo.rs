@@ -0,0 +1,113 @@
+//! Stereoscopic (3D) video layouts.
+//!
+//! Cameras store stereo video in one of three ways: both eyes packed into
+//! one frame and described by an `st3d` atom, an MV-HEVC multiview stream
+//! described by a `vexu` atom (spatial video), or the two eyes as two
+//! separate video tracks. A merge has to keep the layout of its inputs, and
+//! mixing 2D and 3D clips is almost always a mistake.
+
+use crate::codec_config;
+use crate::MovInfo;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StereoLayout {
+    Mono,
+    /// Both eyes in one frame, left eye on top.
+    TopBottom,
+    /// Both eyes in one frame, side by side.
+    LeftRight,
+    /// MV-HEVC with the second eye as an extra layer.
+    Multiview,
+    /// Each eye in its own video track.
+    TrackPair,
+}
+
+impl StereoLayout {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            StereoLayout::Mono => "2D",
+            StereoLayout::TopBottom => "3D, top/bottom frame packing",
+            StereoLayout::LeftRight => "3D, side-by-side frame packing",
+            StereoLayout::Multiview => "3D, MV-HEVC multiview",
+            StereoLayout::TrackPair => "3D, one video track per eye",
+        }
+    }
+}
+
+/// Reads the stereo layout described in a video sample description entry,
+/// if it has one.
+pub fn sample_entry_layout(entry: &[u8]) -> Option<StereoLayout> {
+    let children = codec_config::sample_entry_children(b"vide", entry)?;
+    for (kind, payload) in children {
+        match &kind {
+            // Full atom: version and flags, then the stereo mode
+            b"st3d" => return match payload.get(4) {
+                Some(1) => Some(StereoLayout::TopBottom),
+                Some(2) => Some(StereoLayout::LeftRight),
+                _ => None,
+            },
+            b"vexu" => return Some(StereoLayout::Multiview),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Layout of a file: from the first video track's sample description, or
+/// a pair of video tracks with the same codec and size.
+pub fn layout(info: &MovInfo) -> StereoLayout {
+    let videos: Vec<_> = info.tracks.iter().filter(|t| t.is_video()).collect();
+    let Some(first) = videos.first() else {
+        return StereoLayout::Mono;
+    };
+    if let Some(layout) = first.stereo {
+        return layout;
+    }
+    let second = videos.get(1);
+    if videos.len() == 2 && second.is_some_and(|t| t.codec == first.codec && t.width == first.width && t.height == first.height) {
+        StereoLayout::TrackPair
+    } else {
+        StereoLayout::Mono
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atoms::tests::atom;
+    use crate::codec_config::tests::video_entry;
+    use crate::movie::tests::{movie_file, write_temp, TestTrack};
+
+    fn analyzed(name: &str, tracks: &[TestTrack]) -> MovInfo {
+        let data = movie_file(None, &tracks.iter().map(TestTrack::build).collect::<Vec<_>>());
+        let path = write_temp(name, &data);
+        let info = crate::analyze_mov_file(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        info
+    }
+
+    #[test]
+    fn test_sample_entry_layout() {
+        let st3d = |mode: u8| atom(b"st3d", &[0, 0, 0, 0, mode]);
+        assert_eq!(sample_entry_layout(&video_entry(b"avc1", &st3d(2))), Some(StereoLayout::LeftRight));
+        assert_eq!(sample_entry_layout(&video_entry(b"avc1", &st3d(1))), Some(StereoLayout::TopBottom));
+        assert_eq!(sample_entry_layout(&video_entry(b"avc1", &st3d(0))), None);
+        assert_eq!(sample_entry_layout(&video_entry(b"hvc1", &atom(b"vexu", &[]))), Some(StereoLayout::Multiview));
+        assert_eq!(sample_entry_layout(&video_entry(b"avc1", &[])), None);
+    }
+
+    #[test]
+    fn test_layout() {
+        let eye = |id| TestTrack { width: 1920, height: 1080, ..TestTrack::new(id, b"vide") };
+        assert_eq!(layout(&analyzed("movcat_test_stereo_pair.mov", &[eye(1), eye(2)])), StereoLayout::TrackPair);
+        assert_eq!(layout(&analyzed("movcat_test_stereo_mono.mov", &[eye(1), TestTrack::new(2, b"soun")])),
+            StereoLayout::Mono);
+
+        let packed = TestTrack { sample_entry_children: atom(b"st3d", &[0, 0, 0, 0, 1]), ..eye(1) };
+        assert_eq!(layout(&analyzed("movcat_test_stereo_packed.mov", &[packed])), StereoLayout::TopBottom);
+
+        // A picture-in-picture track of another size is not a second eye
+        let inset = TestTrack { width: 640, height: 360, ..TestTrack::new(2, b"vide") };
+        assert_eq!(layout(&analyzed("movcat_test_stereo_inset.mov", &[eye(1), inset])), StereoLayout::Mono);
+    }
+}
@@ -0,0 +1,239 @@
+//! Player and NLE profiles (`--target`).
+//!
+//! A merge that FFmpeg writes without complaint can still fail where it is
+//! used: browsers only play a few codecs and stall on files without
+//! faststart, QuickTime and Safari only play HEVC tagged `hvc1`, and
+//! broadcast QC rejects anything but PCM audio at 48 kHz. A target profile
+//! collects these constraints, checks the inputs and the output name
+//! against them before anything is copied, and adjusts the output options
+//! where a stream copy can satisfy the target.
+
+use crate::movie::{self, Track};
+use crate::{atoms, MovInfo, OutputOptions};
+use clap::ValueEnum;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Target {
+    /// QuickTime Player and other Apple players
+    Quicktime,
+    /// HTML5 video in browsers
+    Web,
+    /// DaVinci Resolve
+    Davinci,
+    /// Adobe Premiere Pro
+    Premiere,
+    /// Broadcast delivery (QC-checked QuickTime masters)
+    Broadcast,
+}
+
+/// What a target makes of a QuickTime chapter track.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Chapters {
+    Supported,
+    /// Written, but not shown.
+    Ignored,
+    /// Left out: the extra text track fails QC.
+    Dropped,
+}
+
+struct Profile {
+    /// Output file extensions; the extension also decides the major brand
+    /// FFmpeg writes.
+    extensions: &'static [&'static str],
+    /// FFmpeg codec names; `pcm` stands for every PCM format.
+    video_codecs: &'static [&'static str],
+    audio_codecs: &'static [&'static str],
+    /// HEVC must be tagged `hvc1` (parameter sets in the sample
+    /// description) rather than `hev1`.
+    requires_hvc1: bool,
+    audio_sample_rate: Option<u32>,
+    faststart: bool,
+    chapters: Chapters,
+}
+
+const PCM: &str = "pcm";
+
+impl Target {
+    fn profile(self) -> Profile {
+        match self {
+            Target::Quicktime => Profile {
+                extensions: &["mov", "mp4", "m4v"],
+                video_codecs: &["h264", "hevc", "prores", "mpeg4", "mjpeg", "dvvideo"],
+                audio_codecs: &["aac", "alac", PCM],
+                requires_hvc1: true,
+                audio_sample_rate: None,
+                faststart: false,
+                chapters: Chapters::Supported,
+            },
+            Target::Web => Profile {
+                extensions: &["mp4", "m4v"],
+                video_codecs: &["h264", "hevc", "av1", "vp9"],
+                audio_codecs: &["aac", "opus"],
+                requires_hvc1: true,
+                audio_sample_rate: None,
+                faststart: true,
+                chapters: Chapters::Ignored,
+            },
+            Target::Davinci => Profile {
+                extensions: &["mov", "mp4"],
+                video_codecs: &["h264", "hevc", "prores", "mjpeg", "dvvideo"],
+                audio_codecs: &["aac", PCM],
+                requires_hvc1: false,
+                audio_sample_rate: None,
+                faststart: false,
+                chapters: Chapters::Ignored,
+            },
+            Target::Premiere => Profile {
+                extensions: &["mov", "mp4", "m4v"],
+                video_codecs: &["h264", "hevc", "prores", "mpeg4", "mjpeg", "dvvideo"],
+                audio_codecs: &["aac", "ac3", PCM],
+                requires_hvc1: false,
+                audio_sample_rate: None,
+                faststart: false,
+                chapters: Chapters::Ignored,
+            },
+            Target::Broadcast => Profile {
+                extensions: &["mov"],
+                video_codecs: &["prores", "h264", "dvvideo"],
+                audio_codecs: &[PCM],
+                requires_hvc1: false,
+                audio_sample_rate: Some(48000),
+                faststart: false,
+                chapters: Chapters::Dropped,
+            },
+        }
+    }
+
+    fn name(self) -> String {
+        self.to_possible_value().map_or_else(String::new, |value| value.get_name().to_string())
+    }
+}
+
+fn codec_allowed(track: &Track, allowed: &[&str]) -> bool {
+    movie::codec_name(&track.codec).is_some_and(|name| {
+        allowed.iter().any(|&codec| codec == name || (codec == PCM && name.starts_with("pcm_")))
+    })
+}
+
+/// Whether an `hev1` track can be retagged `hvc1` without re-encoding:
+/// its sample description must carry the parameter sets.
+fn hvc1_retaggable(track: &Track) -> bool {
+    track.config.as_ref()
+        .is_none_or(|config| config.fields.iter().any(|field| field.name.starts_with("SPS[")))
+}
+
+/// Checks the inputs and the output name against a target. Returns the
+/// problems a stream copy cannot fix.
+pub fn check(target: Target, infos: &[MovInfo], output: &Path) -> Vec<String> {
+    let profile = target.profile();
+    let name = target.name();
+    let mut problems = Vec::new();
+
+    let extension = output.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+    if !profile.extensions.contains(&extension.as_str()) {
+        problems.push(format!("{:?}: {} expects a .{} file", output, name, profile.extensions.join(" or .")));
+    }
+
+    for info in infos {
+        for track in &info.tracks {
+            let codec = atoms::fourcc_to_string(&track.codec).trim_end().to_string();
+            let allowed = if track.is_video() {
+                profile.video_codecs
+            } else if track.is_audio() {
+                profile.audio_codecs
+            } else {
+                continue;
+            };
+            if !codec_allowed(track, allowed) {
+                problems.push(format!("{:?}: track {} ({}) is not supported by {}", info.path, track.id, codec, name));
+            }
+            if profile.requires_hvc1 && &track.codec == b"hev1" && !hvc1_retaggable(track) {
+                problems.push(format!("{:?}: track {} is hev1 with in-band parameter sets only; {} needs hvc1, \
+                    which requires re-encoding", info.path, track.id, name));
+            }
+            if let Some(rate) = profile.audio_sample_rate.filter(|_| track.is_audio()) {
+                if track.sample_rate != 0 && track.sample_rate != rate {
+                    problems.push(format!("{:?}: track {} is {} Hz; {} requires {} Hz",
+                        info.path, track.id, track.sample_rate, name, rate));
+                }
+            }
+        }
+    }
+    problems
+}
+
+/// Adjusts the output options to a target, returning a note for every
+/// change.
+pub fn apply(target: Target, infos: &[MovInfo], options: &mut OutputOptions) -> Vec<String> {
+    let profile = target.profile();
+    let name = target.name();
+    let mut notes = Vec::new();
+
+    if profile.faststart && !options.faststart {
+        options.faststart = true;
+        notes.push(format!("Writing the moov atom first (faststart) for {}", name));
+    }
+    let hev1 = infos.iter().flat_map(|info| &info.tracks).any(|track| &track.codec == b"hev1");
+    if profile.requires_hvc1 && hev1 {
+        options.hvc1_tag = true;
+        notes.push(format!("Tagging HEVC video as hvc1 for {}", name));
+    }
+    if !options.chapters.is_empty() {
+        match profile.chapters {
+            Chapters::Supported => {}
+            Chapters::Ignored => notes.push(format!("{} ignores QuickTime chapter tracks; chapters are written anyway", name)),
+            Chapters::Dropped => {
+                options.chapters.clear();
+                notes.push(format!("Leaving out chapters for {}", name));
+            }
+        }
+    }
+    notes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::movie::tests::{movie_file, write_temp, TestTrack};
+
+    fn analyzed(name: &str, tracks: &[TestTrack]) -> MovInfo {
+        let data = movie_file(None, &tracks.iter().map(TestTrack::build).collect::<Vec<_>>());
+        let path = write_temp(name, &data);
+        let info = crate::analyze_mov_file(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        info
+    }
+
+    #[test]
+    fn test_check_target() {
+        let pcm = TestTrack { codec: *b"sowt", sample_rate: 44100, ..TestTrack::new(2, b"soun") };
+        let infos = vec![analyzed("movcat_test_target.mov", &[TestTrack::new(1, b"vide"), pcm])];
+
+        assert!(check(Target::Quicktime, &infos, Path::new("out.mov")).is_empty());
+        assert!(check(Target::Premiere, &infos, Path::new("out.mp4")).is_empty());
+        // PCM audio and a .mov name are not for browsers
+        assert_eq!(check(Target::Web, &infos, Path::new("out.mov")).len(), 2);
+        // Broadcast takes PCM, but only at 48 kHz
+        let problems = check(Target::Broadcast, &infos, Path::new("out.mov"));
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("44100 Hz"));
+    }
+
+    #[test]
+    fn test_apply_target() {
+        let hev1 = TestTrack { codec: *b"hev1", ..TestTrack::new(1, b"vide") };
+        let infos = vec![analyzed("movcat_test_target_hev1.mov", &[hev1])];
+        let chapter = crate::job::Chapter { title: "Intro".to_string(), start: 0.0 };
+
+        let mut options = OutputOptions { chapters: vec![chapter.clone()], ..OutputOptions::default() };
+        assert_eq!(apply(Target::Web, &infos, &mut options).len(), 3);
+        assert!(options.faststart && options.hvc1_tag);
+        assert_eq!(options.chapters.len(), 1);
+
+        let mut options = OutputOptions { chapters: vec![chapter], ..OutputOptions::default() };
+        apply(Target::Broadcast, &infos, &mut options);
+        assert!(!options.faststart && !options.hvc1_tag);
+        assert!(options.chapters.is_empty());
+    }
+}
@@ -0,0 +1,114 @@
+//! Early classification of files that cannot be media.
+//!
+//! Globs over camera cards and synced folders also match files that only
+//! look like clips: empty files left by an aborted copy, macOS metadata
+//! (`.DS_Store`, `._CLIP.MOV` AppleDouble files) and thumbnails that some
+//! cameras store with a movie extension. They are recognized from their
+//! name and first bytes before any analysis, reported together, and with
+//! `--skip-bad` left out of the merge.
+
+use anyhow::Result;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Top-level atoms a QuickTime or MP4 file can start with.
+const LEADING_ATOMS: &[&[u8; 4]] = &[b"ftyp", b"moov", b"mdat", b"free", b"skip", b"wide", b"pnot", b"uuid", b"junk"];
+
+/// Why a file is not a media file, or `None` if it may be one.
+pub fn classify(path: &Path) -> Option<String> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    if name == ".DS_Store" || name == "Thumbs.db" || name == "desktop.ini" {
+        return Some("operating system metadata".to_string());
+    }
+    if name.starts_with("._") {
+        return Some("macOS resource fork (AppleDouble) file".to_string());
+    }
+
+    let mut header = [0u8; 12];
+    let read = match File::open(path).and_then(|mut file| read_up_to(&mut file, &mut header)) {
+        Ok(read) => read,
+        Err(e) => return Some(format!("cannot be read: {}", e)),
+    };
+    match &header[..read] {
+        [] => Some("empty (0 bytes)".to_string()),
+        [0xff, 0xd8, 0xff, ..] => Some("JPEG image (e.g. a camera thumbnail)".to_string()),
+        [0x89, b'P', b'N', b'G', ..] => Some("PNG image".to_string()),
+        [b'R', b'I', b'F', b'F', ..] => Some("RIFF file (AVI or WAV), not QuickTime/MP4".to_string()),
+        [0x1a, 0x45, 0xdf, 0xa3, ..] => Some("Matroska/WebM file, not QuickTime/MP4".to_string()),
+        bytes if bytes.iter().all(|&b| b == 0) => Some("contains only zero bytes".to_string()),
+        [_, _, _, _, kind @ ..] if kind.len() >= 4 && LEADING_ATOMS.iter().any(|atom| kind[..4] == atom[..]) => None,
+        _ => Some("does not start with a QuickTime/MP4 atom".to_string()),
+    }
+}
+
+fn read_up_to(file: &mut File, buffer: &mut [u8]) -> std::io::Result<usize> {
+    let mut read = 0;
+    while read < buffer.len() {
+        match file.read(&mut buffer[read..])? {
+            0 => break,
+            n => read += n,
+        }
+    }
+    Ok(read)
+}
+
+/// Reports the files that are not media. Fails unless `skip_bad` is set,
+/// in which case they are left out.
+pub fn check(files: Vec<PathBuf>, skip_bad: bool) -> Result<Vec<PathBuf>> {
+    let mut media = Vec::new();
+    let mut bad = Vec::new();
+    for file in files {
+        match classify(&file) {
+            Some(reason) => bad.push((file, reason)),
+            None => media.push(file),
+        }
+    }
+    if bad.is_empty() {
+        return Ok(media);
+    }
+
+    println!("Not media files ({}):", bad.len());
+    for (file, reason) in &bad {
+        println!("  {:?}: {}", file, reason);
+    }
+    println!();
+    if !skip_bad {
+        anyhow::bail!("{} input(s) are not media files; remove them or use --skip-bad to leave them out", bad.len());
+    }
+    if media.is_empty() {
+        anyhow::bail!("None of the inputs are media files");
+    }
+    println!("Skipping {} file(s) that are not media", bad.len());
+    Ok(media)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::movie::tests::{movie_file, trak, write_temp};
+
+    #[test]
+    fn test_classify() {
+        let movie = write_temp("movcat_test_triage.mov", &movie_file(None, &[trak(1, b"vide", 1, 0)]));
+        let empty = write_temp("movcat_test_triage_empty.mov", &[]);
+        let thumbnail = write_temp("movcat_test_triage_thm.mov", &[0xff, 0xd8, 0xff, 0xe0, 0, 0x10]);
+        let zeros = write_temp("movcat_test_triage_zeros.mov", &[0; 64]);
+        let apple_double = write_temp("._movcat_test_triage.mov", &movie_file(None, &[]));
+
+        assert_eq!(classify(&movie), None);
+        assert_eq!(classify(&empty).as_deref(), Some("empty (0 bytes)"));
+        assert!(classify(&thumbnail).unwrap().contains("JPEG"));
+        assert!(classify(&zeros).unwrap().contains("zero bytes"));
+        assert!(classify(&apple_double).unwrap().contains("AppleDouble"));
+
+        let inputs = vec![movie.clone(), empty.clone(), thumbnail.clone()];
+        assert!(check(inputs.clone(), false).is_err());
+        assert_eq!(check(inputs, true).unwrap(), vec![movie.clone()]);
+        assert!(check(vec![empty.clone()], true).is_err());
+
+        for path in [movie, empty, thumbnail, zeros, apple_double] {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
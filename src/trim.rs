@@ -0,0 +1,124 @@
+//! Where trims actually land when stream copying.
+//!
+//! FFmpeg's concat demuxer can only start a stream-copied clip at a
+//! keyframe, so an in point snaps back to the keyframe before it and the
+//! frames in between end up in the output. Out points cut between frames.
+//! The preview shows both before anything is copied, so in and out points
+//! can be adjusted first.
+
+use crate::{index, MovInfo};
+use anyhow::Result;
+
+#[derive(Debug, PartialEq)]
+pub struct TrimPreview {
+    /// Requested and actual in point in seconds, and the frames included
+    /// before the requested one.
+    pub start: Option<(f64, f64, u64)>,
+    /// Requested and actual out point in seconds, and the frames included
+    /// after the requested one.
+    pub end: Option<(f64, f64, u64)>,
+}
+
+/// Previews the trims of an input from the keyframes of its first video
+/// track. `None` without trims or without a video track; audio cuts are
+/// accurate to an audio frame.
+pub fn preview_input(info: &MovInfo) -> Result<Option<TrimPreview>> {
+    if info.trim_start.is_none() && info.trim_end.is_none() {
+        return Ok(None);
+    }
+    let Some(track) = info.tracks.iter().find(|t| t.is_video()) else {
+        return Ok(None);
+    };
+    if track.sample_count == 0 || track.timescale == 0 {
+        return Ok(None);
+    }
+
+    let index = index::build(&info.path)?;
+    let keyframes: Vec<f64> = index.keyframes.iter()
+        .map(|&(time, _)| time as f64 / index.timescale as f64)
+        .collect();
+    let frame_duration = track.media_duration as f64 / track.timescale as f64 / track.sample_count as f64;
+    Ok(Some(preview(&keyframes, frame_duration, info.duration_secs(), info.trim_start, info.trim_end)))
+}
+
+/// Computes where trims land given the keyframe times of a clip (sorted, in
+/// seconds) and its frame duration.
+pub fn preview(keyframes: &[f64], frame_duration: f64, duration: f64, trim_start: Option<f64>,
+    trim_end: Option<f64>) -> TrimPreview {
+    // Tolerance for times that are on a frame boundary but not exactly
+    // representable
+    let epsilon = frame_duration / 1000.0;
+    let frames = |seconds: f64| (seconds / frame_duration + 0.5).floor().max(0.0) as u64;
+
+    let start = trim_start.map(|requested| {
+        let actual = keyframes.iter().copied()
+            .take_while(|&keyframe| keyframe <= requested + epsilon)
+            .last()
+            .unwrap_or(0.0);
+        (requested, actual, frames(requested - actual))
+    });
+
+    // Frames that start before the out point are kept whole
+    let end = trim_end.map(|requested| {
+        let actual = ((requested - epsilon) / frame_duration).ceil().max(0.0) * frame_duration;
+        let actual = actual.min(duration);
+        (requested, actual, frames((actual - requested).max(0.0)))
+    });
+
+    TrimPreview { start, end }
+}
+
+impl TrimPreview {
+    pub fn print(&self) {
+        if let Some((requested, actual, frames)) = self.start {
+            if frames == 0 {
+                println!("    start: {:.3}s (keyframe, exact)", requested);
+            } else {
+                println!("    start: requested {:.3}s -> keyframe at {:.3}s ({} extra frame(s) before the in point)",
+                    requested, actual, frames);
+            }
+        }
+        if let Some((requested, actual, frames)) = self.end {
+            if frames == 0 {
+                println!("    end:   {:.3}s (exact)", requested);
+            } else {
+                println!("    end:   requested {:.3}s -> {:.3}s ({} extra frame(s) after the out point)",
+                    requested, actual, frames);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preview_snaps_start_to_previous_keyframe() {
+        // 25 fps with a keyframe every 2 s
+        let keyframes = [0.0, 2.0, 4.0, 6.0];
+        let preview = preview(&keyframes, 0.04, 8.0, Some(4.6), None);
+        assert_eq!(preview.start, Some((4.6, 4.0, 15)));
+        assert_eq!(preview.end, None);
+
+        // In points on a keyframe are exact
+        assert_eq!(super::preview(&keyframes, 0.04, 8.0, Some(2.0), None).start, Some((2.0, 2.0, 0)));
+    }
+
+    #[test]
+    fn test_preview_end_rounds_to_frame() {
+        let keyframes = [0.0];
+        // 30 fps: an out point inside a frame keeps that frame
+        let preview = preview(&keyframes, 1.0 / 30.0, 10.0, None, Some(5.01));
+        let (_, actual, frames) = preview.end.unwrap();
+        assert!((actual - 151.0 / 30.0).abs() < 1e-9);
+        assert_eq!(frames, 1);
+
+        let (_, actual, frames) = super::preview(&keyframes, 1.0 / 30.0, 10.0, None, Some(5.0)).end.unwrap();
+        assert!((actual - 5.0).abs() < 1e-9);
+        assert_eq!(frames, 0);
+
+        // Never beyond the end of the clip
+        assert_eq!(super::preview(&keyframes, 0.04, 3.0, None, Some(3.01)).end.unwrap().1, 3.0);
+    }
+}
@@ -0,0 +1,353 @@
+//! Uploading the finished output to remote storage.
+//!
+//! Like concatenation, uploads are delegated to well-known external tools:
+//! the AWS CLI for `s3://` destinations (multipart, with per-part SHA-256
+//! checksums verified by S3) and curl for `http://` / `https://` PUT
+//! destinations. After the upload the size and the SHA-256 checksum of
+//! the remote object are compared with the local file. S3 stores the
+//! checksum with the object; an HTTP server has to report it in a
+//! `Repr-Digest`, `Digest` or `x-amz-checksum-sha256` header. Without a
+//! checksum the upload fails unless `--upload-size-only` accepts a size
+//! check. A failed attempt sends the whole file again, as neither tool
+//! resumes a single upload.
+
+use crate::process;
+use anyhow::{Context, Result};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use sha2::{Digest, Sha256};
+use std::io::Read;
+use std::path::Path;
+
+const MAX_ATTEMPTS: u32 = 3;
+
+/// A SHA-256 checksum reported by the destination, base64 encoded.
+#[derive(Debug, PartialEq)]
+enum RemoteChecksum {
+    /// Of the whole object.
+    Object(String),
+    /// S3's checksum of the part checksums of a multipart upload, all
+    /// parts but the last of `part_size` bytes.
+    Parts { checksum: String, count: u64, part_size: u64 },
+}
+
+/// What the destination reports about the uploaded object.
+#[derive(Debug, Default)]
+struct Remote {
+    size: Option<u64>,
+    checksum: Option<RemoteChecksum>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Destination {
+    S3 { bucket: String, key: String },
+    Http(String),
+}
+
+impl Destination {
+    /// Parses a destination URL. A trailing '/' means "upload into this
+    /// prefix" and appends the output file name.
+    pub fn parse(url: &str, file_name: &str) -> Result<Destination> {
+        let url = if url.ends_with('/') {
+            format!("{}{}", url, file_name)
+        } else {
+            url.to_string()
+        };
+
+        if let Some(rest) = url.strip_prefix("s3://") {
+            let (bucket, key) = rest.split_once('/')
+                .filter(|(bucket, key)| !bucket.is_empty() && !key.is_empty())
+                .with_context(|| format!("Invalid S3 destination (expected s3://bucket/key): {}", url))?;
+            Ok(Destination::S3 { bucket: bucket.to_string(), key: key.to_string() })
+        } else if url.starts_with("http://") || url.starts_with("https://") {
+            Ok(Destination::Http(url))
+        } else {
+            anyhow::bail!("Unsupported upload destination (use s3:// or http(s)://): {}", url);
+        }
+    }
+
+    /// Name of the external program used for this destination.
+    pub fn tool(&self) -> &'static str {
+        match self {
+            Destination::S3 { .. } => "aws",
+            Destination::Http(_) => "curl",
+        }
+    }
+}
+
+/// Checks that the external program needed for the destination is installed.
+pub fn check_tool(destination: &Destination) -> Result<()> {
+    let tool = destination.tool();
+    match process::command(tool)?.arg("--version").output() {
+        Ok(output) if output.status.success() => Ok(()),
+        _ => anyhow::bail!("'{}' is required to upload to this destination but was not found", tool),
+    }
+}
+
+/// Uploads a file, retrying failed attempts, and compares the size and
+/// checksum of the remote copy. With `size_only`, a destination that
+/// reports no checksum is accepted after a size check.
+pub fn upload(path: &Path, destination: &Destination, size_only: bool) -> Result<()> {
+    let local_size = std::fs::metadata(path)
+        .with_context(|| format!("Failed to read metadata of {:?}", path))?
+        .len();
+
+    let mut attempt = 1;
+    let response_headers = loop {
+        match upload_once(path, destination) {
+            Ok(headers) => break headers,
+            Err(e) if attempt < MAX_ATTEMPTS => {
+                eprintln!("Warning: Upload attempt {} of {} failed: {:#}", attempt, MAX_ATTEMPTS, e);
+                attempt += 1;
+            }
+            Err(e) => return Err(e.context(format!("Upload failed after {} attempts", MAX_ATTEMPTS))),
+        }
+    };
+
+    let remote = remote_object(destination, &response_headers)?;
+    if let Some(size) = remote.size.filter(|&size| size != local_size) {
+        anyhow::bail!("Uploaded object size does not match: local {} bytes, remote {} bytes", local_size, size);
+    }
+    let Some(checksum) = &remote.checksum else {
+        if !size_only {
+            anyhow::bail!("The destination reports no SHA-256 checksum, so the uploaded copy could not be verified \
+                (an HTTP server needs to send a Repr-Digest, Digest or x-amz-checksum-sha256 header); \
+                use --upload-size-only to accept a size check");
+        }
+        match remote.size {
+            Some(size) => println!("Upload complete: remote size matches ({} bytes), no checksum reported", size),
+            None => println!("Warning: Remote reported neither a size nor a checksum; the upload could not be checked"),
+        }
+        return Ok(());
+    };
+
+    let local = match checksum {
+        RemoteChecksum::Object(_) => local_checksum(path, None)?,
+        RemoteChecksum::Parts { count, part_size, .. } => {
+            let parts = local_size.div_ceil(*part_size).max(1);
+            if parts != *count {
+                anyhow::bail!("Uploaded object has {} parts of {} bytes, but the local file makes {}", count, part_size, parts);
+            }
+            local_checksum(path, Some(*part_size))?
+        }
+    };
+    let (RemoteChecksum::Object(remote_checksum) | RemoteChecksum::Parts { checksum: remote_checksum, .. }) = checksum;
+    if &local != remote_checksum {
+        anyhow::bail!("Uploaded object checksum does not match: local SHA-256 {}, remote {}", local, remote_checksum);
+    }
+    println!("Upload complete: SHA-256 checksum matches ({})", local);
+    Ok(())
+}
+
+/// SHA-256 of a file, base64 encoded. With a part size, the checksum of
+/// the part checksums, as S3 computes it for a multipart upload.
+fn local_checksum(path: &Path, part_size: Option<u64>) -> Result<String> {
+    let file = std::fs::File::open(path)
+        .with_context(|| format!("Failed to open file: {:?}", path))?;
+    let mut reader = std::io::BufReader::with_capacity(1 << 20, file);
+    let Some(part_size) = part_size else {
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut reader, &mut hasher).with_context(|| format!("Failed to read {:?}", path))?;
+        return Ok(BASE64_STANDARD.encode(hasher.finalize()));
+    };
+
+    let mut part_checksums = Sha256::new();
+    loop {
+        let mut hasher = Sha256::new();
+        let read = std::io::copy(&mut reader.by_ref().take(part_size), &mut hasher)
+            .with_context(|| format!("Failed to read {:?}", path))?;
+        if read == 0 {
+            break;
+        }
+        part_checksums.update(hasher.finalize());
+    }
+    Ok(BASE64_STANDARD.encode(part_checksums.finalize()))
+}
+
+/// Uploads once; returns curl's response headers for HTTP destinations.
+fn upload_once(path: &Path, destination: &Destination) -> Result<String> {
+    let mut cmd = match destination {
+        Destination::S3 { bucket, key } => {
+            let mut cmd = process::command("aws")?;
+            cmd.arg("s3").arg("cp")
+                .arg("--only-show-errors")
+                .arg("--checksum-algorithm").arg("SHA256")
+                .arg(path)
+                .arg(format!("s3://{}/{}", bucket, key));
+            cmd
+        }
+        Destination::Http(url) => {
+            let mut cmd = process::command("curl")?;
+            cmd.arg("--fail")
+                .arg("--silent")
+                .arg("--show-error")
+                .arg("--dump-header").arg("-")
+                .arg("--upload-file").arg(path)
+                .arg(url);
+            cmd
+        }
+    };
+
+    println!("Running: {:?}", cmd);
+    let output = cmd.output()
+        .with_context(|| format!("Failed to execute {}", destination.tool()))?;
+
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        anyhow::bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+}
+
+/// Size and checksum of the uploaded object. For HTTP, the checksum is
+/// taken from the PUT response or else from a HEAD request asking for it.
+fn remote_object(destination: &Destination, response_headers: &str) -> Result<Remote> {
+    let head = head_object(destination, None)?;
+    match destination {
+        Destination::S3 { .. } => {
+            let mut fields = head.split_whitespace();
+            let size = fields.next().and_then(|size| size.parse().ok());
+            let checksum = match fields.next().filter(|&checksum| checksum != "None") {
+                None => None,
+                Some(value) => match value.rsplit_once('-') {
+                    Some((checksum, count)) => {
+                        let count = count.parse().with_context(|| format!("Unexpected S3 checksum: {}", value))?;
+                        // The CLI uploads all parts but the last in the same size
+                        let part = head_object(destination, Some(1))?;
+                        let part_size = part.split_whitespace().next()
+                            .and_then(|size| size.parse().ok())
+                            .filter(|&size| size > 0)
+                            .context("S3 did not report the size of the first part")?;
+                        Some(RemoteChecksum::Parts { checksum: checksum.to_string(), count, part_size })
+                    }
+                    None => Some(RemoteChecksum::Object(value.to_string())),
+                },
+            };
+            Ok(Remote { size, checksum })
+        }
+        Destination::Http(_) => Ok(Remote {
+            size: parse_content_length(&head),
+            checksum: parse_digest(response_headers).or_else(|| parse_digest(&head)).map(RemoteChecksum::Object),
+        }),
+    }
+}
+
+/// Runs `head-object` (for one part of a multipart object) or a HEAD
+/// request and returns its output.
+fn head_object(destination: &Destination, part: Option<u32>) -> Result<String> {
+    let output = match destination {
+        Destination::S3 { bucket, key } => {
+            let mut cmd = process::command("aws")?;
+            cmd.arg("s3api").arg("head-object")
+                .arg("--bucket").arg(bucket)
+                .arg("--key").arg(key)
+                .arg("--checksum-mode").arg("ENABLED");
+            if let Some(part) = part {
+                cmd.arg("--part-number").arg(part.to_string());
+            }
+            cmd.arg("--query").arg("[ContentLength, ChecksumSHA256]")
+                .arg("--output").arg("text")
+                .output()
+        }
+        Destination::Http(url) => process::command("curl")?
+            .arg("--fail")
+            .arg("--silent")
+            .arg("--location")
+            .arg("--head")
+            .arg("--header").arg("Want-Repr-Digest: sha-256=10")
+            .arg("--header").arg("Want-Digest: SHA-256")
+            .arg(url)
+            .output(),
+    }.with_context(|| format!("Failed to execute {}", destination.tool()))?;
+
+    if !output.status.success() {
+        anyhow::bail!("Failed to query uploaded object: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Values of a response header, last header block first.
+fn header_values<'a>(headers: &'a str, name: &'a str) -> impl Iterator<Item = &'a str> {
+    headers.lines().rev()
+        .filter_map(|line| line.split_once(':'))
+        .filter(move |(header, _)| header.trim().eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.trim())
+}
+
+/// The base64 SHA-256 digest from `Repr-Digest: sha-256=:...:` (RFC 9530),
+/// `Digest: SHA-256=...` (RFC 3230) or S3's `x-amz-checksum-sha256`.
+fn parse_digest(headers: &str) -> Option<String> {
+    let sha256 = |value: &str, separator: char| {
+        value.split(separator)
+            .filter_map(|entry| entry.trim().split_once('='))
+            .find(|(algorithm, _)| algorithm.trim().eq_ignore_ascii_case("sha-256"))
+            .map(|(_, digest)| digest.trim().trim_matches(':').to_string())
+    };
+    header_values(headers, "repr-digest").find_map(|value| sha256(value, ','))
+        .or_else(|| header_values(headers, "digest").find_map(|value| sha256(value, ',')))
+        .or_else(|| header_values(headers, "x-amz-checksum-sha256").next().map(str::to_string))
+        .filter(|digest| !digest.is_empty())
+}
+
+fn parse_content_length(headers: &str) -> Option<u64> {
+    header_values(headers, "content-length").find_map(|value| value.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_s3_destination() {
+        let dest = Destination::parse("s3://archive/2024/day1.mov", "out.mov").unwrap();
+        assert_eq!(dest, Destination::S3 { bucket: "archive".to_string(), key: "2024/day1.mov".to_string() });
+
+        let dest = Destination::parse("s3://archive/2024/", "out.mov").unwrap();
+        assert_eq!(dest, Destination::S3 { bucket: "archive".to_string(), key: "2024/out.mov".to_string() });
+
+        assert!(Destination::parse("s3://archive", "out.mov").is_err());
+    }
+
+    #[test]
+    fn test_parse_http_destination() {
+        let dest = Destination::parse("https://example.com/upload/", "out.mov").unwrap();
+        assert_eq!(dest, Destination::Http("https://example.com/upload/out.mov".to_string()));
+        assert_eq!(dest.tool(), "curl");
+    }
+
+    #[test]
+    fn test_parse_unsupported_destination() {
+        assert!(Destination::parse("ftp://example.com/out.mov", "out.mov").is_err());
+        assert!(Destination::parse("/mnt/archive/out.mov", "out.mov").is_err());
+    }
+
+    #[test]
+    fn test_parse_content_length() {
+        // Redirects produce several header blocks; the last one wins
+        let headers = "HTTP/1.1 301 Moved\r\nContent-Length: 0\r\n\r\nHTTP/1.1 200 OK\r\ncontent-length: 1234\r\n\r\n";
+        assert_eq!(parse_content_length(headers), Some(1234));
+        assert_eq!(parse_content_length("HTTP/1.1 200 OK\r\n"), None);
+    }
+
+    #[test]
+    fn test_parse_digest() {
+        let digest = "ungWv48Bz+pBQUDeXa4iI7ADYaOWF3qctBD/YfIAFa0=";
+        assert_eq!(parse_digest(&format!("HTTP/1.1 201 Created\r\nRepr-Digest: sha-512=:abc=:, sha-256=:{}:\r\n", digest)).as_deref(), Some(digest));
+        assert_eq!(parse_digest(&format!("HTTP/1.1 200 OK\r\nDigest: MD5=abc==,SHA-256={}\r\n", digest)).as_deref(), Some(digest));
+        assert_eq!(parse_digest(&format!("HTTP/1.1 200 OK\r\nx-amz-checksum-sha256: {}\r\n", digest)).as_deref(), Some(digest));
+        assert_eq!(parse_digest("HTTP/1.1 200 OK\r\nETag: \"900150983cd24fb0d6963f7d28e17f72\"\r\nDigest: MD5=abc==\r\n"), None);
+    }
+
+    #[test]
+    fn test_local_checksum() {
+        let path = std::env::temp_dir().join("movcat_test_upload_checksum");
+        std::fs::write(&path, b"abc").unwrap();
+        assert_eq!(local_checksum(&path, None).unwrap(), "ungWv48Bz+pBQUDeXa4iI7ADYaOWF3qctBD/YfIAFa0=");
+
+        // Parts "ab" and "c": the checksum of the concatenated part checksums
+        let mut parts = Sha256::digest(b"ab").to_vec();
+        parts.extend(Sha256::digest(b"c"));
+        assert_eq!(local_checksum(&path, Some(2)).unwrap(), BASE64_STANDARD.encode(Sha256::digest(&parts)));
+        let _ = std::fs::remove_file(&path);
+    }
+}
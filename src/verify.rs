@@ -0,0 +1,182 @@
+//! Decoding the finished output once (`--verify-decode`).
+//!
+//! A stream copy never looks inside the packets, so a damaged frame in a
+//! source clip, or a join FFmpeg got wrong, only shows up when the output
+//! is played. This pass has FFmpeg decode every video and audio frame into
+//! the null muxer and maps each decode error back to the source clip and
+//! the position in it that the frame came from.
+//!
+//! FFmpeg's error messages carry no timestamps. With `-debug_ts`, every
+//! packet read is logged as well, to the same stream and in order, so an
+//! error is placed at the last packet read before it.
+
+use crate::{process, profile, MovInfo};
+use anyhow::{Context, Result};
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::process::Stdio;
+
+/// Errors listed individually; the rest are only counted.
+const MAX_LISTED: usize = 20;
+
+/// Log levels of messages that are decode errors.
+const ERROR_TAGS: &[&str] = &["[error] ", "[fatal] ", "[panic] "];
+
+#[derive(Debug, PartialEq)]
+pub struct DecodeError {
+    /// Output time in seconds of the last packet read before the error.
+    pub time: Option<f64>,
+    pub message: String,
+}
+
+/// Where each input starts in the output, in seconds.
+pub fn clip_starts(infos: &[MovInfo], timing: Option<&crate::joins::JoinTiming>) -> Vec<f64> {
+    let mut starts = Vec::with_capacity(infos.len());
+    let mut position = 0.0;
+    for (i, info) in infos.iter().enumerate() {
+        starts.push(position);
+        position += match timing.and_then(|timing| timing.durations_us.get(i)) {
+            Some(&duration) => duration as f64 / 1_000_000.0,
+            None => info.used_duration_secs(),
+        };
+    }
+    starts
+}
+
+/// The input an output time falls into and the time in that input's file.
+pub fn locate(time: f64, starts: &[f64], infos: &[MovInfo]) -> (usize, f64) {
+    let index = starts.partition_point(|&start| start <= time).saturating_sub(1);
+    let offset = time - starts.get(index).copied().unwrap_or(0.0);
+    (index, offset + infos.get(index).and_then(|info| info.trim_start).unwrap_or(0.0))
+}
+
+/// Timestamp of a `-debug_ts` packet line, e.g.
+/// `demuxer -> ist_index:0 type:video ... pkt_pts_time:12.345 ...`.
+fn packet_time(line: &str) -> Option<f64> {
+    if !line.contains("demuxer ->") {
+        return None;
+    }
+    line.split_whitespace()
+        .find_map(|token| token.strip_prefix("pkt_pts_time:"))
+        .and_then(|time| time.parse().ok())
+}
+
+/// The message of an error line of `-loglevel level+...`, keeping the
+/// component in front of the level, e.g. `[h264 @ 0x55d0] error while ...`.
+fn error_message(line: &str) -> Option<String> {
+    ERROR_TAGS.iter().find_map(|tag| {
+        let position = line.find(tag)?;
+        let (component, message) = (line[..position].trim_end(), &line[position + tag.len()..]);
+        Some(if component.is_empty() { message.to_string() } else { format!("{} {}", component, message) })
+    })
+}
+
+/// Collects the decode errors from FFmpeg's log.
+pub fn parse_log(mut log: impl BufRead) -> Result<Vec<DecodeError>> {
+    let mut errors = Vec::new();
+    let mut time = None;
+    let mut line = Vec::new();
+    while log.read_until(b'\n', &mut line)? > 0 {
+        let text = String::from_utf8_lossy(&line);
+        let text = text.trim_end();
+        if let Some(packet) = packet_time(text) {
+            time = Some(packet);
+        } else if let Some(message) = error_message(text) {
+            errors.push(DecodeError { time, message });
+        }
+        line.clear();
+    }
+    Ok(errors)
+}
+
+/// Decodes the output and returns the errors FFmpeg reported.
+pub fn decode(output_path: &Path) -> Result<Vec<DecodeError>> {
+    let _span = profile::span("verify decode");
+    let mut child = process::command("ffmpeg")?
+        .arg("-hide_banner").arg("-nostdin").arg("-nostats")
+        .arg("-loglevel").arg("level+info")
+        .arg("-debug_ts")
+        .arg("-i").arg(output_path)
+        // Timecode and other data tracks have nothing to decode
+        .arg("-map").arg("0:v?").arg("-map").arg("0:a?")
+        .arg("-f").arg("null").arg("-")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to run FFmpeg to decode the output")?;
+    // The log is read while FFmpeg runs; it has a line for every packet
+    let stderr = child.stderr.take().context("FFmpeg's log is not available")?;
+    let mut errors = parse_log(BufReader::new(stderr))?;
+    let status = child.wait().context("Failed to wait for FFmpeg")?;
+    if !status.success() {
+        let time = errors.last().and_then(|error| error.time);
+        errors.push(DecodeError { time, message: format!("FFmpeg stopped decoding ({})", status) });
+    }
+    Ok(errors)
+}
+
+/// Prints the errors with the clip and clip time they belong to.
+pub fn print(errors: &[DecodeError], starts: &[f64], infos: &[MovInfo]) {
+    if errors.is_empty() {
+        println!("Decode check: the output decodes without errors");
+        return;
+    }
+    println!("Decode errors ({}):", errors.len());
+    for error in errors.iter().take(MAX_LISTED) {
+        match error.time {
+            Some(time) => {
+                let (index, offset) = locate(time, starts, infos);
+                let clip = infos.get(index).map_or_else(|| "?".to_string(), |info| format!("{:?}", info.path));
+                println!("  {:.3}s (input {} {} at {:.3}s): {}", time, index + 1, clip, offset, error.message);
+            }
+            None => println!("  before the first frame: {}", error.message),
+        }
+    }
+    if errors.len() > MAX_LISTED {
+        println!("  ... and {} more", errors.len() - MAX_LISTED);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::movie::tests::{movie_file, write_temp, TestTrack};
+
+    #[test]
+    fn test_parse_log() {
+        let log = "\
+[info] Input #0, mov,mp4,m4a,3gp,3g2,mj2, from 'out.mov':
+[info] demuxer -> ist_index:0 type:video next_dts:0 next_dts_time:0 pkt_pts:0 pkt_pts_time:0 pkt_dts:0
+[in#0/mov @ 0x5581] [info] demuxer -> ist_index:0:0 type:video pkt_pts:6144 pkt_pts_time:12.4 pkt_dts:6144
+[h264 @ 0x55d0] [error] error while decoding MB 12 34, bytestream -5
+[h264 @ 0x55d0] [warning] concealing 120 DC, 120 AC, 120 MV errors
+[info] demuxer -> ist_index:0:1 type:audio pkt_pts:NOPTS pkt_pts_time:NOPTS
+[error] Error while decoding stream #0:1: Invalid data found when processing input
+";
+        let errors = parse_log(log.as_bytes()).unwrap();
+        assert_eq!(errors, vec![
+            DecodeError { time: Some(12.4), message: "[h264 @ 0x55d0] error while decoding MB 12 34, bytestream -5".to_string() },
+            DecodeError { time: Some(12.4), message: "Error while decoding stream #0:1: Invalid data found when processing input".to_string() },
+        ]);
+    }
+
+    #[test]
+    fn test_locate() {
+        let clip = |name: &str, frames: u32| {
+            let video = TestTrack { samples: frames, timescale: 30, media_duration: frames, ..TestTrack::new(1, b"vide") };
+            let path = write_temp(name, &movie_file(None, &[video.build()]));
+            let info = crate::analyze_mov_file(&path).unwrap();
+            let _ = std::fs::remove_file(&path);
+            info
+        };
+        let mut infos = vec![clip("movcat_test_verify_1.mov", 300), clip("movcat_test_verify_2.mov", 600)];
+        infos[1].trim_start = Some(5.0);
+
+        let starts = clip_starts(&infos, None);
+        assert_eq!(starts, vec![0.0, 10.0]);
+        assert_eq!(locate(4.0, &starts, &infos), (0, 4.0));
+        // 2s into the second input, which starts 5s into its file
+        assert_eq!(locate(12.0, &starts, &infos), (1, 7.0));
+    }
+}
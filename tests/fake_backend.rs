@@ -0,0 +1,114 @@
+//! Runs the movcat binary end to end with `--backend fake`.
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+fn atom(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+    let mut data = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+    data.extend_from_slice(kind);
+    data.extend_from_slice(payload);
+    data
+}
+
+fn full_atom(kind: &[u8; 4], fields: &[u32]) -> Vec<u8> {
+    let mut payload = vec![0u8; 4];
+    for field in fields {
+        payload.extend_from_slice(&field.to_be_bytes());
+    }
+    atom(kind, &payload)
+}
+
+/// A 10 s movie with one video track of two samples, created at
+/// `creation_time` (seconds since 1904).
+fn clip(creation_time: u32) -> Vec<u8> {
+    let mut hdlr = vec![0u8; 8];
+    hdlr.extend_from_slice(b"vide");
+    hdlr.extend_from_slice(&[0; 12]);
+
+    let mut stsd = vec![0, 0, 0, 0, 0, 0, 0, 1];
+    stsd.extend(atom(b"avc1", &[0; 8]));
+    let mut stbl = atom(b"stsd", &stsd);
+    stbl.extend(full_atom(b"stts", &[1, 2, 512]));
+    stbl.extend(full_atom(b"stsc", &[1, 1, 2, 1]));
+    stbl.extend(full_atom(b"stsz", &[0, 2, 100, 100]));
+    stbl.extend(full_atom(b"stco", &[1, 48]));
+
+    let mut mdia = atom(b"hdlr", &hdlr);
+    mdia.extend(atom(b"minf", &atom(b"stbl", &stbl)));
+    let mut trak = full_atom(b"tkhd", &[0, 0, 1, 0, 0]);
+    trak.extend(atom(b"mdia", &mdia));
+
+    let mut moov = full_atom(b"mvhd", &[creation_time, 0, 600, 6000]);
+    moov.extend(atom(b"trak", &trak));
+
+    let mut data = atom(b"ftyp", b"qt  \0\0\x02\0");
+    data.extend(atom(b"mdat", &[0; 64]));
+    data.extend(atom(b"moov", &moov));
+    data
+}
+
+/// A fresh directory for one test.
+fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("movcat_it_{}", name));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn movcat(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_movcat"))
+        .current_dir(dir)
+        .args(["--backend", "fake"])
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn record(path: &Path) -> serde_json::Value {
+    serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+}
+
+#[test]
+fn test_fake_backend_gaps_and_publish() {
+    let dir = test_dir("publish");
+    // 2024-01-01 00:00:00 and 30 s later: a 20 s gap after the first clip
+    std::fs::write(dir.join("a.mov"), clip(3_786_912_000)).unwrap();
+    std::fs::write(dir.join("b.mov"), clip(3_786_912_030)).unwrap();
+    std::fs::create_dir(dir.join("publish")).unwrap();
+
+    let output = movcat(&dir, &["--fill-gaps", "freeze", "--publish-dir", "publish", "-o", "day.mov", "*.mov"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    // The output and its record are published together
+    assert!(!dir.join("day.mov").exists() && !dir.join("day.mov.movcat-fake.json").exists());
+    assert!(dir.join("publish/day.mov").exists());
+    let record = record(&dir.join("publish/day.mov.movcat-fake.json"));
+    assert_eq!(record["inputs"].as_array().unwrap().len(), 2);
+    assert_eq!(record["duration"], 40.0);
+    assert!(record["filelist"].as_str().unwrap().contains("duration 30\n"));
+    assert_eq!(record["resumed"], false);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_fake_backend_interrupted_run() {
+    let dir = test_dir("resume");
+    std::fs::write(dir.join("a.mov"), clip(0)).unwrap();
+    std::fs::write(dir.join(".day.movcat-partial.mov"), clip(0)).unwrap();
+
+    // Without a terminal, leftovers need an explicit decision
+    let output = movcat(&dir, &["-o", "day.mov", "a.mov"]);
+    assert!(!output.status.success());
+
+    // A partial without a checkpoint cannot be resumed
+    let output = movcat(&dir, &["--resume", "-o", "day.mov", "a.mov"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("No checkpoint"));
+
+    let output = movcat(&dir, &["--restart", "-o", "day.mov", "a.mov"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(dir.join("day.mov").exists() && !dir.join(".day.movcat-partial.mov").exists());
+    assert!(!dir.join(".day.mov.movcat-checkpoint.json").exists());
+    assert_eq!(record(&dir.join("day.mov.movcat-fake.json"))["duration"], 10.0);
+    let _ = std::fs::remove_dir_all(&dir);
+}